@@ -1,5 +1,6 @@
 use crate::names::*;
 use crate::parser::token::Token;
+use crate::ty::{Variance, Visibility};
 
 #[derive(Debug, PartialEq)]
 pub struct Program {
@@ -16,10 +17,32 @@ pub enum TopLevelItem {
 pub enum Definition {
     ClassDefinition {
         name: ClassFirstname,
-        typarams: Vec<String>,
+        typarams: Vec<AstTyParam>,
         super_name: ClassFullname,
+        /// `implements Foo, Bar`
+        interfaces: Vec<ClassFullname>,
+        /// `abstract class Foo`
+        is_abstract: bool,
         defs: Vec<Definition>,
     },
+    ModuleDefinition {
+        name: ClassFirstname,
+        defs: Vec<Definition>,
+    },
+    /// `interface Foo ... end`
+    InterfaceDefinition {
+        name: ClassFirstname,
+        sigs: Vec<AstMethodSignature>,
+    },
+    /// `enum Foo<T> ... end`
+    EnumDefinition {
+        name: ClassFirstname,
+        typarams: Vec<AstTyParam>,
+        cases: Vec<EnumCase>,
+        defs: Vec<Definition>,
+    },
+    /// `include Foo` in a class
+    Include { module_name: ClassFullname },
     InstanceMethodDefinition {
         sig: AstMethodSignature,
         body_exprs: Vec<AstExpression>,
@@ -28,17 +51,37 @@ pub enum Definition {
         sig: AstMethodSignature,
         body_exprs: Vec<AstExpression>,
     },
+    /// `abstract def foo -> Bar` in an abstract class
+    AbstractMethodDefinition { sig: AstMethodSignature },
     ConstDefinition {
         name: ConstFirstname,
         expr: AstExpression,
     },
 }
 
+/// `case Foo(a: Int, b: T)` in an enum
+#[derive(Debug, PartialEq)]
+pub struct EnumCase {
+    pub name: ClassFirstname,
+    pub params: Vec<Param>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct AstMethodSignature {
     pub name: MethodFirstname,
+    pub typarams: Vec<AstTyParam>,
     pub params: Vec<Param>,
     pub ret_typ: Typ,
+    /// `private def`, `protected def` or just `def`
+    pub visibility: Visibility,
+}
+
+/// A type parameter eg. `T`, `out T`, `T: Comparable`
+#[derive(Debug, PartialEq, Clone)]
+pub struct AstTyParam {
+    pub name: String,
+    pub variance: Variance,
+    pub upper_bound: Option<Typ>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -50,6 +93,49 @@ pub struct Param {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Typ {
     pub name: String,
+    /// eg. `[Int, Bool]` for `Fn1<Int, Bool>`
+    pub typ_args: Vec<Typ>,
+    /// True if this is an optional type (eg. `Int?`)
+    pub is_nullable: bool,
+}
+
+/// `rescue e: SomeError` (both of `e` and `SomeError` are optional)
+#[derive(Debug, PartialEq, Clone)]
+pub struct AstRescueClause {
+    pub var_name: Option<String>,
+    pub typ: Option<Typ>,
+    pub exprs: Vec<AstExpression>,
+}
+
+/// `when 1, 2 then ...` of `case`
+#[derive(Debug, PartialEq, Clone)]
+pub struct AstWhenClause {
+    pub exprs: Vec<AstExpression>,
+    pub body_exprs: Vec<AstExpression>,
+}
+
+/// `when Foo(x) then ...` of `match`
+#[derive(Debug, PartialEq, Clone)]
+pub struct AstMatchClause {
+    pub pattern: AstPattern,
+    pub body_exprs: Vec<AstExpression>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AstPattern {
+    /// `_` (matches anything)
+    Wildcard,
+    /// `x` (matches anything and binds it to `x`)
+    Binding(String),
+    /// `x: Foo` (matches an instance of the class and binds it to `x`)
+    TypedBinding { name: String, names: Vec<String> },
+    /// `Foo::Bar(x, y)` or `Bar` (matches an instance of the class)
+    ExtractorPattern {
+        names: Vec<String>,
+        params: Vec<AstPattern>,
+    },
+    /// `1`, `"foo"`, `true`, etc. (compared with `==`)
+    Literal(Box<AstExpression>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -81,6 +167,26 @@ pub enum AstExpressionBody {
         body_exprs: Vec<AstExpression>,
     },
     Break,
+    Return {
+        arg: Option<Box<AstExpression>>,
+    },
+    Begin {
+        body_exprs: Vec<AstExpression>,
+        rescue_clauses: Vec<AstRescueClause>,
+        ensure_exprs: Option<Vec<AstExpression>>,
+    },
+    Raise {
+        expr: Box<AstExpression>,
+    },
+    Case {
+        cond_expr: Box<AstExpression>,
+        when_clauses: Vec<AstWhenClause>,
+        else_exprs: Option<Vec<AstExpression>>,
+    },
+    Match {
+        cond_expr: Box<AstExpression>,
+        clauses: Vec<AstMatchClause>,
+    },
     LVarAssign {
         name: String,
         rhs: Box<AstExpression>,
@@ -103,25 +209,48 @@ pub enum AstExpressionBody {
         arg_exprs: Vec<AstExpression>,
         may_have_paren_wo_args: bool,
     },
+    /// `foo&.bar(args)` (evaluates to nil if `foo` is nil)
+    SafeMethodCall {
+        receiver_expr: Box<AstExpression>,
+        method_name: MethodFirstname,
+        arg_exprs: Vec<AstExpression>,
+    },
     Lambda {
         params: Vec<Param>,
         exprs: Vec<AstExpression>,
     },
+    // `do |x| ... end` or `{ |x| ... }` given to a method call
+    // (Types of the params are inferred from the method signature)
+    Block {
+        param_names: Vec<String>,
+        exprs: Vec<AstExpression>,
+    },
     // Local variable reference or method call with implicit receiver(self)
     BareName(String),
     IVarRef(String),
     ConstRef(Vec<String>),
+    // Generic class with type arguments eg. `Array<Int>`
+    // (Only appears as a method call receiver eg. `Array<Int>.new`)
+    SpecializeExpression {
+        names: Vec<String>,
+        typ_args: Vec<Typ>,
+    },
     PseudoVariable(Token),
     ArrayLiteral(Vec<AstExpression>),
+    HashLiteral(Vec<(AstExpression, AstExpression)>),
     FloatLiteral {
         value: f64,
     },
     DecimalLiteral {
-        value: i32,
+        value: i64,
     },
     StringLiteral {
         content: String,
     },
+    /// `"...#{expr}..."`. Each part is either a string literal or an expression
+    StringInterpolation {
+        parts: Vec<AstExpression>,
+    },
 }
 
 impl Definition {
@@ -133,6 +262,35 @@ impl Definition {
     }
 }
 
+impl EnumCase {
+    /// Return the definitions of the class for this case
+    /// (eg. `def initialize(a: Int, b: T); @a = a; @b = b; end`)
+    pub fn class_defs(&self) -> Vec<Definition> {
+        if self.params.is_empty() {
+            return vec![];
+        }
+        let body_exprs = self
+            .params
+            .iter()
+            .map(|param| assignment(ivar_ref(param.name.clone()), bare_name(&param.name)))
+            .collect();
+        vec![Definition::InstanceMethodDefinition {
+            sig: AstMethodSignature {
+                name: method_firstname("initialize"),
+                typarams: vec![],
+                params: self.params.clone(),
+                ret_typ: Typ {
+                    name: "Void".to_string(),
+                    typ_args: vec![],
+                    is_nullable: false,
+                },
+                visibility: Visibility::Public,
+            },
+            body_exprs,
+        }]
+    }
+}
+
 impl AstExpression {
     pub fn may_have_paren_wo_args(&self) -> bool {
         match self.body {
@@ -153,10 +311,134 @@ impl AstExpression {
         match self.body {
             AstExpressionBody::IVarRef(_) => true,
             AstExpressionBody::ConstRef(_) => true,
-            // TODO: a[b]
+            // `a[b]`
+            AstExpressionBody::MethodCall {
+                ref method_name, ..
+            } => method_name.0 == "[]",
             _ => false,
         }
     }
+
+    /// True if a block (`do ... end`) can be given to this expression
+    pub fn may_take_block(&self) -> bool {
+        match self.body {
+            AstExpressionBody::MethodCall { .. } => true,
+            AstExpressionBody::BareName(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Return the expressions directly contained in this expression
+    pub fn children(&self) -> Vec<&AstExpression> {
+        let mut v: Vec<&AstExpression> = vec![];
+        match &self.body {
+            AstExpressionBody::LogicalNot { expr } => v.push(expr),
+            AstExpressionBody::LogicalAnd { left, right }
+            | AstExpressionBody::LogicalOr { left, right } => {
+                v.push(left);
+                v.push(right);
+            }
+            AstExpressionBody::If {
+                cond_expr,
+                then_exprs,
+                else_exprs,
+            } => {
+                v.push(cond_expr);
+                v.extend(then_exprs);
+                if let Some(exprs) = else_exprs {
+                    v.extend(exprs);
+                }
+            }
+            AstExpressionBody::While {
+                cond_expr,
+                body_exprs,
+            } => {
+                v.push(cond_expr);
+                v.extend(body_exprs);
+            }
+            AstExpressionBody::Return { arg } => {
+                if let Some(expr) = arg {
+                    v.push(expr);
+                }
+            }
+            AstExpressionBody::Begin {
+                body_exprs,
+                rescue_clauses,
+                ensure_exprs,
+            } => {
+                v.extend(body_exprs);
+                for clause in rescue_clauses {
+                    v.extend(&clause.exprs);
+                }
+                if let Some(exprs) = ensure_exprs {
+                    v.extend(exprs);
+                }
+            }
+            AstExpressionBody::Raise { expr } => v.push(expr),
+            AstExpressionBody::Case {
+                cond_expr,
+                when_clauses,
+                else_exprs,
+            } => {
+                v.push(cond_expr);
+                for clause in when_clauses {
+                    v.extend(&clause.exprs);
+                    v.extend(&clause.body_exprs);
+                }
+                if let Some(exprs) = else_exprs {
+                    v.extend(exprs);
+                }
+            }
+            AstExpressionBody::Match { cond_expr, clauses } => {
+                v.push(cond_expr);
+                for clause in clauses {
+                    v.extend(&clause.body_exprs);
+                }
+            }
+            AstExpressionBody::LVarAssign { rhs, .. }
+            | AstExpressionBody::IVarAssign { rhs, .. }
+            | AstExpressionBody::ConstAssign { rhs, .. } => v.push(rhs),
+            AstExpressionBody::MethodCall {
+                receiver_expr,
+                arg_exprs,
+                ..
+            } => {
+                if let Some(expr) = receiver_expr {
+                    v.push(expr);
+                }
+                v.extend(arg_exprs);
+            }
+            AstExpressionBody::SafeMethodCall {
+                receiver_expr,
+                arg_exprs,
+                ..
+            } => {
+                v.push(receiver_expr);
+                v.extend(arg_exprs);
+            }
+            AstExpressionBody::Lambda { exprs, .. } | AstExpressionBody::Block { exprs, .. } => {
+                v.extend(exprs)
+            }
+            AstExpressionBody::ArrayLiteral(exprs)
+            | AstExpressionBody::StringInterpolation { parts: exprs } => v.extend(exprs),
+            AstExpressionBody::HashLiteral(pairs) => {
+                for (key, value) in pairs {
+                    v.push(key);
+                    v.push(value);
+                }
+            }
+            AstExpressionBody::Break
+            | AstExpressionBody::BareName(_)
+            | AstExpressionBody::IVarRef(_)
+            | AstExpressionBody::ConstRef(_)
+            | AstExpressionBody::SpecializeExpression { .. }
+            | AstExpressionBody::PseudoVariable(_)
+            | AstExpressionBody::FloatLiteral { .. }
+            | AstExpressionBody::DecimalLiteral { .. }
+            | AstExpressionBody::StringLiteral { .. } => (),
+        }
+        v
+    }
 }
 
 pub fn logical_not(expr: AstExpression) -> AstExpression {
@@ -202,6 +484,49 @@ pub fn break_expr() -> AstExpression {
     non_primary_expression(AstExpressionBody::Break {})
 }
 
+pub fn return_expr(arg: Option<AstExpression>) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Return {
+        arg: arg.map(Box::new),
+    })
+}
+
+pub fn begin_expr(
+    body_exprs: Vec<AstExpression>,
+    rescue_clauses: Vec<AstRescueClause>,
+    ensure_exprs: Option<Vec<AstExpression>>,
+) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Begin {
+        body_exprs,
+        rescue_clauses,
+        ensure_exprs,
+    })
+}
+
+pub fn case_expr(
+    cond_expr: AstExpression,
+    when_clauses: Vec<AstWhenClause>,
+    else_exprs: Option<Vec<AstExpression>>,
+) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Case {
+        cond_expr: Box::new(cond_expr),
+        when_clauses,
+        else_exprs,
+    })
+}
+
+pub fn match_expr(cond_expr: AstExpression, clauses: Vec<AstMatchClause>) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Match {
+        cond_expr: Box::new(cond_expr),
+        clauses,
+    })
+}
+
+pub fn raise_expr(expr: AstExpression) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Raise {
+        expr: Box::new(expr),
+    })
+}
+
 /// Create an expression for an assigment
 pub fn assignment(lhs: AstExpression, rhs: AstExpression) -> AstExpression {
     let body = match lhs.body {
@@ -219,16 +544,21 @@ pub fn assignment(lhs: AstExpression, rhs: AstExpression) -> AstExpression {
             names,
             rhs: Box::new(rhs),
         },
+        // `a.b = c`, `a[b] = c`
         AstExpressionBody::MethodCall {
             receiver_expr,
             method_name,
+            mut arg_exprs,
             ..
-        } => AstExpressionBody::MethodCall {
-            receiver_expr,
-            method_name: method_name.append("="),
-            arg_exprs: vec![rhs],
-            may_have_paren_wo_args: false,
-        },
+        } => {
+            arg_exprs.push(rhs);
+            AstExpressionBody::MethodCall {
+                receiver_expr,
+                method_name: method_name.append("="),
+                arg_exprs,
+                may_have_paren_wo_args: false,
+            }
+        }
         _ => panic!("[BUG] unexpectd lhs: {:?}", lhs.body),
     };
     non_primary_expression(body)
@@ -280,6 +610,10 @@ pub fn const_ref(names: Vec<String>) -> AstExpression {
     primary_expression(AstExpressionBody::ConstRef(names))
 }
 
+pub fn specialize_expr(names: Vec<String>, typ_args: Vec<Typ>) -> AstExpression {
+    primary_expression(AstExpressionBody::SpecializeExpression { names, typ_args })
+}
+
 pub fn unary_expr(expr: AstExpression, op: &str) -> AstExpression {
     primary_expression(AstExpressionBody::MethodCall {
         receiver_expr: Some(Box::new(expr)),
@@ -302,6 +636,30 @@ pub fn lambda_expr(params: Vec<Param>, exprs: Vec<AstExpression>) -> AstExpressi
     primary_expression(AstExpressionBody::Lambda { params, exprs })
 }
 
+pub fn block_expr(param_names: Vec<String>, exprs: Vec<AstExpression>) -> AstExpression {
+    primary_expression(AstExpressionBody::Block { param_names, exprs })
+}
+
+/// Convert a method call into a safe navigation (`&.`)
+pub fn safe_method_call(call: AstExpression) -> AstExpression {
+    match call.body {
+        AstExpressionBody::MethodCall {
+            receiver_expr: Some(receiver_expr),
+            method_name,
+            arg_exprs,
+            ..
+        } => primary_expression(AstExpressionBody::SafeMethodCall {
+            receiver_expr,
+            method_name,
+            arg_exprs,
+        }),
+        b => panic!(
+            "[BUG] `safe_method_call' takes a MethodCall but got {:?}",
+            b
+        ),
+    }
+}
+
 pub fn pseudo_variable(token: Token) -> AstExpression {
     primary_expression(AstExpressionBody::PseudoVariable(token))
 }
@@ -310,11 +668,15 @@ pub fn array_literal(exprs: Vec<AstExpression>) -> AstExpression {
     primary_expression(AstExpressionBody::ArrayLiteral(exprs))
 }
 
+pub fn hash_literal(pairs: Vec<(AstExpression, AstExpression)>) -> AstExpression {
+    primary_expression(AstExpressionBody::HashLiteral(pairs))
+}
+
 pub fn float_literal(value: f64) -> AstExpression {
     primary_expression(AstExpressionBody::FloatLiteral { value })
 }
 
-pub fn decimal_literal(value: i32) -> AstExpression {
+pub fn decimal_literal(value: i64) -> AstExpression {
     primary_expression(AstExpressionBody::DecimalLiteral { value })
 }
 
@@ -322,6 +684,10 @@ pub fn string_literal(content: String) -> AstExpression {
     primary_expression(AstExpressionBody::StringLiteral { content })
 }
 
+pub fn string_interpolation(parts: Vec<AstExpression>) -> AstExpression {
+    primary_expression(AstExpressionBody::StringInterpolation { parts })
+}
+
 pub fn primary_expression(body: AstExpressionBody) -> AstExpression {
     AstExpression {
         primary: true,
@@ -375,3 +741,30 @@ pub fn set_method_call_args(expr: AstExpression, args: Vec<AstExpression>) -> As
         b => panic!("[BUG] `extend' takes a MethodCall but got {:?}", b),
     }
 }
+
+/// Append a block to the args of `foo.bar(args)`
+/// (expr must be a MethodCall or a BareName)
+pub fn add_block_arg(expr: AstExpression, block: AstExpression) -> AstExpression {
+    let primary = expr.primary;
+    match expr.body {
+        AstExpressionBody::MethodCall {
+            receiver_expr,
+            method_name,
+            mut arg_exprs,
+            ..
+        } => {
+            arg_exprs.push(block);
+            AstExpression {
+                primary,
+                body: AstExpressionBody::MethodCall {
+                    receiver_expr,
+                    method_name,
+                    arg_exprs,
+                    may_have_paren_wo_args: false,
+                },
+            }
+        }
+        AstExpressionBody::BareName(s) => method_call(None, &s, vec![block], primary, false),
+        b => panic!("[BUG] `add_block_arg' takes a MethodCall but got {:?}", b),
+    }
+}
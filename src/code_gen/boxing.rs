@@ -38,14 +38,14 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             .build_xor(b.into_int_value(), x, "invert_sk_bool")
     }
 
-    /// Convert LLVM int into Shiika Int
+    /// Convert LLVM i64 into Shiika Int
     pub fn box_int(&self, int: &inkwell::values::IntValue) -> inkwell::values::BasicValueEnum {
         let sk_int = self.allocate_sk_obj(&class_fullname("Int"), "int");
         self.build_ivar_store(&sk_int, 0, int.as_basic_value_enum(), "int");
         sk_int
     }
 
-    /// Convert Shiika Int into LLVM int
+    /// Convert Shiika Int into LLVM i64
     pub fn unbox_int<'a>(
         &'a self,
         sk_int: inkwell::values::BasicValueEnum<'a>,
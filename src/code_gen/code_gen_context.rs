@@ -9,16 +9,36 @@ pub struct CodeGenContext<'hir: 'run, 'run> {
     /// Current llvm function
     pub function: inkwell::values::FunctionValue<'run>,
     /// If `function` corresponds to a lambda or a method
-    /// (llvm func of methods takes `self` as the first arg but lambdas do not)
+    /// (llvm func of methods takes `self` as the first arg and lambdas
+    /// take `freevars` instead)
     pub function_origin: FunctionOrigin,
     /// Ptr of local variables
     pub lvars: HashMap<String, inkwell::values::PointerValue<'run>>,
+    /// Local variables captured by lambdas (they are allocated on the heap)
+    pub captured_lvars: &'hir [String],
+    /// The llvm type of the captured `self` (only for lambdas)
+    pub lambda_self_type: Option<inkwell::types::BasicTypeEnum<'run>>,
     pub current_loop_end: Option<Rc<inkwell::basic_block::BasicBlock<'run>>>,
+    /// Length of `begin_frames` at the start of the current loop
+    /// (the `begin`s after it are exited by `break`)
+    pub current_loop_begin_depth: usize,
+    /// The `begin`s surrounding the current position (outermost first).
+    /// Exited by `break` and `return`
+    pub begin_frames: Vec<BeginFrame<'hir, 'run>>,
     /// Unique id for lambdas
     /// Used for naming their llvm functions
     pub last_lambda_id: usize,
     /// Lambdas to be compiled
-    pub lambdas: VecDeque<CodeGenLambda<'hir>>,
+    pub lambdas: VecDeque<CodeGenLambda<'hir, 'run>>,
+}
+
+/// A `begin` which is being generated
+#[derive(Debug, Clone, Copy)]
+pub struct BeginFrame<'hir, 'run> {
+    /// The exception handler to restore when leaving the `begin`
+    pub prev_handler: inkwell::values::PointerValue<'run>,
+    /// The ensure clause to run when leaving the `begin`
+    pub ensure_exprs: Option<&'hir HirExpressions>,
 }
 
 #[derive(Debug)]
@@ -29,22 +49,29 @@ pub enum FunctionOrigin {
 }
 
 #[derive(Debug)]
-pub struct CodeGenLambda<'hir> {
+pub struct CodeGenLambda<'hir, 'run> {
     pub func_name: String,
     pub params: &'hir [MethodParam],
     pub exprs: &'hir HirExpressions,
+    pub captured_lvars: &'hir [String],
+    pub self_type: inkwell::types::BasicTypeEnum<'run>,
 }
 
 impl<'hir, 'run> CodeGenContext<'hir, 'run> {
     pub fn new(
         function: inkwell::values::FunctionValue<'run>,
         function_origin: FunctionOrigin,
+        captured_lvars: &'hir [String],
     ) -> CodeGenContext<'hir, 'run> {
         CodeGenContext {
             function,
             function_origin,
             lvars: HashMap::new(),
+            captured_lvars,
+            lambda_self_type: None,
             current_loop_end: None,
+            current_loop_begin_depth: 0,
+            begin_frames: vec![],
             last_lambda_id: 0,
             lambdas: VecDeque::new(),
         }
@@ -53,7 +80,11 @@ impl<'hir, 'run> CodeGenContext<'hir, 'run> {
     /// Return a newly created name for a lambda
     pub fn new_lambda_name(&mut self) -> String {
         self.last_lambda_id += 1;
-        format!("lambda_{}", self.last_lambda_id).to_string()
+        format!(
+            "lambda_{}_in_{}",
+            self.last_lambda_id,
+            self.function.get_name().to_str().unwrap()
+        )
     }
 
     /// Push a lambda into the queue
@@ -62,11 +93,15 @@ impl<'hir, 'run> CodeGenContext<'hir, 'run> {
         func_name: String,
         params: &'hir [MethodParam],
         exprs: &'hir HirExpressions,
+        captured_lvars: &'hir [String],
+        self_type: inkwell::types::BasicTypeEnum<'run>,
     ) {
         let l = CodeGenLambda {
             func_name,
             params,
             exprs,
+            captured_lvars,
+            self_type,
         };
         self.lambdas.push_back(l);
     }
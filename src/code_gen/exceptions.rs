@@ -0,0 +1,170 @@
+/// Exception handling with setjmp/longjmp
+///
+/// `@shiika_exc_handler` holds the jmp_buf of the innermost `begin` and
+/// `@shiika_exception` holds the exception being raised. `raise` stores the
+/// exception and longjmps to the handler. `main` installs the outermost
+/// handler which reports the uncaught exception.
+///
+/// `_setjmp`/`_longjmp` are used instead of `setjmp`/`longjmp` because
+/// Shiika never changes the signal mask, so there is no need to save and
+/// restore it (which costs a syscall on each `begin`.)
+use crate::code_gen::*;
+use crate::ty;
+use inkwell::values::*;
+
+/// Size of jmp_buf in words. The largest one among the major platforms is
+/// 312 bytes (glibc on aarch64, which includes the signal mask)
+const JMP_BUF_WORDS: u32 = 40;
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    /// Declare the functions and globals for exception handling
+    pub(super) fn gen_exception_declares(&self) {
+        let fn_type = self.i32_type.fn_type(&[self.i8ptr_type.into()], false);
+        let setjmp = self.module.add_function("_setjmp", fn_type, None);
+        let kind_id = inkwell::attributes::Attribute::get_named_enum_kind_id("returns_twice");
+        setjmp.add_attribute(
+            inkwell::attributes::AttributeLoc::Function,
+            self.context.create_enum_attribute(kind_id, 0),
+        );
+        let fn_type = self
+            .void_type
+            .fn_type(&[self.i8ptr_type.into(), self.i32_type.into()], false);
+        self.module.add_function("_longjmp", fn_type, None);
+        let fn_type = self
+            .i32_type
+            .fn_type(&[self.i32_type.into(), self.i8ptr_type.into()], true);
+        self.module.add_function("dprintf", fn_type, None);
+
+        for name in &["shiika_exc_handler", "shiika_exception"] {
+            let global = self.module.add_global(self.i8ptr_type, None, name);
+            global.set_initializer(&self.i8ptr_type.const_null());
+        }
+
+        // "%s: %s\n"
+        let bytes = b"%s: %s\n\0"
+            .iter()
+            .map(|b| self.i8_type.const_int(*b as u64, false))
+            .collect::<Vec<_>>();
+        let str_type = self.i8_type.array_type(bytes.len() as u32);
+        let global = self.module.add_global(str_type, None, "uncaught_tmpl");
+        global.set_linkage(inkwell::module::Linkage::Internal);
+        global.set_initializer(&self.i8_type.const_array(&bytes));
+        global.set_constant(true);
+    }
+
+    /// Make a new jmp_buf the innermost handler and call _setjmp.
+    /// Returns the flag which is true when an exception is raised, and the
+    /// ptr to the saved outer handler (to be passed to build_pop_exc_handler)
+    pub(super) fn build_push_exc_handler(
+        &self,
+        function: inkwell::values::FunctionValue<'run>,
+    ) -> (
+        inkwell::values::IntValue<'run>,
+        inkwell::values::PointerValue<'run>,
+    ) {
+        let buf_type = self.i64_type.array_type(JMP_BUF_WORDS);
+        let buf = self.build_entry_alloca(function, buf_type.into(), "jmpbuf");
+        let buf_i8 = self
+            .builder
+            .build_bitcast(buf, self.i8ptr_type, "jmpbuf_i8");
+        let handler = self.exc_global("shiika_exc_handler");
+        let prev = self.build_entry_alloca(function, self.i8ptr_type.into(), "prev_handler");
+        let prev_value = self.builder.build_load(handler, "prev");
+        self.builder.build_store(prev, prev_value);
+        self.builder.build_store(handler, buf_i8);
+
+        let result = self
+            .builder
+            .build_call(self.get_llvm_func("_setjmp"), &[buf_i8], "setjmp")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let raised = self.builder.build_int_compare(
+            inkwell::IntPredicate::NE,
+            result,
+            self.i32_type.const_int(0, false),
+            "raised",
+        );
+        (raised, prev)
+    }
+
+    /// Restore the handler saved by build_push_exc_handler
+    pub(super) fn build_pop_exc_handler(&self, prev: inkwell::values::PointerValue) {
+        let prev_value = self.builder.build_load(prev, "prev");
+        self.builder
+            .build_store(self.exc_global("shiika_exc_handler"), prev_value);
+    }
+
+    /// Jump to the innermost handler (the exception should be stored
+    /// to `@shiika_exception` beforehand)
+    pub(super) fn build_longjmp(&self) {
+        let handler = self
+            .builder
+            .build_load(self.exc_global("shiika_exc_handler"), "handler");
+        self.builder.build_call(
+            self.get_llvm_func("_longjmp"),
+            &[
+                handler,
+                self.i32_type.const_int(1, false).as_basic_value_enum(),
+            ],
+            "",
+        );
+        self.builder.build_unreachable();
+    }
+
+    /// Return the exception being raised (as i8*)
+    pub(super) fn build_load_exception<'a>(&'a self) -> inkwell::values::BasicValueEnum<'a> {
+        self.builder
+            .build_load(self.exc_global("shiika_exception"), "exception")
+    }
+
+    pub(super) fn build_store_exception<'a>(&'a self, exc: inkwell::values::BasicValueEnum<'a>) {
+        let exc_i8 = self.builder.build_bitcast(exc, self.i8ptr_type, "exc");
+        self.builder
+            .build_store(self.exc_global("shiika_exception"), exc_i8);
+    }
+
+    /// Print the class and the message of the uncaught exception to stderr
+    pub(super) fn build_report_uncaught_exception(&self) {
+        let exc_i8 = self.build_load_exception();
+        let exc = self
+            .builder
+            .build_bitcast(exc_i8, self.llvm_type(&ty::raw("Exception")), "exc");
+
+        let cls = self.build_class_load(exc);
+        let name = self.build_ivar_load(cls, 0, "@name");
+        let name_ptr = self.build_ivar_load(name, 0, "@ptr");
+        let msg = self.build_ivar_load(exc, 0, "@message");
+        let msg_ptr = self.build_ivar_load(msg, 0, "@ptr");
+
+        let tmpl = self
+            .module
+            .get_global("uncaught_tmpl")
+            .unwrap()
+            .as_pointer_value();
+        let tmpl_ptr = unsafe {
+            tmpl.const_in_bounds_gep(&[
+                self.i32_type.const_int(0, false),
+                self.i32_type.const_int(0, false),
+            ])
+        };
+        self.builder.build_call(
+            self.get_llvm_func("dprintf"),
+            &[
+                self.i32_type.const_int(2, false).into(), // stderr
+                tmpl_ptr.into(),
+                name_ptr,
+                msg_ptr,
+            ],
+            "",
+        );
+    }
+
+    fn exc_global(&self, name: &str) -> inkwell::values::PointerValue<'ictx> {
+        self.module
+            .get_global(name)
+            .unwrap_or_else(|| panic!("[BUG] global `{}' not created", name))
+            .as_pointer_value()
+    }
+}
@@ -46,6 +46,22 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 body_exprs,
             } => self.gen_while_expr(ctx, &cond_expr, &body_exprs),
             HirBreakExpression => self.gen_break_expr(ctx),
+            HirReturnExpression { arg } => self.gen_return_expr(ctx, arg),
+            HirBeginExpression {
+                body_exprs,
+                rescue_clauses,
+                ensure_exprs,
+            } => self.gen_begin_expr(ctx, &expr.ty, body_exprs, rescue_clauses, ensure_exprs),
+            HirRaiseExpression { expr } => self.gen_raise_expr(ctx, expr),
+            HirCaughtException => Ok(self.gen_caught_exception(&expr.ty)),
+            HirMatchExpression {
+                cond_assign_expr,
+                clauses,
+            } => self.gen_match_expr(ctx, &expr.ty, cond_assign_expr, clauses),
+            HirIsA {
+                expr: target,
+                class_fullnames,
+            } => self.gen_is_a(ctx, target, class_fullnames),
             HirLVarAssign { name, rhs } => self.gen_lvar_assign(ctx, name, rhs),
             HirIVarAssign { name, idx, rhs, .. } => self.gen_ivar_assign(ctx, name, idx, rhs),
             HirConstAssign { fullname, rhs } => self.gen_const_assign(ctx, fullname, rhs),
@@ -54,17 +70,28 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 method_fullname,
                 arg_exprs,
             } => self.gen_method_call(ctx, method_fullname, receiver_expr, arg_exprs),
-            HirArgRef { idx } => self.gen_arg_ref(ctx, idx),
+            HirArgRef { idx } => self.gen_arg_ref(ctx, idx, &expr.ty),
             HirLVarRef { name } => self.gen_lvar_ref(ctx, name),
             HirIVarRef { name, idx } => self.gen_ivar_ref(ctx, name, idx),
             HirConstRef { fullname } => Ok(self.gen_const_ref(fullname)),
-            HirLambda { params, exprs } => self.gen_lambda(ctx, params, exprs),
+            HirLambda {
+                params,
+                exprs,
+                captures,
+                captured_lvars,
+            } => self.gen_lambda(ctx, params, exprs, captures, captured_lvars),
+            HirLambdaCaptureRef { idx } => Ok(self.gen_lambda_capture_ref(ctx, idx, &expr.ty)),
+            HirLambdaCaptureWrite { cidx, rhs } => self.gen_lambda_capture_write(ctx, cidx, rhs),
             HirSelfExpression => self.gen_self_expression(ctx),
             HirArrayLiteral { exprs } => self.gen_array_literal(ctx, exprs),
+            HirHashLiteral { exprs } => self.gen_hash_literal(ctx, exprs),
+            HirStringInterpolation { exprs } => self.gen_exprs(ctx, exprs),
             HirFloatLiteral { value } => Ok(self.gen_float_literal(*value)),
             HirDecimalLiteral { value } => Ok(self.gen_decimal_literal(*value)),
             HirStringLiteral { idx } => Ok(self.gen_string_literal(idx)),
             HirBooleanLiteral { value } => Ok(self.gen_boolean_literal(*value)),
+            HirNilLiteral => Ok(self.gen_nil_literal(&expr.ty)),
+            HirIsNil { expr: target } => self.gen_is_nil(ctx, target),
             HirBitCast { expr: target } => self.gen_bitcast(ctx, target, &expr.ty),
             HirClassLiteral {
                 fullname,
@@ -172,19 +199,33 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 self.builder.position_at_end(then_block);
                 let then_value: &dyn inkwell::values::BasicValue =
                     &self.gen_exprs(ctx, then_exprs)?;
-                self.builder.build_unconditional_branch(merge_block);
+                self.gen_branch_unless_never(&then_exprs.ty, merge_block);
                 let then_block_end = self.builder.get_insert_block().unwrap();
                 // IfElse:
                 self.builder.position_at_end(else_block);
                 let else_value = self.gen_exprs(ctx, else_exprs)?;
-                self.builder.build_unconditional_branch(merge_block);
+                self.gen_branch_unless_never(&else_exprs.ty, merge_block);
                 let else_block_end = self.builder.get_insert_block().unwrap();
                 // IfEnd:
                 self.builder.position_at_end(merge_block);
 
+                if ty.is_never_type() {
+                    return Ok(self.i32_type.const_int(0, false).as_basic_value_enum());
+                    // return Never
+                }
+                // The values of the clauses may have different types
+                // (eg. `if a then 1 else puts "x" end`)
+                if ty.is_void_type() {
+                    return Ok(self.i1_type.const_int(0, false).as_basic_value_enum());
+                    // dummy value
+                }
                 let phi_node = self.builder.build_phi(self.llvm_type(ty), "ifResult");
-                phi_node
-                    .add_incoming(&[(then_value, then_block_end), (&else_value, else_block_end)]);
+                if !then_exprs.ty.is_never_type() {
+                    phi_node.add_incoming(&[(then_value, then_block_end)]);
+                }
+                if !else_exprs.ty.is_never_type() {
+                    phi_node.add_incoming(&[(&else_value, else_block_end)]);
+                }
                 Ok(phi_node.as_basic_value())
             }
             None => {
@@ -195,7 +236,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 // IfThen:
                 self.builder.position_at_end(then_block);
                 self.gen_exprs(ctx, then_exprs)?;
-                self.builder.build_unconditional_branch(merge_block);
+                self.gen_branch_unless_never(&then_exprs.ty, merge_block);
                 // IfEnd:
                 self.builder.position_at_end(merge_block);
                 Ok(self.i1_type.const_int(0, false).as_basic_value_enum()) // dummy value
@@ -221,9 +262,12 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         self.builder.position_at_end(body_block);
         let rc1 = Rc::new(end_block);
         let rc2 = Rc::clone(&rc1);
-        ctx.current_loop_end = Some(rc1);
+        let outer_loop_end = ctx.current_loop_end.replace(rc1);
+        let outer_begin_depth =
+            std::mem::replace(&mut ctx.current_loop_begin_depth, ctx.begin_frames.len());
         self.gen_exprs(ctx, body_exprs)?;
-        ctx.current_loop_end = None;
+        ctx.current_loop_end = outer_loop_end;
+        ctx.current_loop_begin_depth = outer_begin_depth;
         self.builder.build_unconditional_branch(begin_block);
 
         // WhileEnd:
@@ -235,15 +279,335 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
-        match &ctx.current_loop_end {
+        match ctx.current_loop_end.clone() {
             Some(b) => {
-                self.builder.build_unconditional_branch(*Rc::clone(b));
-                Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Void
+                // Exit the `begin`s in the loop
+                let depth = ctx.current_loop_begin_depth;
+                self.gen_exit_begins(ctx, depth)?;
+                self.builder.build_unconditional_branch(*b);
+                // Any code after `break` is unreachable
+                let dead_block = self.context.append_basic_block(ctx.function, "AfterBreak");
+                self.builder.position_at_end(dead_block);
+                Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Never
             }
             None => Err(error::program_error("break outside of a loop")),
         }
     }
 
+    fn gen_return_expr(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        arg: &'hir HirExpression,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, arg)?;
+        self.gen_exit_begins(ctx, 0)?;
+        match ctx.function_origin {
+            FunctionOrigin::Lambda => {
+                let value = if arg.ty.is_void_type() {
+                    self.gen_const_ref(&const_fullname("::void"))
+                } else {
+                    value
+                };
+                let obj = self.build_coerce(value, self.llvm_type(&ty::raw("Object")));
+                self.builder.build_return(Some(&obj));
+            }
+            _ => match ctx.function.get_type().get_return_type() {
+                Some(ret_type) => {
+                    let ret_value = self.build_coerce(value, ret_type);
+                    self.builder.build_return(Some(&ret_value));
+                }
+                None => {
+                    self.builder.build_return(None);
+                }
+            },
+        }
+        // Any code after `return` is unreachable
+        let dead_block = self.context.append_basic_block(ctx.function, "AfterReturn");
+        self.builder.position_at_end(dead_block);
+        Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Never
+    }
+
+    /// Run the ensure clauses and restore the exception handlers of the
+    /// `begin`s being exited by `break` or `return` (the ones after `depth`
+    /// in `ctx.begin_frames`)
+    fn gen_exit_begins(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        depth: usize,
+    ) -> Result<(), Error> {
+        let frames = ctx.begin_frames.split_off(depth);
+        for (i, frame) in frames.iter().enumerate().rev() {
+            self.build_pop_exc_handler(frame.prev_handler);
+            if let Some(exprs) = frame.ensure_exprs {
+                // The outer `begin`s are still active in the ensure clause.
+                // Variables defined here must not be visible from the
+                // ensure clause generated by `gen_begin_expr`
+                ctx.begin_frames.extend_from_slice(&frames[..i]);
+                let lvars = ctx.lvars.clone();
+                self.gen_exprs(ctx, exprs)?;
+                ctx.lvars = lvars;
+                ctx.begin_frames.truncate(depth);
+            }
+        }
+        ctx.begin_frames.extend(frames);
+        Ok(())
+    }
+
+    /// Generate code for `begin ... rescue ... ensure ... end`
+    fn gen_begin_expr(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        ty: &TermTy,
+        body_exprs: &'hir HirExpressions,
+        rescue_clauses: &'hir [HirRescueClause],
+        ensure_exprs: &'hir Option<HirExpressions>,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let result = if ty.is_void_type() || ty.is_never_type() {
+            None
+        } else {
+            let llvm_type = self.llvm_type(ty);
+            let ptr = self.build_entry_alloca(ctx.function, llvm_type, "begin_result");
+            Some((ptr, llvm_type))
+        };
+        // The exception being rescued
+        let exc = self.build_entry_alloca(ctx.function, self.i8ptr_type.into(), "rescued");
+        // Whether to raise `exc` again after the ensure clause
+        let reraise = self.build_entry_alloca(ctx.function, self.i1_type.into(), "reraise");
+        self.builder
+            .build_store(reraise, self.i1_type.const_int(0, false));
+        let body_block = self.context.append_basic_block(ctx.function, "BeginBody");
+        let rescue_block = self.context.append_basic_block(ctx.function, "BeginRescue");
+        let ensure_block = self.context.append_basic_block(ctx.function, "BeginEnsure");
+        let end_block = self.context.append_basic_block(ctx.function, "BeginEnd");
+        let (raised, prev_handler) = self.build_push_exc_handler(ctx.function);
+        self.builder
+            .build_conditional_branch(raised, rescue_block, body_block);
+
+        // BeginBody:
+        self.builder.position_at_end(body_block);
+        ctx.begin_frames.push(BeginFrame {
+            prev_handler,
+            ensure_exprs: ensure_exprs.as_ref(),
+        });
+        let value = self.gen_exprs(ctx, body_exprs)?;
+        ctx.begin_frames.pop();
+        self.build_pop_exc_handler(prev_handler);
+        self.gen_begin_result(result, value, &body_exprs.ty, ensure_block);
+
+        // BeginRescue:
+        self.builder.position_at_end(rescue_block);
+        self.build_pop_exc_handler(prev_handler);
+        self.builder.build_store(exc, self.build_load_exception());
+        // Exceptions in the rescue clauses should also run the ensure clause
+        let inner_handler = if ensure_exprs.is_some() {
+            let dispatch_block = self
+                .context
+                .append_basic_block(ctx.function, "RescueDispatch");
+            let raised_block = self
+                .context
+                .append_basic_block(ctx.function, "RescueRaised");
+            let (raised, prev) = self.build_push_exc_handler(ctx.function);
+            self.builder
+                .build_conditional_branch(raised, raised_block, dispatch_block);
+            // RescueRaised:
+            self.builder.position_at_end(raised_block);
+            self.build_pop_exc_handler(prev);
+            self.builder.build_store(exc, self.build_load_exception());
+            self.builder
+                .build_store(reraise, self.i1_type.const_int(1, false));
+            self.builder.build_unconditional_branch(ensure_block);
+            // RescueDispatch:
+            self.builder.position_at_end(dispatch_block);
+            ctx.begin_frames.push(BeginFrame {
+                prev_handler: prev,
+                ensure_exprs: ensure_exprs.as_ref(),
+            });
+            Some(prev)
+        } else {
+            None
+        };
+        for clause in rescue_clauses {
+            let clause_block = self
+                .context
+                .append_basic_block(ctx.function, "RescueClause");
+            let next_block = self.context.append_basic_block(ctx.function, "RescueNext");
+            let exc_value = self.builder.build_load(exc, "exc");
+            let matched = self.build_class_check(exc_value, &clause.class_fullnames);
+            self.builder
+                .build_conditional_branch(matched, clause_block, next_block);
+            // RescueClause:
+            self.builder.position_at_end(clause_block);
+            let value = self.gen_exprs(ctx, &clause.exprs)?;
+            if let Some(name) = &clause.var_name {
+                // The variable is only visible in the clause
+                ctx.lvars.remove(name);
+            }
+            if let Some(prev) = inner_handler {
+                self.build_pop_exc_handler(prev);
+            }
+            self.gen_begin_result(result, value, &clause.exprs.ty, ensure_block);
+            // RescueNext:
+            self.builder.position_at_end(next_block);
+        }
+        // No rescue clause matched
+        if let Some(prev) = inner_handler {
+            ctx.begin_frames.pop();
+            self.build_pop_exc_handler(prev);
+        }
+        self.builder
+            .build_store(reraise, self.i1_type.const_int(1, false));
+        self.builder.build_unconditional_branch(ensure_block);
+
+        // BeginEnsure:
+        self.builder.position_at_end(ensure_block);
+        if let Some(exprs) = ensure_exprs {
+            self.gen_exprs(ctx, exprs)?;
+        }
+        let reraise_block = self.context.append_basic_block(ctx.function, "Reraise");
+        let flag = self.builder.build_load(reraise, "reraise").into_int_value();
+        self.builder
+            .build_conditional_branch(flag, reraise_block, end_block);
+        // Reraise:
+        self.builder.position_at_end(reraise_block);
+        self.build_store_exception(self.builder.build_load(exc, "exc"));
+        self.build_longjmp();
+
+        // BeginEnd:
+        self.builder.position_at_end(end_block);
+        match result {
+            Some((ptr, _)) => Ok(self.builder.build_load(ptr, "begin_result")),
+            None => Ok(self.i32_type.const_int(0, false).as_basic_value_enum()), // return Void
+        }
+    }
+
+    /// Store the value of the body or a rescue clause and jump to the
+    /// ensure clause
+    fn gen_begin_result(
+        &self,
+        result: Option<(inkwell::values::PointerValue, inkwell::types::BasicTypeEnum)>,
+        value: inkwell::values::BasicValueEnum,
+        ty: &TermTy,
+        ensure_block: inkwell::basic_block::BasicBlock,
+    ) {
+        if ty.is_never_type() {
+            self.builder.build_unreachable();
+            return;
+        }
+        if let Some((ptr, llvm_type)) = result {
+            self.builder
+                .build_store(ptr, self.build_coerce(value, llvm_type));
+        }
+        self.builder.build_unconditional_branch(ensure_block);
+    }
+
+    fn gen_raise_expr(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        expr: &'hir HirExpression,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let exc = self.gen_expr(ctx, expr)?;
+        self.build_store_exception(exc);
+        self.build_longjmp();
+        // Any code after `raise` is unreachable
+        let dead_block = self.context.append_basic_block(ctx.function, "AfterRaise");
+        self.builder.position_at_end(dead_block);
+        Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Never
+    }
+
+    fn gen_caught_exception(&self, ty: &TermTy) -> inkwell::values::BasicValueEnum {
+        let exc = self.build_load_exception();
+        self.builder.build_bitcast(exc, self.llvm_type(ty), "exc")
+    }
+
+    /// Generate code for `match`
+    fn gen_match_expr(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        ty: &TermTy,
+        cond_assign_expr: &'hir HirExpression,
+        clauses: &'hir [HirMatchClause],
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let result = if ty.is_void_type() || ty.is_never_type() {
+            None
+        } else {
+            let llvm_type = self.llvm_type(ty);
+            let ptr = self.build_entry_alloca(ctx.function, llvm_type, "match_result");
+            Some((ptr, llvm_type))
+        };
+        let end_block = self.context.append_basic_block(ctx.function, "MatchEnd");
+        self.gen_expr(ctx, cond_assign_expr)?;
+        for clause in clauses {
+            let next_block = self.context.append_basic_block(ctx.function, "MatchNext");
+            for component in &clause.components {
+                match component {
+                    HirMatchComponent::Test(expr) => {
+                        let cond_value = self.gen_expr(ctx, expr)?.into_int_value();
+                        let ok_block = self.context.append_basic_block(ctx.function, "MatchOk");
+                        self.gen_conditional_branch(cond_value, ok_block, next_block);
+                        self.builder.position_at_end(ok_block);
+                    }
+                    HirMatchComponent::Bind(name, expr) => {
+                        self.gen_lvar_assign(ctx, name, expr)?;
+                    }
+                }
+            }
+            let value = self.gen_exprs(ctx, &clause.body_hir)?;
+            // The variables are only visible in the clause
+            for component in &clause.components {
+                if let HirMatchComponent::Bind(name, _) = component {
+                    ctx.lvars.remove(name);
+                }
+            }
+            if clause.body_hir.ty.is_never_type() {
+                self.builder.build_unreachable();
+            } else {
+                if let Some((ptr, llvm_type)) = result {
+                    self.builder
+                        .build_store(ptr, self.build_coerce(value, llvm_type));
+                }
+                self.builder.build_unconditional_branch(end_block);
+            }
+            // MatchNext:
+            self.builder.position_at_end(next_block);
+        }
+        // No clause matched (this does not happen because `match` is
+        // checked to be exhaustive)
+        self.builder.build_unreachable();
+
+        // MatchEnd:
+        self.builder.position_at_end(end_block);
+        match result {
+            Some((ptr, _)) => Ok(self.builder.build_load(ptr, "match_result")),
+            None => Ok(self.i32_type.const_int(0, false).as_basic_value_enum()), // return Void
+        }
+    }
+
+    fn gen_is_a(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        expr: &'hir HirExpression,
+        class_fullnames: &[ClassFullname],
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let obj = self.gen_expr(ctx, expr)?;
+        let matched = self.build_class_check(obj, class_fullnames);
+        Ok(self.builder.build_select(
+            matched,
+            self.gen_boolean_literal(true),
+            self.gen_boolean_literal(false),
+            "is_a",
+        ))
+    }
+
+    /// Jump to `block` unless the value of `ty` is Never
+    /// (there is no need to jump after `raise`, etc.)
+    fn gen_branch_unless_never(&self, ty: &TermTy, block: inkwell::basic_block::BasicBlock) {
+        if ty.is_never_type() {
+            self.builder.build_unreachable();
+        } else {
+            self.builder.build_unconditional_branch(block);
+        }
+    }
+
     fn gen_lvar_assign(
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
@@ -257,8 +621,19 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 self.builder.build_store(*ptr, value);
             }
             None => {
-                let ptr = self.builder.build_alloca(self.llvm_type(&rhs.ty), name);
-                self.builder.build_store(ptr, value);
+                let ptr = if ctx.captured_lvars.iter().any(|s| s == name) {
+                    // Allocate on the heap so that lambdas can share it
+                    // even after this function returns
+                    let cell = self.build_cell(value);
+                    let ptr_type = self.llvm_type(&rhs.ty).ptr_type(AddressSpace::Generic);
+                    self.builder
+                        .build_bitcast(cell, ptr_type, name)
+                        .into_pointer_value()
+                } else {
+                    let ptr = self.builder.build_alloca(self.llvm_type(&rhs.ty), name);
+                    self.builder.build_store(ptr, value);
+                    ptr
+                };
                 ctx.lvars.insert(name.to_string(), ptr);
             }
         }
@@ -307,7 +682,65 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             .iter()
             .map(|arg_expr| self.gen_expr(ctx, arg_expr))
             .collect::<Result<Vec<_>, _>>()?;
-        self.gen_llvm_func_call(&method_fullname.full_name, receiver_value, arg_values)
+        match self.vtable_index(&receiver_expr.ty, method_fullname) {
+            Some(idx) => {
+                self.gen_virtual_call(&method_fullname.full_name, idx, receiver_value, arg_values)
+            }
+            None => self.gen_llvm_func_call(&method_fullname.full_name, receiver_value, arg_values),
+        }
+    }
+
+    /// Return the vtable index of the method if it should be called dynamically
+    fn vtable_index(
+        &self,
+        receiver_ty: &TermTy,
+        method_fullname: &MethodFullname,
+    ) -> Option<usize> {
+        let class_name = match &receiver_ty.body {
+            TyBody::TySpe { base_name, .. } => class_fullname(base_name),
+            TyBody::TyParamRef { .. } => class_fullname("Object"),
+            _ => receiver_ty.fullname.clone(),
+        };
+        // These are not a pointer to a Shiika object
+        if class_name.0 == "Bool" || class_name.0 == "Shiika::Internal::Ptr" {
+            return None;
+        }
+        // Initializers are always called with the exact class
+        if method_fullname.first_name.0 == "initialize" {
+            return None;
+        }
+        // Modules have no vtable; only the methods of Object are called
+        // dynamically (methods of the module are called directly)
+        let vtable = self
+            .vtables
+            .get(&class_name)
+            .or_else(|| self.vtables.get(&class_fullname("Object")))?;
+        vtable.get(&method_fullname.first_name)
+    }
+
+    /// Generate method call via vtable
+    fn gen_virtual_call<'a>(
+        &'a self,
+        func_name: &str,
+        idx: usize,
+        receiver_value: inkwell::values::BasicValueEnum<'a>,
+        mut arg_values: Vec<inkwell::values::BasicValueEnum<'a>>,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        // The overriding method must have the same llvm func type
+        let func_type = self.get_llvm_func(func_name).get_type();
+        let func = self.build_vtable_ref(receiver_value, idx, func_type);
+        let mut llvm_args = vec![receiver_value];
+        llvm_args.append(&mut arg_values);
+        let llvm_args = self.build_coerce_args(llvm_args, func_type);
+        match self
+            .builder
+            .build_call(func, &llvm_args, "result")
+            .try_as_basic_value()
+            .left()
+        {
+            Some(result_value) => Ok(result_value),
+            None => Ok(self.gen_const_ref(&const_fullname("::void"))),
+        }
     }
 
     /// Generate llvm function call
@@ -320,6 +753,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         let function = self.get_llvm_func(func_name);
         let mut llvm_args = vec![receiver_value];
         llvm_args.append(&mut arg_values);
+        let llvm_args = self.build_coerce_args(llvm_args, function.get_type());
         match self
             .builder
             .build_call(function, &llvm_args, "result")
@@ -335,12 +769,19 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
         idx: &usize,
+        ty: &TermTy,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
-        let plus = match ctx.function_origin {
-            FunctionOrigin::Method => 1, // +1 for the first %self
-            _ => 0,
-        };
-        Ok(ctx.function.get_nth_param((*idx as u32) + plus).unwrap())
+        match ctx.function_origin {
+            // +1 for the first %self
+            FunctionOrigin::Method => Ok(ctx.function.get_nth_param((*idx as u32) + 1).unwrap()),
+            // +1 for the first %freevars
+            FunctionOrigin::Lambda => {
+                // Lambdas take the args as Object (see lambda_llvm_func_type)
+                let arg = ctx.function.get_nth_param((*idx as u32) + 1).unwrap();
+                Ok(self.build_coerce(arg, self.llvm_type(ty)))
+            }
+            _ => Ok(ctx.function.get_nth_param(*idx as u32).unwrap()),
+        }
     }
 
     fn gen_lvar_ref(
@@ -375,23 +816,111 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         ctx: &mut CodeGenContext<'hir, 'run>,
         params: &'hir [MethodParam],
         exprs: &'hir HirExpressions,
+        captures: &'hir [HirLambdaCapture],
+        captured_lvars: &'hir [String],
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
         let func_name = ctx.new_lambda_name();
-        ctx.push_lambda(func_name.clone(), params, exprs);
+        let self_type = self.self_llvm_type(ctx);
+        ctx.push_lambda(func_name.clone(), params, exprs, captured_lvars, self_type);
 
-        let ret_ty = &exprs.ty;
-        let func_type = self.llvm_func_type(None, &params, &ret_ty);
+        let func_type = self.lambda_llvm_func_type(params.len());
         self.module.add_function(&func_name, func_type, None);
 
-        // Fn1.new(fnptr, freevars)
-        let meta = self.gen_const_ref(&const_fullname("::Fn1"));
+        // eg. Fn1.new(fnptr, freevars)
+        let cls_name = format!("Fn{}", params.len());
+        let meta = self.gen_const_ref(&const_fullname(&format!("::{}", cls_name)));
         let fnptr = self
             .get_llvm_func(&func_name)
             .as_global_value()
             .as_basic_value_enum();
         let fnptr_i8 = self.builder.build_bitcast(fnptr, self.i8ptr_type, "");
-        let arg_values = vec![fnptr_i8];
-        self.gen_llvm_func_call("Meta:Fn1#new", meta, arg_values)
+        let freevars = self.gen_lambda_freevars(ctx, captures)?;
+        let arg_values = vec![fnptr_i8, freevars];
+        self.gen_llvm_func_call(&format!("Meta:{}#new", cls_name), meta, arg_values)
+    }
+
+    /// Create `freevars` of a lambda, which is an array of pointers to
+    /// the captured values. The first item is for the captured `self`
+    fn gen_lambda_freevars(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        captures: &'hir [HirLambdaCapture],
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let size = self
+            .i64_type
+            .const_int(((captures.len() + 1) * BYTES_OF_PTR) as u64, false);
+        let mem = self.build_gc_malloc(size, "freevars");
+        let ary = self
+            .builder
+            .build_bitcast(
+                mem,
+                self.i8ptr_type.ptr_type(AddressSpace::Generic),
+                "freevars",
+            )
+            .into_pointer_value();
+
+        let the_self = match ctx.function_origin {
+            // Lambdas in constant initializers have no `self`
+            FunctionOrigin::Other if self.the_main.is_none() => self
+                .llvm_type(&ty::raw("Object"))
+                .into_pointer_type()
+                .const_null()
+                .as_basic_value_enum(),
+            _ => self.gen_self_expression(ctx)?,
+        };
+        self.build_freevar_store(ary, 0, self.build_cell(the_self));
+
+        for (i, cap) in captures.iter().enumerate() {
+            let cell = match &cap.detail {
+                HirLambdaCaptureDetail::CaptureLVar => {
+                    let ptr = *ctx
+                        .lvars
+                        .get(&cap.name)
+                        .expect("[BUG] captured lvar not declared");
+                    if cap.readonly {
+                        // Just copy the value because it never changes
+                        let value = self.builder.build_load(ptr, &cap.name);
+                        self.build_cell(value)
+                    } else {
+                        // Share the heap memory (see gen_lvar_assign)
+                        self.builder.build_bitcast(ptr, self.i8ptr_type, "cell")
+                    }
+                }
+                HirLambdaCaptureDetail::CaptureArg { idx } => {
+                    let value = self.gen_arg_ref(ctx, idx, &cap.ty)?;
+                    self.build_cell(value)
+                }
+                HirLambdaCaptureDetail::CaptureFwd { cidx } => {
+                    self.build_freevar_load(ctx, cidx + 1)
+                }
+            };
+            self.build_freevar_store(ary, i + 1, cell);
+        }
+        Ok(mem)
+    }
+
+    /// Load a captured variable
+    fn gen_lambda_capture_ref(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        idx: &usize,
+        ty: &TermTy,
+    ) -> inkwell::values::BasicValueEnum {
+        let ptr = self.build_captured_var_ptr(ctx, *idx, ty);
+        self.builder.build_load(ptr, "captured")
+    }
+
+    /// Store a value to a captured variable
+    fn gen_lambda_capture_write(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        cidx: &usize,
+        rhs: &'hir HirExpression,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, rhs)?;
+        let ptr = self.build_captured_var_ptr(ctx, *cidx, &rhs.ty);
+        self.builder.build_store(ptr, value);
+        Ok(value)
     }
 
     fn gen_self_expression(
@@ -400,6 +929,17 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
         if ctx.function.get_name().to_str().unwrap() == "user_main" {
             Ok(self.the_main.expect("[BUG] self.the_main is None"))
+        } else if let FunctionOrigin::Lambda = ctx.function_origin {
+            // `self` is captured as the first item of the freevars
+            let self_type = ctx
+                .lambda_self_type
+                .expect("[BUG] lambda_self_type is None");
+            let cell = self.build_freevar_load(ctx, 0);
+            let ptr = self
+                .builder
+                .build_bitcast(cell, self_type.ptr_type(AddressSpace::Generic), "self")
+                .into_pointer_value();
+            Ok(self.builder.build_load(ptr, "self"))
         } else {
             // The first arg of llvm function is `self`
             Ok(ctx
@@ -409,6 +949,27 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         }
     }
 
+    /// Return the llvm type of `self` in the current function
+    fn self_llvm_type(
+        &self,
+        ctx: &CodeGenContext<'hir, 'run>,
+    ) -> inkwell::types::BasicTypeEnum<'run> {
+        match ctx.function_origin {
+            FunctionOrigin::Method => ctx
+                .function
+                .get_first_param()
+                .expect("[BUG] get_first_param() is None")
+                .get_type(),
+            FunctionOrigin::Lambda => ctx
+                .lambda_self_type
+                .expect("[BUG] lambda_self_type is None"),
+            FunctionOrigin::Other => match self.the_main {
+                Some(the_main) => the_main.get_type(),
+                None => self.llvm_type(&ty::raw("Object")),
+            },
+        }
+    }
+
     /// Generate code for creating an array
     fn gen_array_literal(
         &self,
@@ -418,12 +979,21 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         self.gen_exprs(ctx, exprs)
     }
 
+    /// Generate code for creating a hash
+    fn gen_hash_literal(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        exprs: &'hir HirExpressions,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        self.gen_exprs(ctx, exprs)
+    }
+
     fn gen_float_literal(&self, value: f64) -> inkwell::values::BasicValueEnum {
         self.box_float(&self.f64_type.const_float(value))
     }
 
-    fn gen_decimal_literal(&self, value: i32) -> inkwell::values::BasicValueEnum {
-        self.box_int(&self.i32_type.const_int(value as u64, false))
+    fn gen_decimal_literal(&self, value: i64) -> inkwell::values::BasicValueEnum {
+        self.box_int(&self.i64_type.const_int(value as u64, false))
     }
 
     fn gen_string_literal(&self, idx: &usize) -> inkwell::values::BasicValueEnum {
@@ -442,7 +1012,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
 
         // Store bytesize
         let bytesize = self
-            .i32_type
+            .i64_type
             .const_int(self.str_literals[*idx].len() as u64, false);
         let sk_int = self.box_int(&bytesize);
         self.build_ivar_store(&sk_str, 1, sk_int, "@bytesize");
@@ -455,6 +1025,39 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         self.i64_type.const_int(i, false).as_basic_value_enum()
     }
 
+    fn gen_nil_literal(&self, ty: &TermTy) -> inkwell::values::BasicValueEnum {
+        self.llvm_type(ty)
+            .into_pointer_type()
+            .const_null()
+            .as_basic_value_enum()
+    }
+
+    fn gen_is_nil(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        expr: &'hir HirExpression,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        // `Bool?` is an i64 and its nil is 0 (see `llvm_type`)
+        let is_null = match value {
+            BasicValueEnum::IntValue(i) => self.builder.build_int_compare(
+                inkwell::IntPredicate::EQ,
+                i,
+                i.get_type().const_int(0, false),
+                "is_nil",
+            ),
+            _ => self
+                .builder
+                .build_is_null(value.into_pointer_value(), "is_nil"),
+        };
+        Ok(self.builder.build_select(
+            is_null,
+            self.gen_boolean_literal(true),
+            self.gen_boolean_literal(false),
+            "is_nil",
+        ))
+    }
+
     fn gen_conditional_branch(
         &self,
         cond: inkwell::values::IntValue,
@@ -479,15 +1082,16 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         ty: &TermTy,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
         let obj = self.gen_expr(ctx, expr)?;
-        Ok(self.builder.build_bitcast(obj, self.llvm_type(ty), "as"))
+        Ok(self.build_coerce(obj, self.llvm_type(ty)))
     }
 
+    /// Set up the class object created by `gen_class_obj`
     fn gen_class_literal(
         &self,
         fullname: &ClassFullname,
         str_literal_idx: &usize,
     ) -> inkwell::values::BasicValueEnum {
-        let cls_obj = self.allocate_sk_obj(&fullname.meta_name(), &format!("class_{}", fullname.0));
+        let cls_obj = self.gen_const_ref(&fullname.to_const_fullname());
         // Set @name
         self.build_ivar_store(
             &cls_obj,
@@ -495,10 +1099,34 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             self.gen_string_literal(str_literal_idx),
             "@name",
         );
+        // Set the class of the class object (`Class` may not be created yet
+        // in `gen_class_obj`)
+        let cls = self.gen_const_ref(&const_fullname("::Class"));
+        let cls_i8 = self.builder.build_bitcast(cls, self.i8ptr_type, "class");
+        let ptr = self
+            .builder
+            .build_struct_gep(
+                cls_obj.into_pointer_value(),
+                OBJ_CLASS_IDX as u32,
+                "addr_class",
+            )
+            .unwrap();
+        self.builder.build_store(ptr, cls_i8);
 
         cls_obj
     }
 
+    /// Allocate a class object and store it to the constant
+    pub(super) fn gen_class_obj(&self, fullname: &ClassFullname) {
+        let cls_obj = self.allocate_sk_obj(&fullname.meta_name(), &format!("class_{}", fullname.0));
+        let ptr = self
+            .module
+            .get_global(&fullname.to_const_fullname().0)
+            .unwrap_or_else(|| panic!("[BUG] global for class `{}' not created", fullname.0))
+            .as_pointer_value();
+        self.builder.build_store(ptr, cls_obj);
+    }
+
     // Generate call of GC_malloc and returns a ptr to Shiika object
     pub fn allocate_sk_obj(
         &self,
@@ -512,16 +1140,234 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             .expect("[BUG] object_type has no size");
 
         // %mem = call i8* @GC_malloc(i64 %size)",
-        let func = self.get_llvm_func("GC_malloc");
-        let raw_addr = self
+        let raw_addr = self.build_gc_malloc(size, "mem");
+
+        // %foo = bitcast i8* %mem to %#{t}*",
+        let obj = self.builder.build_bitcast(raw_addr, obj_ptr_type, reg_name);
+        self.build_obj_header(obj, class_fullname);
+        obj
+    }
+
+    /// Store the vtable and the class object to the header of `obj`
+    fn build_obj_header(
+        &self,
+        obj: inkwell::values::BasicValueEnum<'ictx>,
+        class_fullname: &ClassFullname,
+    ) {
+        let vtable = self
+            .module
+            .get_global(&format!("vtable_{}", class_fullname.0))
+            .unwrap_or_else(|| panic!("[BUG] vtable of `{}' not created", class_fullname.0))
+            .as_pointer_value();
+        let vtable_i8 = self
             .builder
-            .build_call(func, &[size.as_basic_value_enum()], "mem")
-            .try_as_basic_value()
-            .left()
+            .build_bitcast(vtable, self.i8ptr_type, "vtable");
+        let ptr = self
+            .builder
+            .build_struct_gep(
+                obj.into_pointer_value(),
+                OBJ_VTABLE_IDX as u32,
+                "addr_vtable",
+            )
             .unwrap();
+        self.builder.build_store(ptr, vtable_i8);
 
-        // %foo = bitcast i8* %mem to %#{t}*",
-        self.builder.build_bitcast(raw_addr, obj_ptr_type, reg_name)
+        // The class of metaclass objects (ie. Class) is set by
+        // `gen_class_literal`
+        let cls_obj = if class_fullname.is_meta() {
+            self.i8ptr_type.const_null().as_basic_value_enum()
+        } else {
+            let cls = self.gen_const_ref(&class_fullname.to_const_fullname());
+            self.builder.build_bitcast(cls, self.i8ptr_type, "class")
+        };
+        let ptr = self
+            .builder
+            .build_struct_gep(obj.into_pointer_value(), OBJ_CLASS_IDX as u32, "addr_class")
+            .unwrap();
+        self.builder.build_store(ptr, cls_obj);
+    }
+
+    /// Load the vtable of `obj`.
+    /// `obj` may be a Bool upcasted to Object (which is not a pointer but
+    /// a tagged value; see boxing.rs). The vtable of Bool is used for it
+    pub(super) fn build_vtable_load<'a>(
+        &'a self,
+        obj: inkwell::values::BasicValueEnum<'a>,
+    ) -> inkwell::values::BasicValueEnum<'a> {
+        self.build_bool_or_obj(
+            obj,
+            self.i8ptr_type.as_basic_type_enum(),
+            || {
+                // The vtable of Bool
+                let vtable = self
+                    .module
+                    .get_global("vtable_Bool")
+                    .expect("[BUG] vtable of Bool not created")
+                    .as_pointer_value();
+                self.builder
+                    .build_bitcast(vtable, self.i8ptr_type, "vtable")
+            },
+            || {
+                // The vtable stored in the header
+                let ptr = self
+                    .builder
+                    .build_struct_gep(
+                        obj.into_pointer_value(),
+                        OBJ_VTABLE_IDX as u32,
+                        "addr_vtable",
+                    )
+                    .unwrap();
+                self.builder.build_load(ptr, "vtable")
+            },
+        )
+    }
+
+    /// Load the function pointer of the method from the vtable of `obj`
+    fn build_vtable_ref<'a>(
+        &'a self,
+        obj: inkwell::values::BasicValueEnum<'a>,
+        idx: usize,
+        func_type: inkwell::types::FunctionType<'a>,
+    ) -> inkwell::values::PointerValue<'a> {
+        let vtable_i8 = self.build_vtable_load(obj);
+        let vtable = self
+            .builder
+            .build_bitcast(
+                vtable_i8,
+                self.i8ptr_type.ptr_type(AddressSpace::Generic),
+                "vtable",
+            )
+            .into_pointer_value();
+        let idx = self.i64_type.const_int(idx as u64, false);
+        let fnptr_ptr = unsafe { self.builder.build_gep(vtable, &[idx], "addr_fnptr") };
+        let fnptr = self.builder.build_load(fnptr_ptr, "fnptr");
+        self.builder
+            .build_bitcast(fnptr, func_type.ptr_type(AddressSpace::Generic), "func")
+            .into_pointer_value()
+    }
+
+    /// Convert the args to the param types of the function
+    /// (eg. an `Int` is passed to a param of type `Object`)
+    pub(super) fn build_coerce_args<'a>(
+        &'a self,
+        args: Vec<inkwell::values::BasicValueEnum<'a>>,
+        func_type: inkwell::types::FunctionType<'a>,
+    ) -> Vec<inkwell::values::BasicValueEnum<'a>> {
+        args.into_iter()
+            .zip(func_type.get_param_types())
+            .map(|(arg, param_type)| self.build_coerce(arg, param_type))
+            .collect()
+    }
+
+    /// Convert `value` to the llvm type `ty`. This is a bitcast but
+    /// Bool (an i64) is converted from/to a pointer
+    pub(super) fn build_coerce<'a>(
+        &'a self,
+        value: inkwell::values::BasicValueEnum<'a>,
+        ty: inkwell::types::BasicTypeEnum<'a>,
+    ) -> inkwell::values::BasicValueEnum<'a> {
+        if value.get_type() == ty {
+            return value;
+        }
+        match (value, ty) {
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::PointerType(t)) => self
+                .builder
+                .build_int_to_ptr(i, t, "as")
+                .as_basic_value_enum(),
+            (BasicValueEnum::PointerValue(p), BasicTypeEnum::IntType(t)) => self
+                .builder
+                .build_ptr_to_int(p, t, "as")
+                .as_basic_value_enum(),
+            _ => self.builder.build_bitcast(value, ty, "as"),
+        }
+    }
+
+    /// Return the pointer to the memory of a captured variable
+    fn build_captured_var_ptr(
+        &self,
+        ctx: &CodeGenContext<'hir, 'run>,
+        cidx: usize,
+        ty: &TermTy,
+    ) -> inkwell::values::PointerValue {
+        // +1 for the captured `self`
+        let cell = self.build_freevar_load(ctx, cidx + 1);
+        self.builder
+            .build_bitcast(
+                cell,
+                self.llvm_type(ty).ptr_type(AddressSpace::Generic),
+                "captured",
+            )
+            .into_pointer_value()
+    }
+
+    /// Load an item of the freevars of the current lambda
+    fn build_freevar_load(
+        &self,
+        ctx: &CodeGenContext<'hir, 'run>,
+        idx: usize,
+    ) -> inkwell::values::BasicValueEnum {
+        let freevars = ctx
+            .function
+            .get_first_param()
+            .expect("[BUG] get_first_param() is None");
+        let ary = self
+            .builder
+            .build_bitcast(
+                freevars,
+                self.i8ptr_type.ptr_type(AddressSpace::Generic),
+                "freevars",
+            )
+            .into_pointer_value();
+        let idx = self.i64_type.const_int(idx as u64, false);
+        let ptr = unsafe { self.builder.build_gep(ary, &[idx], "addr_freevar") };
+        self.builder.build_load(ptr, "freevar")
+    }
+
+    /// Store an item to the freevars being created
+    fn build_freevar_store(
+        &self,
+        ary: inkwell::values::PointerValue,
+        idx: usize,
+        value: inkwell::values::BasicValueEnum,
+    ) {
+        let idx = self.i64_type.const_int(idx as u64, false);
+        let ptr = unsafe { self.builder.build_gep(ary, &[idx], "addr_freevar") };
+        self.builder.build_store(ptr, value);
+    }
+
+    /// Allocate a memory on the heap and store `value` to it.
+    /// Returns the pointer as i8*
+    fn build_cell(
+        &self,
+        value: inkwell::values::BasicValueEnum,
+    ) -> inkwell::values::BasicValueEnum<'ictx> {
+        // Every Shiika value is a pointer or an i64
+        let size = self.i64_type.const_int(BYTES_OF_PTR as u64, false);
+        let mem = self.build_gc_malloc(size, "cell");
+        let ptr = self
+            .builder
+            .build_bitcast(
+                mem,
+                value.get_type().ptr_type(AddressSpace::Generic),
+                "cell",
+            )
+            .into_pointer_value();
+        self.builder.build_store(ptr, value);
+        mem
+    }
+
+    /// Generate call of GC_malloc
+    fn build_gc_malloc(
+        &self,
+        size: inkwell::values::IntValue<'ictx>,
+        reg_name: &str,
+    ) -> inkwell::values::BasicValueEnum<'ictx> {
+        let func = self.get_llvm_func("GC_malloc");
+        self.builder
+            .build_call(func, &[size.as_basic_value_enum()], reg_name)
+            .try_as_basic_value()
+            .left()
+            .unwrap()
     }
 
     pub fn llvm_type(&self, ty: &TermTy) -> inkwell::types::BasicTypeEnum<'ictx> {
@@ -531,6 +1377,10 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 "Shiika::Internal::Ptr" => self.i8ptr_type.as_basic_type_enum(),
                 _ => self.sk_obj_llvm_type(ty),
             },
+            // nil is represented by the null pointer. For `Bool?` it is 0,
+            // which is neither SK_TRUE nor SK_FALSE (and is what the null
+            // pointer becomes by `build_coerce`)
+            TyBody::TyNullable { ref inner } => self.llvm_type(inner),
             _ => self.sk_obj_llvm_type(ty),
         }
     }
@@ -548,13 +1398,14 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
 
     pub fn sk_obj_llvm_type(&self, ty: &TermTy) -> inkwell::types::BasicTypeEnum<'ictx> {
         let s = match &ty.body {
-            TyBody::TySpe { base_name, .. } => &base_name,
-            TyBody::TyParamRef { .. } => "Object", // its upper bound
-            _ => &ty.fullname.0,
+            TyBody::TySpe { base_name, .. } => class_fullname(base_name),
+            TyBody::TySpeMeta { base_name, .. } => metaclass_fullname(base_name),
+            TyBody::TyParamRef { .. } => class_fullname("Object"), // its upper bound
+            _ => ty.fullname.clone(),
         };
         let struct_type = self
             .llvm_struct_types
-            .get(&class_fullname(s))
+            .get(&s)
             .unwrap_or_else(|| panic!("[BUG] struct_type not found: {:?}", ty.fullname));
         struct_type
             .ptr_type(AddressSpace::Generic)
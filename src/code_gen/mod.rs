@@ -1,11 +1,15 @@
 mod boxing;
 mod code_gen_context;
+mod exceptions;
 mod gen_exprs;
 mod utils;
+mod vtables;
 use crate::code_gen::code_gen_context::*;
+use crate::code_gen::vtables::VTables;
 use crate::error::Error;
 use crate::hir::*;
 use crate::names::*;
+use crate::ty;
 use crate::ty::*;
 use either::*;
 use inkwell::types::*;
@@ -17,6 +21,16 @@ use std::collections::HashMap;
 const SK_FALSE: u64 = 0b010;
 const SK_TRUE: u64 = 0b110;
 
+/// Every Shiika object starts with these fields
+/// 0: pointer to the vtable of its class
+/// 1: pointer to the class object
+const OBJ_HEADER_SIZE: usize = 2;
+const OBJ_VTABLE_IDX: usize = 0;
+const OBJ_CLASS_IDX: usize = 1;
+
+/// Assuming 64bit env
+const BYTES_OF_PTR: usize = 8;
+
 /// CodeGen
 ///
 /// 'hir > 'ictx >= 'run
@@ -36,17 +50,24 @@ pub struct CodeGen<'hir: 'ictx, 'run, 'ictx: 'run> {
     pub f64_type: inkwell::types::FloatType<'ictx>,
     pub void_type: inkwell::types::VoidType<'ictx>,
     pub llvm_struct_types: HashMap<ClassFullname, inkwell::types::StructType<'ictx>>,
+    vtables: VTables,
     str_literals: &'hir Vec<String>,
     /// Toplevel `self`
     the_main: Option<inkwell::values::BasicValueEnum<'ictx>>,
+    /// Whether to raise OverflowError on Int overflow
+    pub checked_arithmetic: bool,
 }
 
 /// Compile hir and dump it to `outpath`
-pub fn run(hir: &Hir, outpath: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn run(
+    hir: &Hir,
+    outpath: &str,
+    checked_arithmetic: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     let context = inkwell::context::Context::create();
     let module = context.create_module("main");
     let builder = context.create_builder();
-    let mut code_gen = CodeGen::new(&hir, &context, &module, &builder);
+    let mut code_gen = CodeGen::new(&hir, &context, &module, &builder, checked_arithmetic);
     code_gen.gen_program(&hir)?;
     code_gen.module.print_to_file(outpath)?;
     Ok(())
@@ -58,6 +79,7 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         context: &'ictx inkwell::context::Context,
         module: &'run inkwell::module::Module<'ictx>,
         builder: &'run inkwell::builder::Builder<'ictx>,
+        checked_arithmetic: bool,
     ) -> CodeGen<'hir, 'run, 'ictx> {
         CodeGen {
             context,
@@ -71,8 +93,10 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             f64_type: context.f64_type(),
             void_type: context.void_type(),
             llvm_struct_types: HashMap::new(),
+            vtables: VTables::build(&hir.sk_classes),
             str_literals: &hir.str_literals,
             the_main: None,
+            checked_arithmetic,
         }
     }
 
@@ -82,9 +106,12 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         self.gen_string_literals(&hir.str_literals);
         self.gen_constant_ptrs(&hir.constants);
         self.gen_method_funcs(&hir.sk_methods);
+        self.gen_interface_funcs(&hir.sk_classes);
+        self.gen_vtables();
+        self.gen_interface_dispatchers(&hir.sk_classes);
         self.gen_methods(&hir.sk_methods)?;
         self.gen_const_inits(&hir.const_inits)?;
-        self.gen_user_main(&hir.main_exprs)?;
+        self.gen_user_main(&hir.main_exprs, &hir.main_captured_lvars)?;
         self.gen_main()?;
         Ok(())
     }
@@ -96,6 +123,17 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         self.module.add_function("printf", fn_type, None);
         let fn_type = self.i32_type.fn_type(&[self.i8ptr_type.into()], false);
         self.module.add_function("puts", fn_type, None);
+        let fn_type = self.i32_type.fn_type(
+            &[
+                self.i8ptr_type.into(),
+                self.i64_type.into(),
+                self.i8ptr_type.into(),
+            ],
+            true,
+        );
+        self.module.add_function("snprintf", fn_type, None);
+        let fn_type = self.void_type.fn_type(&[self.i32_type.into()], false);
+        self.module.add_function("exit", fn_type, None);
 
         let fn_type = self.void_type.fn_type(&[], false);
         self.module.add_function("GC_init", fn_type, None);
@@ -117,7 +155,23 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         );
         self.module
             .add_function("llvm.memcpy.p0i8.p0i8.i64", fn_type, None);
+        let result_type = self
+            .context
+            .struct_type(&[self.i64_type.into(), self.i1_type.into()], false);
+        let fn_type = result_type.fn_type(&[self.i64_type.into(), self.i64_type.into()], false);
+        for op in &["sadd", "ssub", "smul"] {
+            self.module
+                .add_function(&format!("llvm.{}.with.overflow.i64", op), fn_type, None);
+        }
 
+        let fn_type = self.f64_type.fn_type(
+            &[
+                self.i8ptr_type.into(),
+                self.i8ptr_type.ptr_type(AddressSpace::Generic).into(),
+            ],
+            false,
+        );
+        self.module.add_function("strtod", fn_type, None);
         let fn_type = self.f64_type.fn_type(&[self.f64_type.into()], false);
         self.module.add_function("sin", fn_type, None);
         let fn_type = self.f64_type.fn_type(&[self.f64_type.into()], false);
@@ -129,16 +183,30 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         let fn_type = self.f64_type.fn_type(&[self.f64_type.into()], false);
         self.module.add_function("floor", fn_type, None);
 
-        let str_type = self.i8_type.array_type(3);
+        let str_type = self.i8_type.array_type(5);
         let global = self.module.add_global(str_type, None, "putd_tmpl");
         global.set_linkage(inkwell::module::Linkage::Internal);
         global.set_initializer(&self.i8_type.const_array(&[
             self.i8_type.const_int(37, false),  // %
+            self.i8_type.const_int(108, false), // l
+            self.i8_type.const_int(108, false), // l
             self.i8_type.const_int(100, false), // d
             self.i8_type.const_int(0, false),
         ]));
         global.set_constant(true);
 
+        let str_type = self.i8_type.array_type(5);
+        let global = self.module.add_global(str_type, None, "print_tmpl");
+        global.set_linkage(inkwell::module::Linkage::Internal);
+        global.set_initializer(&self.i8_type.const_array(&[
+            self.i8_type.const_int(37, false),  // %
+            self.i8_type.const_int(46, false),  // .
+            self.i8_type.const_int(42, false),  // *
+            self.i8_type.const_int(115, false), // s
+            self.i8_type.const_int(0, false),
+        ]));
+        global.set_constant(true);
+
         let str_type = self.i8_type.array_type(3);
         let global = self.module.add_global(str_type, None, "putf_tmpl");
         global.set_linkage(inkwell::module::Linkage::Internal);
@@ -148,9 +216,27 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             self.i8_type.const_int(0, false),
         ]));
         global.set_constant(true);
+
+        let str_type = self.i8_type.array_type(5);
+        let global = self.module.add_global(str_type, None, "pute_tmpl");
+        global.set_linkage(inkwell::module::Linkage::Internal);
+        global.set_initializer(&self.i8_type.const_array(&[
+            self.i8_type.const_int(37, false),  // %
+            self.i8_type.const_int(46, false),  // .
+            self.i8_type.const_int(42, false),  // *
+            self.i8_type.const_int(101, false), // e
+            self.i8_type.const_int(0, false),
+        ]));
+        global.set_constant(true);
+
+        self.gen_exception_declares();
     }
 
-    fn gen_user_main(&mut self, main_exprs: &'hir HirExpressions) -> Result<(), Error> {
+    fn gen_user_main(
+        &mut self,
+        main_exprs: &'hir HirExpressions,
+        captured_lvars: &'hir [String],
+    ) -> Result<(), Error> {
         // define void @user_main()
         let user_main_type = self.void_type.fn_type(&[], false);
         let function = self.module.add_function("user_main", user_main_type, None);
@@ -164,7 +250,7 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
 
         // UserMain:
         self.builder.position_at_end(user_main_block);
-        let mut ctx = CodeGenContext::new(function, FunctionOrigin::Other);
+        let mut ctx = CodeGenContext::new(function, FunctionOrigin::Other, captured_lvars);
         self.gen_exprs(&mut ctx, &main_exprs)?;
         self.builder.build_return(None);
 
@@ -185,24 +271,39 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         let func = self.get_llvm_func("GC_init");
         self.builder.build_call(func, &[], "");
 
+        // Catch the exceptions not rescued
+        let run_block = self.context.append_basic_block(function, "Run");
+        let uncaught_block = self.context.append_basic_block(function, "Uncaught");
+        let (raised, _) = self.build_push_exc_handler(function);
+        self.builder
+            .build_conditional_branch(raised, uncaught_block, run_block);
+
+        // Run:
         // Call init_constants, user_main
+        self.builder.position_at_end(run_block);
         let func = self.get_llvm_func("init_constants");
         self.builder.build_call(func, &[], "");
         let func = self.get_llvm_func("user_main");
         self.builder.build_call(func, &[], "");
-
         // ret i32 0
         self.builder
             .build_return(Some(&self.i32_type.const_int(0, false)));
+
+        // Uncaught:
+        self.builder.position_at_end(uncaught_block);
+        self.build_report_uncaught_exception();
+        // ret i32 1
+        self.builder
+            .build_return(Some(&self.i32_type.const_int(1, false)));
         Ok(())
     }
 
     /// Create llvm functions for lambdas
-    fn gen_lambda_funcs(&mut self, ctx: &mut CodeGenContext<'hir, 'run>) -> Result<(), Error> {
-        // We need a queue because a lambda may have another lambda inside
+    /// (Lambdas inside them are created recursively)
+    fn gen_lambda_funcs(&self, ctx: &mut CodeGenContext<'hir, 'run>) -> Result<(), Error> {
         while let Some(l) = ctx.lambdas.pop_front() {
             let ret_ty = &l.exprs.ty;
-            self.gen_llvm_func_body(&l.func_name, l.params, Right(l.exprs), &ret_ty)?;
+            self.gen_llvm_func_body(&l.func_name, l.params, Right(&l), &ret_ty)?;
         }
         Ok(())
     }
@@ -218,16 +319,23 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         // 2. Set ivars
         for (name, sk_class) in classes {
             let struct_type = self.llvm_struct_types.get(&name).unwrap();
+            let mut fields = self.llvm_header_types();
             if name.0 == "Int" {
-                struct_type.set_body(&[self.i32_type.into()], false);
+                fields.push(self.i64_type.into());
             } else if name.0 == "Float" {
-                struct_type.set_body(&[self.f64_type.into()], false);
+                fields.push(self.f64_type.into());
             } else {
-                struct_type.set_body(&self.llvm_field_types(&sk_class.ivars), false);
+                fields.append(&mut self.llvm_field_types(&sk_class.ivars));
             }
+            struct_type.set_body(&fields, false);
         }
     }
 
+    /// Types of the object header (vtable and class)
+    fn llvm_header_types(&self) -> Vec<inkwell::types::BasicTypeEnum<'ictx>> {
+        vec![self.i8ptr_type.into(), self.i8ptr_type.into()]
+    }
+
     fn llvm_field_types(
         &self,
         ivars: &HashMap<String, SkIVar>,
@@ -240,6 +348,108 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             .collect::<Vec<_>>()
     }
 
+    /// Generate llvm constants for vtables
+    /// (Must be called after `gen_method_funcs`)
+    fn gen_vtables(&self) {
+        for (class_fullname, vtable) in self.vtables.iter() {
+            let null = self.i8ptr_type.const_null();
+            let fnptrs = vtable
+                .fullnames
+                .iter()
+                .map(|name| match self.module.get_function(&name.full_name) {
+                    Some(func) => func
+                        .as_global_value()
+                        .as_pointer_value()
+                        .const_cast(self.i8ptr_type),
+                    // eg. `Meta:Int#new` is never defined
+                    None => null,
+                })
+                .collect::<Vec<_>>();
+            let ary_type = self.i8ptr_type.array_type(vtable.size() as u32);
+            let global =
+                self.module
+                    .add_global(ary_type, None, &format!("vtable_{}", class_fullname.0));
+            global.set_linkage(inkwell::module::Linkage::Internal);
+            global.set_initializer(&self.i8ptr_type.const_array(&fnptrs));
+            global.set_constant(true);
+        }
+    }
+
+    /// Create llvm functions for the methods of interfaces
+    fn gen_interface_funcs(&self, classes: &HashMap<ClassFullname, SkClass>) {
+        for interface in classes.values().filter(|c| c.is_interface) {
+            let self_ty = &interface.instance_ty;
+            for sig in interface.method_sigs.values() {
+                let func_type = self.method_llvm_func_type(self_ty, &sig.replace_self(self_ty));
+                self.module
+                    .add_function(&sig.fullname.full_name, func_type, None);
+            }
+        }
+    }
+
+    /// Generate the body of the interface methods, which call the method
+    /// of the receiver's class
+    /// (Must be called after `gen_vtables`)
+    fn gen_interface_dispatchers(&self, classes: &HashMap<ClassFullname, SkClass>) {
+        for interface in classes.values().filter(|c| c.is_interface) {
+            let implementors = implementors_of(classes, &interface.fullname);
+            for sig in interface.method_sigs.values() {
+                self.gen_interface_dispatcher(&sig.fullname, &implementors);
+            }
+        }
+    }
+
+    /// Generate an interface method which compares the vtable of the
+    /// receiver with the ones of the implementors
+    fn gen_interface_dispatcher(
+        &self,
+        method_fullname: &MethodFullname,
+        implementors: &[ClassFullname],
+    ) {
+        let function = self.get_llvm_func(&method_fullname.full_name);
+        let basic_block = self.context.append_basic_block(function, "");
+        self.builder.position_at_end(basic_block);
+        let receiver = function.get_nth_param(0).unwrap();
+        inkwell_set_name(receiver, "self");
+
+        for cls in implementors {
+            let vtable = self
+                .vtables
+                .get(cls)
+                .unwrap_or_else(|| panic!("[BUG] vtable of `{}' not found", cls));
+            let idx = vtable
+                .get(&method_fullname.first_name)
+                .unwrap_or_else(|| panic!("[BUG] `{}' not found in {}", method_fullname, cls));
+            let impl_func = self.get_llvm_func(&vtable.fullnames[idx].full_name);
+
+            let then_block = self.context.append_basic_block(function, &cls.0);
+            let else_block = self.context.append_basic_block(function, "");
+            let matched = self.build_class_check(receiver, &[cls.clone()]);
+            self.builder
+                .build_conditional_branch(matched, then_block, else_block);
+
+            self.builder.position_at_end(then_block);
+            let args = self.build_coerce_args(function.get_params(), impl_func.get_type());
+            let result = self
+                .builder
+                .build_call(impl_func, &args, "result")
+                .try_as_basic_value()
+                .left();
+            match (function.get_type().get_return_type(), result) {
+                (Some(ret_type), Some(value)) => {
+                    let value = self.build_coerce(value, ret_type);
+                    self.builder.build_return(Some(&value));
+                }
+                _ => {
+                    self.builder.build_return(None);
+                }
+            }
+            self.builder.position_at_end(else_block);
+        }
+        // The receiver is always an instance of one of the implementors
+        self.builder.build_unreachable();
+    }
+
     /// Generate llvm constants for string literals
     fn gen_string_literals(&self, str_literals: &[String]) {
         str_literals.iter().enumerate().for_each(|(i, s)| {
@@ -280,7 +490,17 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         let basic_block = self.context.append_basic_block(function, "");
         self.builder.position_at_end(basic_block);
 
-        let mut ctx = CodeGenContext::new(function, FunctionOrigin::Other);
+        // Create class objects first so that objects created afterwards
+        // (eg. the class names) can refer their class
+        for expr in const_inits {
+            if let HirExpressionBase::HirConstAssign { rhs, .. } = &expr.node {
+                if let HirExpressionBase::HirClassLiteral { fullname, .. } = &rhs.node {
+                    self.gen_class_obj(fullname);
+                }
+            }
+        }
+
+        let mut ctx = CodeGenContext::new(function, FunctionOrigin::Other, &[]);
         for expr in const_inits {
             self.gen_expr(&mut ctx, &expr)?;
         }
@@ -295,6 +515,7 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         self.builder.build_store(ptr, value);
 
         self.builder.build_return(None);
+        self.gen_lambda_funcs(&mut ctx)?;
         Ok(())
     }
 
@@ -319,6 +540,17 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         self.llvm_func_type(Some(self_ty), &signature.params, &signature.ret_ty)
     }
 
+    /// Return llvm funcion type of lambdas
+    /// Params and the return value are passed as Object so that
+    /// `FnN#call` can call any lambda with `arity` params
+    pub fn lambda_llvm_func_type(&self, arity: usize) -> inkwell::types::FunctionType<'ictx> {
+        let obj_type = self.llvm_type(&ty::raw("Object"));
+        let mut arg_types = vec![obj_type; arity];
+        // The first argument is the freevars
+        arg_types.insert(0, self.i8ptr_type.as_basic_type_enum());
+        obj_type.fn_type(&arg_types, false)
+    }
+
     /// Return llvm funcion type
     fn llvm_func_type(
         &self,
@@ -370,7 +602,7 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         &self,
         func_name: &str,
         params: &[MethodParam],
-        body: Either<&'hir SkMethodBody, &'hir HirExpressions>,
+        body: Either<&'hir SkMethodBody, &CodeGenLambda<'hir, 'run>>,
         ret_ty: &TermTy,
     ) -> Result<(), Error> {
         // LLVM function
@@ -379,7 +611,8 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         // Set param names
         for (i, param) in function.get_param_iter().enumerate() {
             if i == 0 {
-                inkwell_set_name(param, "self")
+                let name = if body.is_left() { "self" } else { "freevars" };
+                inkwell_set_name(param, name)
             } else {
                 inkwell_set_name(param, &params[i - 1].name)
             }
@@ -394,20 +627,19 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             Left(method_body) => match method_body {
                 SkMethodBody::RustMethodBody { gen } => gen(self, &function)?,
                 SkMethodBody::RustClosureMethodBody { boxed_gen } => boxed_gen(self, &function)?,
-                SkMethodBody::ShiikaMethodBody { exprs } => self.gen_shiika_method_body(
-                    function,
-                    FunctionOrigin::Method,
-                    ret_ty.is_void_type(),
-                    &exprs,
-                )?,
+                SkMethodBody::ShiikaMethodBody {
+                    exprs,
+                    captured_lvars,
+                } => {
+                    let ctx = CodeGenContext::new(function, FunctionOrigin::Method, captured_lvars);
+                    self.gen_shiika_method_body(ctx, ret_ty.is_void_type(), &exprs)?
+                }
             },
-            Right(exprs) => {
-                self.gen_shiika_method_body(
-                    function,
-                    FunctionOrigin::Lambda,
-                    ret_ty.is_void_type(),
-                    &exprs,
-                )?;
+            Right(l) => {
+                let mut ctx =
+                    CodeGenContext::new(function, FunctionOrigin::Lambda, l.captured_lvars);
+                ctx.lambda_self_type = Some(l.self_type);
+                self.gen_lambda_body(ctx, l.exprs)?;
             }
         }
         Ok(())
@@ -415,19 +647,77 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
 
     fn gen_shiika_method_body(
         &self,
-        function: inkwell::values::FunctionValue<'run>,
-        function_origin: code_gen_context::FunctionOrigin,
+        mut ctx: CodeGenContext<'hir, 'run>,
         void_method: bool,
         exprs: &'hir HirExpressions,
     ) -> Result<(), Error> {
-        let mut ctx = CodeGenContext::new(function, function_origin);
         let last_value = self.gen_exprs(&mut ctx, exprs)?;
         if void_method {
             self.builder.build_return(None);
+        } else if exprs.ty.is_never_type() {
+            self.builder.build_unreachable();
         } else {
             self.builder.build_return(Some(&last_value));
         }
-        Ok(())
+        self.gen_lambda_funcs(&mut ctx)
+    }
+
+    /// Generate body of a lambda (see also: lambda_llvm_func_type)
+    fn gen_lambda_body(
+        &self,
+        mut ctx: CodeGenContext<'hir, 'run>,
+        exprs: &'hir HirExpressions,
+    ) -> Result<(), Error> {
+        let last_value = self.gen_exprs(&mut ctx, exprs)?;
+        // Check the last expr too because the type of the body may be
+        // decided by `return` (eg. `fn(){ return 1 }`)
+        let last_expr = exprs.exprs.last().unwrap();
+        if exprs.ty.is_never_type() || last_expr.ty.is_never_type() {
+            self.builder.build_unreachable();
+            return self.gen_lambda_funcs(&mut ctx);
+        }
+        let value = if exprs.ty.is_void_type() {
+            self.gen_const_ref(&const_fullname("::void"))
+        } else {
+            last_value
+        };
+        let obj = self.build_coerce(value, self.llvm_type(&ty::raw("Object")));
+        self.builder.build_return(Some(&obj));
+        self.gen_lambda_funcs(&mut ctx)
+    }
+
+    /// Load the class object of `object` from its header
+    pub fn build_class_load<'a>(
+        &'a self,
+        object: inkwell::values::BasicValueEnum<'a>,
+    ) -> inkwell::values::BasicValueEnum<'a> {
+        let cls_type = self.llvm_type(&ty::raw("Class"));
+        self.build_bool_or_obj(
+            object,
+            cls_type,
+            || {
+                // The class object of Bool
+                let global = self
+                    .module
+                    .get_global("::Bool")
+                    .expect("[BUG] constant `::Bool' not created")
+                    .as_pointer_value();
+                let cls = self.builder.build_load(global, "class");
+                self.builder.build_bitcast(cls, cls_type, "cls")
+            },
+            || {
+                let ptr = self
+                    .builder
+                    .build_struct_gep(
+                        object.into_pointer_value(),
+                        OBJ_CLASS_IDX as u32,
+                        "addr_class",
+                    )
+                    .unwrap();
+                let cls_i8 = self.builder.build_load(ptr, "class");
+                self.builder.build_bitcast(cls_i8, cls_type, "cls")
+            },
+        )
     }
 
     pub fn build_ivar_load<'a>(
@@ -440,7 +730,7 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             .builder
             .build_struct_gep(
                 object.into_pointer_value(),
-                idx as u32,
+                (OBJ_HEADER_SIZE + idx) as u32,
                 &format!("addr_{}", name),
             )
             .unwrap();
@@ -458,7 +748,7 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             .builder
             .build_struct_gep(
                 object.into_pointer_value(),
-                idx as u32,
+                (OBJ_HEADER_SIZE + idx) as u32,
                 &format!("addr_{}", name),
             )
             .unwrap();
@@ -467,6 +757,34 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
 }
 
 // Question: is there a better way to do this?
+/// Return the classes which implement the interface (directly or via
+/// their superclass)
+fn implementors_of(
+    classes: &HashMap<ClassFullname, SkClass>,
+    interface: &ClassFullname,
+) -> Vec<ClassFullname> {
+    let mut v = classes
+        .values()
+        .filter(|cls| !cls.is_module && !cls.is_interface && !cls.is_abstract)
+        .filter(|cls| {
+            let mut c = Some(*cls);
+            while let Some(cls) = c {
+                if cls.interfaces.contains(interface) {
+                    return true;
+                }
+                c = cls
+                    .superclass_fullname
+                    .as_ref()
+                    .and_then(|name| classes.get(name));
+            }
+            false
+        })
+        .map(|cls| cls.fullname.clone())
+        .collect::<Vec<_>>();
+    v.sort_by(|a, b| a.0.cmp(&b.0));
+    v
+}
+
 fn inkwell_set_name(val: BasicValueEnum, name: &str) {
     match val {
         BasicValueEnum::ArrayValue(v) => v.set_name(name),
@@ -1,5 +1,8 @@
 /// Provides utility functions used by code_gen/*.rs
 use crate::code_gen::*;
+use crate::names::*;
+use crate::ty;
+use inkwell::AddressSpace;
 
 impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     /// Return the llvm func
@@ -9,4 +12,459 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             .get_function(name)
             .unwrap_or_else(|| panic!("[BUG] get_llvm_func: `{:?}' not found", name))
     }
+
+    /// Format `value` with the printf template `tmpl_name` (eg. `putd_tmpl`)
+    /// and return the result as a Shiika String
+    pub fn build_formatted_string(
+        &self,
+        tmpl_name: &str,
+        value: inkwell::values::BasicValueEnum,
+    ) -> inkwell::values::BasicValueEnum {
+        let tmpl = self
+            .module
+            .get_global(tmpl_name)
+            .unwrap_or_else(|| panic!("[BUG] template `{}' not found", tmpl_name))
+            .as_pointer_value();
+        let tmpl_ptr = unsafe {
+            tmpl.const_in_bounds_gep(&[
+                self.i32_type.const_int(0, false),
+                self.i32_type.const_int(0, false),
+            ])
+        };
+        let snprintf = self.get_llvm_func("snprintf");
+
+        // Get the length first
+        let len = self
+            .builder
+            .build_call(
+                snprintf,
+                &[
+                    self.i8ptr_type.const_null().into(),
+                    self.i64_type.const_int(0, false).into(),
+                    tmpl_ptr.into(),
+                    value,
+                ],
+                "len",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let len = self
+            .builder
+            .build_int_z_extend(len, self.i64_type, "len_64");
+        // +1 for the last null
+        let size = self
+            .builder
+            .build_int_add(len, self.i64_type.const_int(1, false), "size");
+        let buf = self
+            .builder
+            .build_call(self.get_llvm_func("GC_malloc"), &[size.into()], "buf")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        self.builder
+            .build_call(snprintf, &[buf, size.into(), tmpl_ptr.into(), value], "");
+
+        let sk_str = self.allocate_sk_obj(&class_fullname("String"), "str");
+        self.build_ivar_store(&sk_str, 0, buf, "@ptr");
+        let bytesize = self.box_int(&len);
+        self.build_ivar_store(&sk_str, 1, bytesize, "@bytesize");
+        sk_str
+    }
+
+    /// Return the shortest string in the form of `%e` which is read back
+    /// as `float` by strtod (eg. "1.5e+00"), as a Shiika String
+    pub fn build_shortest_exp_string(
+        &self,
+        function: &inkwell::values::FunctionValue,
+        float: inkwell::values::FloatValue,
+    ) -> inkwell::values::BasicValueEnum {
+        let tmpl = self
+            .module
+            .get_global("pute_tmpl")
+            .unwrap()
+            .as_pointer_value();
+        let tmpl_ptr = unsafe {
+            tmpl.const_in_bounds_gep(&[
+                self.i32_type.const_int(0, false),
+                self.i32_type.const_int(0, false),
+            ])
+        };
+        // Long enough for "-d.ddddddddddddddddde+ddd"
+        let size = self.i64_type.const_int(32, false);
+        let buf = self
+            .builder
+            .build_call(self.get_llvm_func("GC_malloc"), &[size.into()], "buf")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        let prec_ptr = self.builder.build_alloca(self.i32_type, "prec");
+        self.builder
+            .build_store(prec_ptr, self.i32_type.const_int(0, false));
+        let loop_block = self.context.append_basic_block(*function, "Loop");
+        let end_block = self.context.append_basic_block(*function, "End");
+        self.builder.build_unconditional_branch(loop_block);
+
+        // Loop: Try from the shortest (17 digits are always enough)
+        self.builder.position_at_end(loop_block);
+        let prec = self.builder.build_load(prec_ptr, "prec").into_int_value();
+        let len = self
+            .builder
+            .build_call(
+                self.get_llvm_func("snprintf"),
+                &[buf, size.into(), tmpl_ptr.into(), prec.into(), float.into()],
+                "len",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let parsed = self
+            .builder
+            .build_call(
+                self.get_llvm_func("strtod"),
+                &[
+                    buf,
+                    self.i8ptr_type
+                        .ptr_type(AddressSpace::Generic)
+                        .const_null()
+                        .into(),
+                ],
+                "parsed",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_float_value();
+        let same =
+            self.builder
+                .build_float_compare(inkwell::FloatPredicate::OEQ, parsed, float, "same");
+        let is_last = self.builder.build_int_compare(
+            inkwell::IntPredicate::SGE,
+            prec,
+            self.i32_type.const_int(16, false),
+            "is_last",
+        );
+        let done = self.builder.build_or(same, is_last, "done");
+        let next_prec =
+            self.builder
+                .build_int_add(prec, self.i32_type.const_int(1, false), "next_prec");
+        self.builder.build_store(prec_ptr, next_prec);
+        self.builder
+            .build_conditional_branch(done, end_block, loop_block);
+
+        // End:
+        self.builder.position_at_end(end_block);
+        let len = self
+            .builder
+            .build_int_z_extend(len, self.i64_type, "len_64");
+        let sk_str = self.allocate_sk_obj(&class_fullname("String"), "str");
+        self.build_ivar_store(&sk_str, 0, buf, "@ptr");
+        let bytesize = self.box_int(&len);
+        self.build_ivar_store(&sk_str, 1, bytesize, "@bytesize");
+        sk_str
+    }
+
+    /// Build an arithmetic operation on two i64s (`op` is one of "add",
+    /// "sub", "mul", "div", "rem" and "shl".)
+    /// When `checked_arithmetic` is on, call `Int#_overflow` of `receiver`
+    /// if the result does not fit in i64 and `Int#_zero_division` if
+    /// divided by zero
+    pub fn build_int_arith<'a>(
+        &'a self,
+        function: &inkwell::values::FunctionValue<'a>,
+        op: &str,
+        receiver: inkwell::values::BasicValueEnum<'a>,
+        lhs: inkwell::values::IntValue<'a>,
+        rhs: inkwell::values::IntValue<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        if !self.checked_arithmetic {
+            return match op {
+                "add" => self.builder.build_int_add(lhs, rhs, "add"),
+                "sub" => self.builder.build_int_sub(lhs, rhs, "sub"),
+                "mul" => self.builder.build_int_mul(lhs, rhs, "mul"),
+                "div" => self.builder.build_int_signed_div(lhs, rhs, "div"),
+                "rem" => self.builder.build_int_signed_rem(lhs, rhs, "rem"),
+                "shl" => self.builder.build_left_shift(lhs, rhs, "lshift"),
+                _ => panic!("[BUG] build_int_arith: unknown op {}", op),
+            };
+        }
+        match op {
+            "div" | "rem" => self.build_checked_int_div(function, op, receiver, lhs, rhs),
+            "shl" => self.build_checked_int_shl(function, receiver, lhs, rhs),
+            _ => self.build_checked_int_arith(function, op, receiver, lhs, rhs),
+        }
+    }
+
+    /// Build `+`, `-` or `*` with `llvm.s*.with.overflow`
+    fn build_checked_int_arith<'a>(
+        &'a self,
+        function: &inkwell::values::FunctionValue<'a>,
+        op: &str,
+        receiver: inkwell::values::BasicValueEnum<'a>,
+        lhs: inkwell::values::IntValue<'a>,
+        rhs: inkwell::values::IntValue<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        let intrinsic = self.get_llvm_func(&format!("llvm.s{}.with.overflow.i64", op));
+        let result = self
+            .builder
+            .build_call(intrinsic, &[lhs.into(), rhs.into()], "result")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let value = self
+            .builder
+            .build_extract_value(result, 0, op)
+            .unwrap()
+            .into_int_value();
+        let overflow = self
+            .builder
+            .build_extract_value(result, 1, "overflow")
+            .unwrap()
+            .into_int_value();
+        self.build_int_error_if(function, overflow, "Int#_overflow", receiver);
+        value
+    }
+
+    /// Build `sdiv` or `srem`. Division by zero and `MIN / -1` (which
+    /// overflows) are undefined in LLVM, so they are checked beforehand
+    fn build_checked_int_div<'a>(
+        &'a self,
+        function: &inkwell::values::FunctionValue<'a>,
+        op: &str,
+        receiver: inkwell::values::BasicValueEnum<'a>,
+        lhs: inkwell::values::IntValue<'a>,
+        rhs: inkwell::values::IntValue<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        let zero = self.i64_type.const_int(0, false);
+        let minus_one = self.i64_type.const_int(-1_i64 as u64, true);
+        let is_zero =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::EQ, rhs, zero, "is_zero");
+        self.build_int_error_if(function, is_zero, "Int#_zero_division", receiver);
+        let is_minus_one = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            rhs,
+            minus_one,
+            "is_minus_one",
+        );
+        if op == "div" {
+            let min = self.i64_type.const_int(i64::min_value() as u64, true);
+            let is_min =
+                self.builder
+                    .build_int_compare(inkwell::IntPredicate::EQ, lhs, min, "is_min");
+            let overflow = self.builder.build_and(is_min, is_minus_one, "overflow");
+            self.build_int_error_if(function, overflow, "Int#_overflow", receiver);
+            self.builder.build_int_signed_div(lhs, rhs, "div")
+        } else {
+            // `x.reminder(-1)` is always 0 (even for `MIN`)
+            let one = self.i64_type.const_int(1, false);
+            let divisor = self
+                .builder
+                .build_select(is_minus_one, one, rhs, "divisor")
+                .into_int_value();
+            self.builder.build_int_signed_rem(lhs, divisor, "rem")
+        }
+    }
+
+    /// Build `shl`. It overflows if the shift amount is not in `0...64` or
+    /// some of the bits are lost (including the sign bit)
+    fn build_checked_int_shl<'a>(
+        &'a self,
+        function: &inkwell::values::FunctionValue<'a>,
+        receiver: inkwell::values::BasicValueEnum<'a>,
+        lhs: inkwell::values::IntValue<'a>,
+        rhs: inkwell::values::IntValue<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        let zero = self.i64_type.const_int(0, false);
+        let bits = self.i64_type.const_int(64, false);
+        // (Negative amounts are also out of range when compared as unsigned)
+        let out_of_range =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::UGE, rhs, bits, "out_of_range");
+        let amount = self
+            .builder
+            .build_select(out_of_range, zero, rhs, "amount")
+            .into_int_value();
+        let shifted = self.builder.build_left_shift(lhs, amount, "lshift");
+        let restored = self
+            .builder
+            .build_right_shift(shifted, amount, true, "restored");
+        let lost = self
+            .builder
+            .build_int_compare(inkwell::IntPredicate::NE, restored, lhs, "lost");
+        let overflow = self.builder.build_or(out_of_range, lost, "overflow");
+        self.build_int_error_if(function, overflow, "Int#_overflow", receiver);
+        shifted
+    }
+
+    /// Build a branch which calls `method_name` (a method of Int which
+    /// never returns) on `receiver` if `cond` is true
+    fn build_int_error_if<'a>(
+        &'a self,
+        function: &inkwell::values::FunctionValue<'a>,
+        cond: inkwell::values::IntValue<'a>,
+        method_name: &str,
+        receiver: inkwell::values::BasicValueEnum<'a>,
+    ) {
+        let error_block = self.context.append_basic_block(*function, "IntError");
+        let ok_block = self.context.append_basic_block(*function, "IntOk");
+        self.builder
+            .build_conditional_branch(cond, error_block, ok_block);
+        // IntError:
+        self.builder.position_at_end(error_block);
+        self.builder
+            .build_call(self.get_llvm_func(method_name), &[receiver], "");
+        self.builder.build_unreachable();
+        // IntOk:
+        self.builder.position_at_end(ok_block);
+    }
+
+    /// Build alloca at the beginning of the function so that it is not
+    /// repeated in a loop
+    pub(super) fn build_entry_alloca(
+        &self,
+        function: inkwell::values::FunctionValue<'run>,
+        ty: inkwell::types::BasicTypeEnum<'run>,
+        name: &str,
+    ) -> inkwell::values::PointerValue<'run> {
+        let builder = self.context.create_builder();
+        let entry = function
+            .get_first_basic_block()
+            .expect("[BUG] function has no basic block");
+        match entry.get_first_instruction() {
+            Some(inst) => builder.position_before(&inst),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name)
+    }
+
+    /// Return true if the class of `obj` is one of `class_fullnames`
+    /// (Compares the vtable since classes do not know their superclass
+    /// at runtime)
+    pub fn build_class_check<'a>(
+        &'a self,
+        obj: inkwell::values::BasicValueEnum<'a>,
+        class_fullnames: &[ClassFullname],
+    ) -> inkwell::values::IntValue<'a> {
+        let obj = self.build_coerce(obj, self.llvm_type(&ty::raw("Object")));
+        let vtable = self.build_vtable_load(obj).into_pointer_value();
+        class_fullnames
+            .iter()
+            .fold(self.i1_type.const_int(0, false), |acc, name| {
+                let expected = self
+                    .module
+                    .get_global(&format!("vtable_{}", name.0))
+                    .unwrap_or_else(|| panic!("[BUG] vtable of `{}' not created", name.0))
+                    .as_pointer_value();
+                let expected_i8 = self
+                    .builder
+                    .build_bitcast(expected, self.i8ptr_type, "expected");
+                let eq = self.builder.build_int_compare(
+                    inkwell::IntPredicate::EQ,
+                    self.builder
+                        .build_ptr_to_int(vtable, self.i64_type, "vtable_addr"),
+                    self.builder.build_ptr_to_int(
+                        expected_i8.into_pointer_value(),
+                        self.i64_type,
+                        "expected_addr",
+                    ),
+                    "eq",
+                );
+                self.builder.build_or(acc, eq, "matched")
+            })
+    }
+
+    /// Build `==` which is true only for the instances of `class_name`
+    /// (eg. `Int#==` takes an Object but is false unless it is an Int).
+    /// `compare` is called with `other` casted to the class
+    pub fn build_eq_same_class<'a>(
+        &'a self,
+        class_name: &str,
+        other: inkwell::values::BasicValueEnum<'a>,
+        compare: impl FnOnce(inkwell::values::BasicValueEnum<'a>) -> inkwell::values::IntValue<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        let is_same = self.build_class_check(other, &[class_fullname(class_name)]);
+        let function = self
+            .builder
+            .get_insert_block()
+            .and_then(|b| b.get_parent())
+            .expect("[BUG] not in a function");
+        let same_block = self.context.append_basic_block(function, "SameClass");
+        let other_block = self.context.append_basic_block(function, "OtherClass");
+        let merge_block = self.context.append_basic_block(function, "EqMerge");
+        self.builder
+            .build_conditional_branch(is_same, same_block, other_block);
+
+        self.builder.position_at_end(same_block);
+        let casted =
+            self.builder
+                .build_bitcast(other, self.llvm_type(&ty::raw(class_name)), "casted");
+        let eq = compare(casted);
+        let same_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(other_block);
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(merge_block);
+        let phi = self.builder.build_phi(self.i1_type, "eq");
+        phi.add_incoming(&[
+            (&eq, same_end),
+            (&self.i1_type.const_int(0, false), other_block),
+        ]);
+        phi.as_basic_value().into_int_value()
+    }
+
+    /// Return `bool_value()` if `obj` is a Bool upcasted to Object (which is
+    /// not a pointer but a tagged value; see boxing.rs) and `obj_value()`
+    /// otherwise
+    pub(super) fn build_bool_or_obj<'a>(
+        &'a self,
+        obj: inkwell::values::BasicValueEnum<'a>,
+        ty: inkwell::types::BasicTypeEnum<'a>,
+        bool_value: impl FnOnce() -> inkwell::values::BasicValueEnum<'a>,
+        obj_value: impl FnOnce() -> inkwell::values::BasicValueEnum<'a>,
+    ) -> inkwell::values::BasicValueEnum<'a> {
+        let function = self
+            .builder
+            .get_insert_block()
+            .and_then(|b| b.get_parent())
+            .expect("[BUG] not in a function");
+        let obj_block = self.context.append_basic_block(function, "TagObj");
+        let bool_block = self.context.append_basic_block(function, "TagBool");
+        let merge_block = self.context.append_basic_block(function, "TagMerge");
+
+        let addr = self
+            .builder
+            .build_ptr_to_int(obj.into_pointer_value(), self.i64_type, "addr");
+        let tag = self
+            .builder
+            .build_and(addr, self.i64_type.const_int(0b10, false), "tag");
+        let is_bool = self.builder.build_int_compare(
+            inkwell::IntPredicate::NE,
+            tag,
+            self.i64_type.const_int(0, false),
+            "is_bool",
+        );
+        self.builder
+            .build_conditional_branch(is_bool, bool_block, obj_block);
+
+        self.builder.position_at_end(obj_block);
+        let obj_v = obj_value();
+        let obj_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(bool_block);
+        let bool_v = bool_value();
+        let bool_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(merge_block);
+        let phi = self.builder.build_phi(ty, "bool_or_obj");
+        phi.add_incoming(&[(&obj_v, obj_end), (&bool_v, bool_end)]);
+        phi.as_basic_value()
+    }
 }
@@ -0,0 +1,128 @@
+use crate::hir::*;
+use crate::names::*;
+use std::collections::HashMap;
+
+/// Method table of a class
+///
+/// A subclass inherits the layout of its superclass' vtable, so a method
+/// has the same index in every vtable of the class hierarchy.
+#[derive(Debug, PartialEq)]
+pub struct VTable {
+    /// Implementation of each slot
+    pub fullnames: Vec<MethodFullname>,
+    /// Index of each method
+    index: HashMap<MethodFirstname, usize>,
+}
+
+impl VTable {
+    fn null() -> VTable {
+        VTable {
+            fullnames: vec![],
+            index: HashMap::new(),
+        }
+    }
+
+    /// Create a vtable of a class by extending the one of its superclass
+    /// (Methods of the included modules are placed before the class' own
+    /// methods, so that the class can override them)
+    fn build(super_vtable: &VTable, modules: &[&SkClass], class: &SkClass) -> VTable {
+        let mut vtable = VTable {
+            fullnames: super_vtable.fullnames.clone(),
+            index: super_vtable.index.clone(),
+        };
+        for module in modules {
+            vtable.add_methods(module);
+        }
+        vtable.add_methods(class);
+        vtable
+    }
+
+    /// Add (or override) the slots for the methods of `class`
+    fn add_methods(&mut self, class: &SkClass) {
+        // Sort to make the layout deterministic
+        let mut sigs = class.method_sigs.values().collect::<Vec<_>>();
+        sigs.sort_by(|a, b| a.fullname.full_name.cmp(&b.fullname.full_name));
+        for sig in sigs {
+            let first_name = sig.first_name();
+            match self.index.get(first_name) {
+                // Overriding
+                Some(i) => self.fullnames[*i] = sig.fullname.clone(),
+                None => {
+                    self.index.insert(first_name.clone(), self.fullnames.len());
+                    self.fullnames.push(sig.fullname.clone());
+                }
+            }
+        }
+    }
+
+    /// Return the index of the method
+    pub fn get(&self, name: &MethodFirstname) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Number of the slots
+    pub fn size(&self) -> usize {
+        self.fullnames.len()
+    }
+}
+
+/// Vtables of all the classes
+#[derive(Debug, PartialEq)]
+pub struct VTables {
+    vtables: HashMap<ClassFullname, VTable>,
+}
+
+impl VTables {
+    /// Build vtables of the classes
+    /// (Modules and interfaces have no vtable because they have no instances.
+    /// Abstract classes have one because their subclasses extend it)
+    pub fn build(sk_classes: &HashMap<ClassFullname, SkClass>) -> VTables {
+        let mut vtables = HashMap::new();
+        for (name, class) in sk_classes {
+            if !class.is_module && !class.is_interface {
+                VTables::build_vtable(name, sk_classes, &mut vtables);
+            }
+        }
+        VTables { vtables }
+    }
+
+    /// Build the vtable of a class (and its ancestors, if not yet)
+    fn build_vtable(
+        name: &ClassFullname,
+        sk_classes: &HashMap<ClassFullname, SkClass>,
+        vtables: &mut HashMap<ClassFullname, VTable>,
+    ) {
+        if vtables.contains_key(name) {
+            return;
+        }
+        let class = sk_classes
+            .get(name)
+            .unwrap_or_else(|| panic!("[BUG] VTables::build: class `{}' not found", name));
+        let modules = class
+            .includes
+            .iter()
+            .map(|module_name| {
+                sk_classes.get(module_name).unwrap_or_else(|| {
+                    panic!("[BUG] VTables::build: module `{}' not found", module_name)
+                })
+            })
+            .collect::<Vec<_>>();
+        let vtable = match &class.superclass_fullname {
+            Some(super_name) => {
+                VTables::build_vtable(super_name, sk_classes, vtables);
+                VTable::build(&vtables[super_name], &modules, class)
+            }
+            None => VTable::build(&VTable::null(), &modules, class),
+        };
+        vtables.insert(name.clone(), vtable);
+    }
+
+    /// Return the vtable of the class
+    pub fn get(&self, name: &ClassFullname) -> Option<&VTable> {
+        self.vtables.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ClassFullname, &VTable)> {
+        self.vtables.iter()
+    }
+}
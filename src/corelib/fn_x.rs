@@ -1,44 +1,88 @@
+use crate::code_gen::CodeGen;
 use crate::corelib::*;
 use crate::hir::*;
 use crate::ty;
 use inkwell::AddressSpace;
 use std::collections::HashMap;
 
-pub fn create_methods_1() -> Vec<SkMethod> {
-    vec![create_method_generic(
-        "Fn1",
-        "call(arg1: S1) -> T",
-        |code_gen, function| {
-            let receiver = function.get_params()[0];
-            let args = vec![function.get_params()[1]];
-            let ptr = code_gen.build_ivar_load(receiver, 0, "@func");
-
-            let struct_type = code_gen
-                .llvm_struct_types
-                .get(&class_fullname("Object"))
-                .unwrap();
-            let obj_type = struct_type.ptr_type(AddressSpace::Generic);
-            let fntype = obj_type.fn_type(&[obj_type.into()], false);
-            let fnptype = fntype.ptr_type(AddressSpace::Generic);
-
-            let func = code_gen
-                .builder
-                .build_bitcast(ptr, fnptype, "")
-                .into_pointer_value();
-            let result = code_gen
-                .builder
-                .build_call(func, &args, "result")
-                .try_as_basic_value()
-                .left()
-                .unwrap();
-            code_gen.builder.build_return(Some(&result));
-            Ok(())
+/// Classes of lambdas (indexed by arity)
+const FN_CLASS_NAMES: [&str; LAMBDA_MAX_ARITY + 1] = [
+    "Fn0", "Fn1", "Fn2", "Fn3", "Fn4", "Fn5", "Fn6", "Fn7", "Fn8", "Fn9",
+];
+
+/// Create Fn0 .. Fn9
+pub fn fn_items() -> Vec<ClassItem> {
+    FN_CLASS_NAMES
+        .iter()
+        .enumerate()
+        .map(|(arity, name)| {
+            (
+                *name,
+                vec![create_call(name, arity)],
+                vec![],
+                ivars(),
+                typarams(arity),
+            )
+        })
+        .collect()
+}
+
+/// `in S1, in S2, ..., out T`
+fn typarams(arity: usize) -> Vec<ty::TyParam> {
+    let mut typarams = (1..=arity)
+        .map(|i| ty::TyParam {
+            variance: ty::Variance::Contravariant,
+            ..ty::TyParam::new(format!("S{}", i))
+        })
+        .collect::<Vec<_>>();
+    typarams.push(ty::TyParam {
+        variance: ty::Variance::Covariant,
+        ..ty::TyParam::new("T")
+    });
+    typarams
+}
+
+/// Create `FnN#call`, which just calls the llvm func of the lambda
+fn create_call(class_name: &str, arity: usize) -> SkMethod {
+    let params = (1..=arity)
+        .map(|i| format!("arg{}: S{}", i, i))
+        .collect::<Vec<_>>();
+    let sig_str = format!("call({}) -> T", params.join(", "));
+    let sig = parse_signature(class_name, &sig_str, &typarams(arity));
+
+    let call_body = move |code_gen: &CodeGen, function: &inkwell::values::FunctionValue| {
+        let receiver = function.get_params()[0];
+        let ptr = code_gen.build_ivar_load(receiver, 0, "@func");
+        let freevars = code_gen.build_ivar_load(receiver, 1, "@freevars");
+        let mut args = vec![freevars];
+        args.extend(function.get_params().into_iter().skip(1));
+
+        let fnptype = code_gen
+            .lambda_llvm_func_type(arity)
+            .ptr_type(AddressSpace::Generic);
+        let func = code_gen
+            .builder
+            .build_bitcast(ptr, fnptype, "")
+            .into_pointer_value();
+        let result = code_gen
+            .builder
+            .build_call(func, &args, "result")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        code_gen.builder.build_return(Some(&result));
+        Ok(())
+    };
+
+    SkMethod {
+        signature: sig,
+        body: SkMethodBody::RustClosureMethodBody {
+            boxed_gen: Box::new(call_body),
         },
-        &vec!["S1".to_string(), "T".to_string()],
-    )]
+    }
 }
 
-pub fn ivars() -> HashMap<String, SkIVar> {
+fn ivars() -> HashMap<String, SkIVar> {
     let mut ivars = HashMap::new();
     ivars.insert(
         "@func".to_string(),
@@ -54,7 +98,7 @@ pub fn ivars() -> HashMap<String, SkIVar> {
         SkIVar {
             name: "@freevars".to_string(),
             idx: 1,
-            ty: ty::spe("Array", vec![ty::raw("Shiika::Internal::Ptr")]),
+            ty: ty::raw("Shiika::Internal::Ptr"),
             readonly: true,
         },
     );
@@ -30,8 +30,10 @@ impl Corelib {
     }
 
     pub fn create() -> Corelib {
-        let items = rust_body_items();
-        let (sk_classes, sk_methods) = make_classes(items);
+        let (mut sk_classes, mut sk_methods) = make_classes(rust_body_items(), false);
+        let (modules, module_methods) = make_classes(rust_body_modules(), true);
+        sk_classes.extend(modules);
+        sk_methods.extend(module_methods);
         Corelib {
             sk_classes,
             sk_methods,
@@ -44,11 +46,11 @@ type ClassItem = (
     Vec<SkMethod>,
     Vec<SkMethod>,
     HashMap<String, SkIVar>,
-    Vec<String>,
+    Vec<ty::TyParam>,
 );
 
 fn rust_body_items() -> Vec<ClassItem> {
-    vec![
+    let mut items = vec![
         // Classes
         (
             "Bool",
@@ -94,13 +96,6 @@ fn rust_body_items() -> Vec<ClassItem> {
             vec![],
         ),
         ("Class", vec![], vec![], HashMap::new(), vec![]),
-        (
-            "Fn1",
-            fn_x::create_methods_1(),
-            vec![],
-            fn_x::ivars(),
-            vec!["T".to_string()],
-        ),
         (
             "Shiika::Internal::Ptr",
             shiika_internal_ptr::create_methods(),
@@ -108,7 +103,14 @@ fn rust_body_items() -> Vec<ClassItem> {
             HashMap::new(),
             vec![],
         ),
-        // Modules
+    ];
+    // Fn0 .. Fn9
+    items.append(&mut fn_x::fn_items());
+    items
+}
+
+fn rust_body_modules() -> Vec<ClassItem> {
+    vec![
         (
             "Math",
             vec![],
@@ -130,6 +132,7 @@ fn rust_body_items() -> Vec<ClassItem> {
 
 fn make_classes(
     items: Vec<ClassItem>,
+    is_module: bool,
 ) -> (
     HashMap<ClassFullname, SkClass>,
     HashMap<ClassFullname, Vec<SkMethod>>,
@@ -146,11 +149,15 @@ fn make_classes(
             ClassFullname(name.to_string()),
             SkClass {
                 fullname: class_fullname(name),
-                typarams: typarams
-                    .into_iter()
-                    .map(|s| ty::TyParam { name: s })
-                    .collect(),
+                typarams,
                 superclass_fullname: super_name,
+                is_module,
+                is_interface: false,
+                is_abstract: false,
+                includes: vec![],
+                interfaces: vec![],
+                abstract_methods: vec![],
+                enum_cases: None,
                 instance_ty: ty::raw(name),
                 ivars,
                 method_sigs: imethods
@@ -176,6 +183,13 @@ fn make_classes(
                 fullname: metaclass_fullname(name),
                 typarams: vec![],
                 superclass_fullname: Some(class_fullname("Class")),
+                is_module: false,
+                is_interface: false,
+                is_abstract: false,
+                includes: vec![],
+                interfaces: vec![],
+                abstract_methods: vec![],
+                enum_cases: None,
                 instance_ty: ty::meta(name),
                 ivars: meta_ivars,
                 method_sigs: cmethods
@@ -199,19 +213,28 @@ fn create_method_generic(
     class_name: &str,
     sig_str: &str,
     gen: GenMethodBody,
-    typaram_names: &[String],
+    typarams: &[ty::TyParam],
 ) -> SkMethod {
-    let mut parser = parser::Parser::new_with_state(sig_str, parser::lexer::LexerState::MethodName);
-    let (ast_sig, _) = parser.parse_method_signature().unwrap();
-    parser.expect_eof().unwrap();
-    let sig = crate::hir::signature::create_signature(
-        &class_fullname(class_name),
-        &ast_sig,
-        typaram_names,
-    );
-
     SkMethod {
-        signature: sig,
+        signature: parse_signature(class_name, sig_str, typarams),
         body: SkMethodBody::RustMethodBody { gen },
     }
 }
+
+/// Make the method private (eg. a helper of a method in builtin/*.sk)
+fn private(mut method: SkMethod) -> SkMethod {
+    method.signature.visibility = ty::Visibility::Private;
+    method
+}
+
+/// Create a MethodSignature from a string like `foo(x: Int) -> Int`
+fn parse_signature(
+    class_name: &str,
+    sig_str: &str,
+    typarams: &[ty::TyParam],
+) -> ty::MethodSignature {
+    let mut parser = parser::Parser::new_with_state(sig_str, parser::lexer::LexerState::MethodName);
+    let (ast_sig, _) = parser.parse_method_signature().unwrap();
+    parser.expect_eof().unwrap();
+    crate::hir::signature::create_signature(&class_fullname(class_name), &ast_sig, typarams)
+}
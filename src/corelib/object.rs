@@ -8,12 +8,76 @@ pub fn create_methods() -> Vec<SkMethod> {
             code_gen.builder.build_return(None);
             Ok(())
         }),
+        create_method(
+            "Object",
+            "==(other: Object) -> Bool",
+            |code_gen, function| {
+                // Compare the identity
+                let this = code_gen.builder.build_ptr_to_int(
+                    function.get_params()[0].into_pointer_value(),
+                    code_gen.i64_type,
+                    "this",
+                );
+                let that = code_gen.builder.build_ptr_to_int(
+                    function.get_params()[1].into_pointer_value(),
+                    code_gen.i64_type,
+                    "that",
+                );
+                let result =
+                    code_gen
+                        .builder
+                        .build_int_compare(inkwell::IntPredicate::EQ, this, that, "eq");
+                let sk_result = code_gen.box_bool(&result);
+                code_gen.builder.build_return(Some(&sk_result));
+                Ok(())
+            },
+        ),
+        create_method("Object", "hash() -> Int", |code_gen, function| {
+            // Use the address (lower bits are dropped because they are
+            // always zero due to the alignment)
+            let addr = code_gen.builder.build_ptr_to_int(
+                function.get_params()[0].into_pointer_value(),
+                code_gen.i64_type,
+                "addr",
+            );
+            let shifted = code_gen.builder.build_right_shift(
+                addr,
+                code_gen.i64_type.const_int(3, false),
+                false,
+                "shifted",
+            );
+            let sk_int = code_gen.box_int(&shifted);
+            code_gen.builder.build_return(Some(&sk_int));
+            Ok(())
+        }),
+        create_method(
+            "Object",
+            "exit(status: Int) -> Never",
+            |code_gen, function| {
+                let sk_int = function.get_params()[1];
+                let status = code_gen.builder.build_int_truncate(
+                    code_gen.unbox_int(sk_int),
+                    code_gen.i32_type,
+                    "status",
+                );
+                let func = code_gen.module.get_function("exit").unwrap();
+                code_gen
+                    .builder
+                    .build_call(func, &[status.as_basic_value_enum()], "");
+                code_gen.builder.build_unreachable();
+                Ok(())
+            },
+        ),
         create_method(
             "Object",
             "putchar(ord: Int) -> Void",
             |code_gen, function| {
                 let sk_int = function.get_params()[1];
-                let n = code_gen.unbox_int(sk_int);
+                let n = code_gen.builder.build_int_truncate(
+                    code_gen.unbox_int(sk_int),
+                    code_gen.i32_type,
+                    "n",
+                );
                 let func = code_gen.module.get_function("putchar").unwrap();
                 code_gen
                     .builder
@@ -64,13 +128,42 @@ pub fn create_methods() -> Vec<SkMethod> {
             code_gen.builder.build_return(None);
             Ok(())
         }),
-        create_method("Object", "puts(s: String) -> Void", |code_gen, function| {
-            let s = function.get_params()[1].into_pointer_value();
-            let pptr = code_gen.builder.build_struct_gep(s, 0, "").unwrap();
-            let ptr = code_gen.builder.build_load(pptr, "");
-            let func = code_gen.module.get_function("puts").unwrap();
-            code_gen.builder.build_call(func, &[ptr], "");
-            code_gen.builder.build_return(None);
+        create_method(
+            "Object",
+            "print(s: String) -> Void",
+            |code_gen, function| {
+                let s = function.get_params()[1];
+                let ptr = code_gen.build_ivar_load(s, 0, "@ptr");
+                let sk_int = code_gen.build_ivar_load(s, 1, "@bytesize");
+                // The precision of `%.*s` is an `int`
+                let bytesize = code_gen.builder.build_int_truncate(
+                    code_gen.unbox_int(sk_int),
+                    code_gen.i32_type,
+                    "bytesize",
+                );
+                let printf = code_gen.module.get_function("printf").unwrap();
+                let tmpl = code_gen
+                    .module
+                    .get_global("print_tmpl")
+                    .unwrap()
+                    .as_pointer_value();
+                let tmpl_ptr = unsafe {
+                    tmpl.const_in_bounds_gep(&[
+                        code_gen.i32_type.const_int(0, false),
+                        code_gen.i32_type.const_int(0, false),
+                    ])
+                };
+                code_gen
+                    .builder
+                    .build_call(printf, &[tmpl_ptr.into(), bytesize.into(), ptr], "");
+                code_gen.builder.build_return(None);
+                Ok(())
+            },
+        ),
+        create_method("Object", "class() -> Class", |code_gen, function| {
+            let this = function.get_params()[0];
+            let cls = code_gen.build_class_load(this);
+            code_gen.builder.build_return(Some(&cls));
             Ok(())
         }),
     ]
@@ -10,14 +10,10 @@ pub fn create_class_methods() -> Vec<SkMethod> {
             |code_gen, function| {
                 let sk_int = function.get_params()[1];
                 let n_bytes = code_gen.unbox_int(sk_int);
-                let n_bytes_64 =
-                    code_gen
-                        .builder
-                        .build_int_z_extend(n_bytes, code_gen.i64_type, "n_bytes_64");
                 let func = code_gen.module.get_function("GC_malloc").unwrap();
                 let mem = code_gen
                     .builder
-                    .build_call(func, &[n_bytes_64.into()], "mem")
+                    .build_call(func, &[n_bytes.into()], "mem")
                     .try_as_basic_value()
                     .left()
                     .unwrap();
@@ -32,14 +28,10 @@ pub fn create_class_methods() -> Vec<SkMethod> {
                 let ptr = function.get_params()[1];
                 let sk_int = function.get_params()[2];
                 let n_bytes = code_gen.unbox_int(sk_int);
-                let n_bytes_64 =
-                    code_gen
-                        .builder
-                        .build_int_z_extend(n_bytes, code_gen.i64_type, "n_bytes_64");
                 let func = code_gen.module.get_function("GC_realloc").unwrap();
                 let mem = code_gen
                     .builder
-                    .build_call(func, &[ptr, n_bytes_64.into()], "mem")
+                    .build_call(func, &[ptr, n_bytes.into()], "mem")
                     .try_as_basic_value()
                     .left()
                     .unwrap();
@@ -59,10 +51,6 @@ pub fn create_class_methods() -> Vec<SkMethod> {
                 let src = function.get_params()[2];
                 let sk_int = function.get_params()[3];
                 let n_bytes = code_gen.unbox_int(sk_int);
-                let n_bytes_64 =
-                    code_gen
-                        .builder
-                        .build_int_z_extend(n_bytes, code_gen.i64_type, "n_bytes_64");
                 let func = code_gen
                     .module
                     .get_function("llvm.memcpy.p0i8.p0i8.i64")
@@ -72,7 +60,7 @@ pub fn create_class_methods() -> Vec<SkMethod> {
                     &[
                         dst,
                         src,
-                        n_bytes_64.into(),
+                        n_bytes.into(),
                         code_gen.i32_type.const_int(0, false).into(),
                         code_gen.i1_type.const_int(0, false).into(),
                     ],
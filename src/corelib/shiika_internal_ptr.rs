@@ -55,5 +55,19 @@ pub fn create_methods() -> Vec<SkMethod> {
                 Ok(())
             },
         ),
+        create_method(
+            "Shiika::Internal::Ptr",
+            "load_byte -> Int",
+            |code_gen, function| {
+                let i8ptr = function.get_params()[0].into_pointer_value();
+                let byte = code_gen.builder.build_load(i8ptr, "byte").into_int_value();
+                let n = code_gen
+                    .builder
+                    .build_int_z_extend(byte, code_gen.i64_type, "n");
+                let sk_int = code_gen.box_int(&n);
+                code_gen.builder.build_return(Some(&sk_int));
+                Ok(())
+            },
+        ),
     ]
 }
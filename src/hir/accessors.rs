@@ -12,12 +12,10 @@ impl HirMaker {
     ) {
         let method_names = defs
             .iter()
-            .filter_map(|def| {
-                if let ast::Definition::InstanceMethodDefinition { sig, .. } = def {
-                    Some(&sig.name.0)
-                } else {
-                    None
-                }
+            .filter_map(|def| match def {
+                ast::Definition::InstanceMethodDefinition { sig, .. }
+                | ast::Definition::AbstractMethodDefinition { sig } => Some(&sig.name.0),
+                _ => None,
             })
             .collect::<Vec<_>>();
         for (name, ivar) in ivars {
@@ -44,6 +42,8 @@ fn create_getter(clsname: &ClassFullname, ivar: &SkIVar) -> SkMethod {
         fullname: method_fullname(clsname, &ivar.name),
         ret_ty: ivar.ty.clone(),
         params: vec![],
+        typarams: vec![],
+        visibility: Visibility::Public,
     };
     let name = ivar.name.clone(); // Clone to embed into the closure
     let idx = ivar.idx;
@@ -71,6 +71,8 @@ fn create_setter(clsname: &ClassFullname, ivar: &SkIVar) -> SkMethod {
             name: ivar.name.clone(),
             ty: ivar.ty.clone(),
         }],
+        typarams: vec![],
+        visibility: Visibility::Public,
     };
     let idx = ivar.idx;
     let getter_body = move |code_gen: &CodeGen, function: &inkwell::values::FunctionValue| {
@@ -45,8 +45,15 @@ impl ClassDict {
         corelib.into_iter().for_each(|(_, c)| {
             self.add_class(SkClass {
                 fullname: c.fullname,
-                typarams: vec![],
+                typarams: c.typarams,
                 superclass_fullname: c.superclass_fullname,
+                is_module: c.is_module,
+                is_interface: c.is_interface,
+                is_abstract: c.is_abstract,
+                includes: c.includes,
+                interfaces: c.interfaces,
+                abstract_methods: c.abstract_methods,
+                enum_cases: c.enum_cases,
                 instance_ty: c.instance_ty,
                 ivars: c.ivars,
                 method_sigs: c.method_sigs,
@@ -60,9 +67,35 @@ impl ClassDict {
                 name,
                 typarams,
                 super_name,
+                interfaces,
+                is_abstract,
                 defs,
             } => {
-                self.index_class(&name.add_namespace(""), &typarams, &super_name, &defs)?;
+                self.index_class(
+                    &name.add_namespace(""),
+                    &typarams,
+                    &super_name,
+                    &interfaces,
+                    *is_abstract,
+                    &defs,
+                )?;
+                Ok(())
+            }
+            ast::Definition::ModuleDefinition { name, defs } => {
+                self.index_module(&name.add_namespace(""), &defs)?;
+                Ok(())
+            }
+            ast::Definition::InterfaceDefinition { name, sigs } => {
+                self.index_interface(&name.add_namespace(""), &sigs)?;
+                Ok(())
+            }
+            ast::Definition::EnumDefinition {
+                name,
+                typarams,
+                cases,
+                defs,
+            } => {
+                self.index_enum(&name.add_namespace(""), &typarams, &cases, &defs)?;
                 Ok(())
             }
             ast::Definition::ConstDefinition { .. } => Ok(()),
@@ -70,62 +103,133 @@ impl ClassDict {
                 "must not be toplevel: {:?}",
                 def
             ))),
-        })
+        })?;
+        // Checked after indexing all the classes because the methods may
+        // refer to the classes defined later
+        self.check_overrides()?;
+        self.check_interface_conformance()
     }
 
     fn index_class(
         &mut self,
         fullname: &ClassFullname,
-        typarams: &[String],
+        typarams: &[ast::AstTyParam],
         super_name: &ClassFullname,
+        interfaces: &[ClassFullname],
+        is_abstract: bool,
         defs: &[ast::Definition],
     ) -> Result<(), Error> {
+        for name in interfaces {
+            self.check_interface(name)?;
+        }
+        // `class Foo` may reopen `abstract class Foo`
+        let is_abstract = is_abstract
+            || self
+                .find_class(fullname)
+                .map_or(false, |cls| cls.is_abstract);
+        let typarams = signature::convert_typarams(typarams, &[]);
         let instance_ty = ty::raw(&fullname.0);
         let class_ty = instance_ty.meta_ty();
 
         let metaclass_fullname = class_ty.fullname.clone();
         let mut instance_methods = HashMap::new();
         let mut class_methods = HashMap::new();
+        let mut includes = vec![];
+        let mut abstract_methods = vec![];
         let new_sig = signature::signature_of_new(
             &metaclass_fullname,
-            self.initializer_params(&super_name.instance_ty(), &defs),
+            self.initializer_params(&typarams, &super_name.instance_ty(), &defs),
             &instance_ty,
         );
 
         for def in defs {
             match def {
                 ast::Definition::InstanceMethodDefinition { sig, .. } => {
-                    let hir_sig = signature::create_signature(&fullname, sig, typarams);
+                    let hir_sig = signature::create_signature(&fullname, sig, &typarams);
                     instance_methods.insert(sig.name.clone(), hir_sig);
                 }
                 ast::Definition::ClassMethodDefinition { sig, .. } => {
                     let hir_sig = signature::create_signature(&metaclass_fullname, sig, &[]);
                     class_methods.insert(sig.name.clone(), hir_sig);
                 }
+                ast::Definition::AbstractMethodDefinition { sig } => {
+                    if !is_abstract {
+                        return Err(error::program_error(&format!(
+                            "`{}' must be an abstract class to have abstract method `{}'",
+                            fullname, sig.name
+                        )));
+                    }
+                    if sig.name.0 == "initialize" {
+                        return Err(error::program_error(&format!(
+                            "`{}#initialize' cannot be abstract",
+                            fullname
+                        )));
+                    }
+                    let hir_sig = signature::create_signature(&fullname, sig, &typarams);
+                    instance_methods.insert(sig.name.clone(), hir_sig);
+                    abstract_methods.push(sig.name.clone());
+                }
                 ast::Definition::ConstDefinition { .. } => (),
                 ast::Definition::ClassDefinition {
                     name,
                     typarams,
                     super_name,
+                    interfaces,
+                    is_abstract,
+                    defs,
+                } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_class(
+                        &full,
+                        &typarams,
+                        &super_name,
+                        &interfaces,
+                        *is_abstract,
+                        &defs,
+                    )?;
+                }
+                ast::Definition::ModuleDefinition { name, defs } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_module(&full, &defs)?;
+                }
+                ast::Definition::InterfaceDefinition { name, sigs } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_interface(&full, &sigs)?;
+                }
+                ast::Definition::EnumDefinition {
+                    name,
+                    typarams,
+                    cases,
                     defs,
                 } => {
                     let full = name.add_namespace(&fullname.0);
-                    self.index_class(&full, &typarams, &super_name, &defs)?;
+                    self.index_enum(&full, &typarams, &cases, &defs)?;
+                }
+                ast::Definition::Include { module_name } => {
+                    self.check_module(module_name)?;
+                    includes.push(module_name.clone());
                 }
             }
         }
 
         match self.sk_classes.get_mut(&fullname) {
             Some(class) => {
+                if class.is_module || class.is_interface {
+                    return Err(error::type_error(&format!("`{}' is not a class", fullname)));
+                }
                 // Merge methods to existing class (Class is reopened)
                 class.method_sigs.extend(instance_methods);
+                class.includes.extend(includes);
+                class.interfaces.extend(interfaces.iter().cloned());
+                class.abstract_methods.extend(abstract_methods);
                 let metaclass = self
                     .sk_classes
                     .get_mut(&metaclass_fullname)
                     .expect("[BUG] Only class is indexed");
                 metaclass.method_sigs.extend(class_methods);
-                // Add `.new` to the metaclass
-                if !metaclass.method_sigs.contains_key(&method_firstname("new")) {
+                // Add `.new` to the metaclass (abstract classes cannot be
+                // instantiated)
+                if !is_abstract && !metaclass.method_sigs.contains_key(&method_firstname("new")) {
                     metaclass
                         .method_sigs
                         .insert(new_sig.fullname.first_name.clone(), new_sig);
@@ -133,17 +237,215 @@ impl ClassDict {
             }
             None => {
                 // Add `.new` to the metaclass
-                class_methods.insert(new_sig.fullname.first_name.clone(), new_sig);
-                if !self.class_exists(&super_name.0) {
-                    return Err(error::name_error(&format!(
-                        "unknown superclass: {:?}",
-                        super_name
+                if !is_abstract {
+                    class_methods.insert(new_sig.fullname.first_name.clone(), new_sig);
+                }
+                match self.find_class(&super_name) {
+                    None => {
+                        return Err(error::name_error(&format!(
+                            "unknown superclass: {:?}",
+                            super_name
+                        )))
+                    }
+                    // Enums are sealed
+                    Some(SkClass {
+                        enum_cases: Some(cases),
+                        ..
+                    }) if !cases.contains(fullname) => {
+                        return Err(error::program_error(&format!(
+                            "cannot inherit enum `{}' (add a `case' instead)",
+                            super_name
+                        )))
+                    }
+                    _ => (),
+                }
+                self.add_class(SkClass {
+                    fullname: fullname.clone(),
+                    typarams,
+                    superclass_fullname: Some(super_name.clone()),
+                    is_module: false,
+                    is_interface: false,
+                    is_abstract,
+                    includes,
+                    interfaces: interfaces.to_vec(),
+                    abstract_methods,
+                    enum_cases: None,
+                    instance_ty,
+                    ivars: HashMap::new(),
+                    method_sigs: instance_methods,
+                });
+                self.add_class(SkClass {
+                    fullname: metaclass_fullname,
+                    typarams: vec![],
+                    superclass_fullname: Some(class_fullname("Class")),
+                    is_module: false,
+                    is_interface: false,
+                    is_abstract: false,
+                    includes: vec![],
+                    interfaces: vec![],
+                    abstract_methods: vec![],
+                    enum_cases: None,
+                    instance_ty: class_ty,
+                    ivars: HashMap::new(),
+                    method_sigs: class_methods,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Index an enum and its cases
+    /// (Each case is a subclass of the enum, eg. `Maybe::Some`)
+    fn index_enum(
+        &mut self,
+        fullname: &ClassFullname,
+        typarams: &[ast::AstTyParam],
+        cases: &[ast::EnumCase],
+        defs: &[ast::Definition],
+    ) -> Result<(), Error> {
+        if let Some(cls) = self.find_class(fullname) {
+            if cls.enum_cases.is_none() {
+                return Err(error::type_error(&format!(
+                    "`{}' is a class, not an enum",
+                    fullname
+                )));
+            }
+        }
+        self.index_class(
+            fullname,
+            typarams,
+            &class_fullname("Object"),
+            &[],
+            false,
+            defs,
+        )?;
+
+        // Register the cases first so that they can inherit the enum
+        let enum_class = self.get_class_mut(fullname, "ClassDict::index_enum");
+        let enum_cases = enum_class.enum_cases.get_or_insert_with(Vec::new);
+        for case in cases {
+            let full = case.name.add_namespace(&fullname.0);
+            if !enum_cases.contains(&full) {
+                enum_cases.push(full);
+            }
+        }
+
+        for case in cases {
+            let full = case.name.add_namespace(&fullname.0);
+            self.index_class(&full, typarams, fullname, &[], false, &case.class_defs())?;
+        }
+        Ok(())
+    }
+
+    fn index_module(
+        &mut self,
+        fullname: &ClassFullname,
+        defs: &[ast::Definition],
+    ) -> Result<(), Error> {
+        let instance_ty = ty::raw(&fullname.0);
+        let class_ty = instance_ty.meta_ty();
+
+        let metaclass_fullname = class_ty.fullname.clone();
+        let mut instance_methods = HashMap::new();
+        let mut class_methods = HashMap::new();
+
+        for def in defs {
+            match def {
+                ast::Definition::InstanceMethodDefinition { sig, .. } => {
+                    if def.is_initializer() {
+                        return Err(error::program_error(&format!(
+                            "module `{}' cannot have `initialize'",
+                            fullname
+                        )));
+                    }
+                    let hir_sig = signature::create_signature(&fullname, sig, &[]);
+                    instance_methods.insert(sig.name.clone(), hir_sig);
+                }
+                ast::Definition::ClassMethodDefinition { sig, .. } => {
+                    let hir_sig = signature::create_signature(&metaclass_fullname, sig, &[]);
+                    class_methods.insert(sig.name.clone(), hir_sig);
+                }
+                ast::Definition::AbstractMethodDefinition { sig } => {
+                    return Err(error::program_error(&format!(
+                        "module `{}' cannot have abstract method `{}'",
+                        fullname, sig.name
+                    )));
+                }
+                ast::Definition::ConstDefinition { .. } => (),
+                ast::Definition::ClassDefinition {
+                    name,
+                    typarams,
+                    super_name,
+                    interfaces,
+                    is_abstract,
+                    defs,
+                } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_class(
+                        &full,
+                        &typarams,
+                        &super_name,
+                        &interfaces,
+                        *is_abstract,
+                        &defs,
+                    )?;
+                }
+                ast::Definition::ModuleDefinition { name, defs } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_module(&full, &defs)?;
+                }
+                ast::Definition::InterfaceDefinition { name, sigs } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_interface(&full, &sigs)?;
+                }
+                ast::Definition::EnumDefinition {
+                    name,
+                    typarams,
+                    cases,
+                    defs,
+                } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.index_enum(&full, &typarams, &cases, &defs)?;
+                }
+                ast::Definition::Include { .. } => {
+                    return Err(error::program_error(&format!(
+                        "module `{}' cannot include other modules (not supported yet)",
+                        fullname
                     )));
                 }
+            }
+        }
+
+        match self.sk_classes.get_mut(&fullname) {
+            Some(module) => {
+                if !module.is_module {
+                    return Err(error::type_error(&format!(
+                        "`{}' is not a module",
+                        fullname
+                    )));
+                }
+                // Merge methods to existing module (Module is reopened)
+                module.method_sigs.extend(instance_methods);
+                let metaclass = self
+                    .sk_classes
+                    .get_mut(&metaclass_fullname)
+                    .expect("[BUG] Only module is indexed");
+                metaclass.method_sigs.extend(class_methods);
+            }
+            None => {
+                // Modules inherit Object so that `self` in the methods
+                // can call the methods of Object
                 self.add_class(SkClass {
                     fullname: fullname.clone(),
                     typarams: vec![],
-                    superclass_fullname: Some(super_name.clone()),
+                    superclass_fullname: Some(class_fullname("Object")),
+                    is_module: true,
+                    is_interface: false,
+                    is_abstract: false,
+                    includes: vec![],
+                    interfaces: vec![],
+                    abstract_methods: vec![],
+                    enum_cases: None,
                     instance_ty,
                     ivars: HashMap::new(),
                     method_sigs: instance_methods,
@@ -152,6 +454,13 @@ impl ClassDict {
                     fullname: metaclass_fullname,
                     typarams: vec![],
                     superclass_fullname: Some(class_fullname("Class")),
+                    is_module: false,
+                    is_interface: false,
+                    is_abstract: false,
+                    includes: vec![],
+                    interfaces: vec![],
+                    abstract_methods: vec![],
+                    enum_cases: None,
                     instance_ty: class_ty,
                     ivars: HashMap::new(),
                     method_sigs: class_methods,
@@ -160,4 +469,232 @@ impl ClassDict {
         }
         Ok(())
     }
+
+    /// Check `name` is a module which can be included
+    fn check_module(&self, name: &ClassFullname) -> Result<(), Error> {
+        match self.find_class(name) {
+            Some(cls) if cls.is_module => Ok(()),
+            Some(_) => Err(error::type_error(&format!("`{}' is not a module", name))),
+            None => Err(error::name_error(&format!("unknown module: {:?}", name))),
+        }
+    }
+
+    /// Index an interface
+    /// (`Self` in the signatures is left as is and replaced on lookup)
+    fn index_interface(
+        &mut self,
+        fullname: &ClassFullname,
+        sigs: &[ast::AstMethodSignature],
+    ) -> Result<(), Error> {
+        let instance_ty = ty::raw(&fullname.0);
+        let class_ty = instance_ty.meta_ty();
+        let metaclass_fullname = class_ty.fullname.clone();
+        let method_sigs = sigs
+            .iter()
+            .map(|sig| {
+                let hir_sig = signature::create_signature(&fullname, sig, &[]);
+                (sig.name.clone(), hir_sig)
+            })
+            .collect::<HashMap<_, _>>();
+
+        match self.sk_classes.get_mut(&fullname) {
+            Some(interface) => {
+                if !interface.is_interface {
+                    return Err(error::type_error(&format!(
+                        "`{}' is not an interface",
+                        fullname
+                    )));
+                }
+                // Merge methods to existing interface (Interface is reopened)
+                interface.method_sigs.extend(method_sigs);
+            }
+            None => {
+                // Interfaces inherit Object so that the methods of Object
+                // can be called on them
+                self.add_class(SkClass {
+                    fullname: fullname.clone(),
+                    typarams: vec![],
+                    superclass_fullname: Some(class_fullname("Object")),
+                    is_module: false,
+                    is_interface: true,
+                    is_abstract: false,
+                    includes: vec![],
+                    interfaces: vec![],
+                    abstract_methods: vec![],
+                    enum_cases: None,
+                    instance_ty,
+                    ivars: HashMap::new(),
+                    method_sigs,
+                });
+                self.add_class(SkClass {
+                    fullname: metaclass_fullname,
+                    typarams: vec![],
+                    superclass_fullname: Some(class_fullname("Class")),
+                    is_module: false,
+                    is_interface: false,
+                    is_abstract: false,
+                    includes: vec![],
+                    interfaces: vec![],
+                    abstract_methods: vec![],
+                    enum_cases: None,
+                    instance_ty: class_ty,
+                    ivars: HashMap::new(),
+                    method_sigs: HashMap::new(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check `name` is an interface which can be implemented
+    fn check_interface(&self, name: &ClassFullname) -> Result<(), Error> {
+        match self.find_class(name) {
+            Some(cls) if cls.is_interface => Ok(()),
+            Some(_) => Err(error::type_error(&format!(
+                "`{}' is not an interface",
+                name
+            ))),
+            None => Err(error::name_error(&format!("unknown interface: {:?}", name))),
+        }
+    }
+
+    /// Check each concrete class implements all the abstract methods of its
+    /// ancestors
+    pub fn check_abstract_methods(&self) -> Result<(), Error> {
+        let mut classes = self
+            .sk_classes
+            .values()
+            .filter(|cls| !cls.is_abstract && !cls.is_module && !cls.is_interface)
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| a.fullname.0.cmp(&b.fullname.0));
+        for cls in classes {
+            let mut missing = vec![];
+            let mut ancestor = self.get_superclass(&cls.fullname);
+            while let Some(ances) = ancestor {
+                for name in &ances.abstract_methods {
+                    let (sig, found_cls) = self
+                        .lookup_method(&cls.instance_ty, name)
+                        .expect("[BUG] abstract method not found");
+                    // Not overridden by a concrete method
+                    if self
+                        .get_class(&found_cls, "check_abstract_methods")
+                        .abstract_methods
+                        .contains(name)
+                    {
+                        if !missing.contains(&sig.fullname) {
+                            missing.push(sig.fullname);
+                        }
+                        continue;
+                    }
+                    let abstract_sig = ances
+                        .method_sigs
+                        .get(name)
+                        .expect("[BUG] abstract method signature not found");
+                    if !sig.conforms_to(abstract_sig, self) {
+                        return Err(error::type_error(&format!(
+                            "`{}' does not match the abstract method `{}' (expected {} but got {})",
+                            sig.fullname, abstract_sig.fullname, abstract_sig, sig
+                        )));
+                    }
+                }
+                ancestor = self.get_superclass(&ances.fullname);
+            }
+            if !missing.is_empty() {
+                let names = missing
+                    .iter()
+                    .map(|x| format!("`{}'", x))
+                    .collect::<Vec<_>>();
+                return Err(error::program_error(&format!(
+                    "`{}' must implement the abstract methods: {}",
+                    cls.fullname,
+                    names.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check each method which overrides a method of the superclass can be
+    /// called as the overridden one (it takes over the vtable slot, so
+    /// eg. `==(other: String)` cannot override `Object#==(other: Object)`)
+    fn check_overrides(&self) -> Result<(), Error> {
+        let mut classes = self
+            .sk_classes
+            .values()
+            // Class methods are not called via vtables
+            .filter(|cls| !cls.is_module && !cls.is_interface && !cls.fullname.is_meta())
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| a.fullname.0.cmp(&b.fullname.0));
+        for cls in classes {
+            let super_ty = match &cls.superclass_fullname {
+                Some(name) => name.instance_ty(),
+                None => continue,
+            };
+            let mut sigs = cls.method_sigs.values().collect::<Vec<_>>();
+            sigs.sort_by(|a, b| a.fullname.full_name.cmp(&b.fullname.full_name));
+            for sig in sigs {
+                // Initializers are always called with the exact class
+                if sig.first_name().0 == "initialize" {
+                    continue;
+                }
+                let (super_sig, super_cls) = match self.lookup_method(&super_ty, sig.first_name()) {
+                    Ok(found) => found,
+                    Err(_) => continue,
+                };
+                // Checked by `check_abstract_methods` (which also covers accessors)
+                if self
+                    .get_class(&super_cls, "check_overrides")
+                    .abstract_methods
+                    .contains(sig.first_name())
+                {
+                    continue;
+                }
+                if !sig.conforms_to(&super_sig, self) {
+                    return Err(error::type_error(&format!(
+                        "`{}' does not match `{}' which it overrides (expected {} but got {})",
+                        sig.fullname, super_sig.fullname, super_sig, sig
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check each class has all the methods required by the interfaces it
+    /// implements
+    fn check_interface_conformance(&self) -> Result<(), Error> {
+        let mut classes = self
+            .sk_classes
+            .values()
+            .filter(|cls| !cls.interfaces.is_empty())
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| a.fullname.0.cmp(&b.fullname.0));
+        for cls in classes {
+            for interface_name in &cls.interfaces {
+                let interface = self.get_class(interface_name, "check_interface_conformance");
+                let mut required_sigs = interface.method_sigs.values().collect::<Vec<_>>();
+                required_sigs.sort_by(|a, b| a.fullname.full_name.cmp(&b.fullname.full_name));
+                for required in required_sigs {
+                    // eg. `other: Self` of `Comparable#<=>` is a `Version`
+                    let required = required.replace_self(&cls.instance_ty);
+                    let sig = match self.lookup_method(&cls.instance_ty, required.first_name()) {
+                        Ok((sig, _)) => sig,
+                        Err(_) => {
+                            return Err(error::program_error(&format!(
+                                "`{}' does not implement `{}' required by `{}'",
+                                cls.fullname, required.fullname, interface_name
+                            )))
+                        }
+                    };
+                    if !sig.conforms_to(&required, self) {
+                        return Err(error::type_error(&format!(
+                            "`{}' does not match `{}' required by `{}' (expected {} but got {})",
+                            sig.fullname, required.fullname, interface_name, required, sig
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
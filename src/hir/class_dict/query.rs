@@ -17,7 +17,8 @@ impl ClassDict {
             .and_then(|class| class.method_sigs.get(method_name))
     }
 
-    /// Similar to find_method, but lookup into superclass if not in the class.
+    /// Similar to find_method, but lookup into included modules and
+    /// superclass if not in the class.
     /// Returns Err if not found.
     pub fn lookup_method(
         &self,
@@ -34,7 +35,28 @@ impl ClassDict {
                 .expect("[BUG] base_cls not found")
                 .instance_ty;
             let (base_sig, found_cls) = self.lookup_method_(base_cls, base_cls, method_name)?;
-            Ok((base_sig.specialize(&type_args), found_cls))
+            Ok((base_sig.specialize(&type_args, &[]), found_cls))
+        } else if let TyBody::TySpeMeta {
+            base_name,
+            type_args,
+        } = &class.body
+        {
+            // eg. `Array<Int>.new`
+            let base_meta = ty::meta(base_name);
+            let (base_sig, found_cls) = self.lookup_method_(&base_meta, &base_meta, method_name)?;
+            Ok((base_sig.specialize(&type_args, &[]), found_cls))
+        } else if let TyBody::TyParamRef { upper_bound, .. } = &class.body {
+            // Look up in the upper bound
+            if upper_bound.is_specialized() {
+                self.lookup_method(upper_bound, method_name)
+            } else {
+                self.lookup_method_(class, upper_bound, method_name)
+            }
+        } else if class.is_nullable() {
+            Err(error::type_error(&format!(
+                "cannot call `{}' on {} because it may be nil (check it with `!= nil' or use `&.')",
+                method_name, class
+            )))
         } else {
             self.lookup_method_(class, class, method_name)
         }
@@ -47,7 +69,14 @@ impl ClassDict {
         method_name: &MethodFirstname,
     ) -> Result<(MethodSignature, ClassFullname), Error> {
         if let Some(sig) = self.find_method(&class.fullname, method_name) {
-            Ok((sig.clone(), class.fullname.clone()))
+            if self.is_interface(&class.fullname) {
+                Ok((
+                    interface_method_sig(receiver_class, class, sig)?,
+                    class.fullname.clone(),
+                ))
+            } else {
+                Ok((sig.clone(), class.fullname.clone()))
+            }
         } else {
             // Look up in superclass
             let sk_class = self.find_class(&class.fullname).unwrap_or_else(|| {
@@ -56,6 +85,12 @@ impl ClassDict {
                     &method_name.0, &class.fullname.0
                 )
             });
+            // Look up in included modules (the last included one first)
+            for module_name in sk_class.includes.iter().rev() {
+                if let Some(sig) = self.find_method(module_name, method_name) {
+                    return Ok((sig.clone(), module_name.clone()));
+                }
+            }
             if let Some(super_name) = &sk_class.superclass_fullname {
                 // TODO #115: super may not be a ty::raw
                 let super_class = ty::raw(&super_name.0);
@@ -104,6 +139,42 @@ impl ClassDict {
             .contains_key(&ClassFullname(class_fullname.to_string()))
     }
 
+    /// Return true if `name` is an interface
+    pub fn is_interface(&self, name: &ClassFullname) -> bool {
+        self.find_class(name).map_or(false, |cls| cls.is_interface)
+    }
+
+    /// Return true if the class of `ty` declares `implements interface`
+    /// (Does not look into the superclasses)
+    pub fn implements(&self, ty: &TermTy, interface: &TermTy) -> bool {
+        let name = match &ty.body {
+            TyBody::TySpe { base_name, .. } => class_fullname(base_name),
+            _ => ty.fullname.clone(),
+        };
+        self.find_class(&name)
+            .map_or(false, |cls| cls.interfaces.contains(&interface.fullname))
+    }
+
+    /// Return true if `ty` is `class`, its subclass or a class which
+    /// includes the module `class`
+    pub fn is_kind_of(&self, ty: &TermTy, class: &ClassFullname) -> bool {
+        let name = match &ty.body {
+            TyBody::TySpe { base_name, .. } => class_fullname(base_name),
+            _ => ty.fullname.clone(),
+        };
+        let mut c = self.find_class(&name);
+        while let Some(cls) = c {
+            if cls.fullname == *class || cls.includes.contains(class) {
+                return true;
+            }
+            c = cls
+                .superclass_fullname
+                .as_ref()
+                .and_then(|name| self.find_class(name));
+        }
+        false
+    }
+
     /// Find the superclass
     /// Return None if the class is `Object`
     pub fn get_superclass(&self, classname: &ClassFullname) -> Option<&SkClass> {
@@ -113,6 +184,30 @@ impl ClassDict {
             .map(|super_name| self.get_class(&super_name, "ClassDict::get_superclass"))
     }
 
+    /// Return the class and its descendant classes
+    pub fn descendant_classes(&self, fullname: &ClassFullname) -> Vec<ClassFullname> {
+        let mut v = self
+            .sk_classes
+            .values()
+            .filter(|cls| {
+                let mut c = Some(*cls);
+                while let Some(cls) = c {
+                    if cls.fullname == *fullname {
+                        return true;
+                    }
+                    c = cls
+                        .superclass_fullname
+                        .as_ref()
+                        .and_then(|name| self.find_class(name));
+                }
+                false
+            })
+            .map(|cls| cls.fullname.clone())
+            .collect::<Vec<_>>();
+        v.sort_by(|a, b| a.0.cmp(&b.0));
+        v
+    }
+
     /// Return supertype of `ty`
     pub fn supertype_of(&self, ty: &TermTy) -> Option<TermTy> {
         ty.supertype(self)
@@ -139,3 +234,24 @@ impl ClassDict {
         class.ivars.get(ivar_name)
     }
 }
+
+/// Replace `Self` in the signature of an interface method
+fn interface_method_sig(
+    receiver_class: &TermTy,
+    interface: &TermTy,
+    sig: &MethodSignature,
+) -> Result<MethodSignature, Error> {
+    if let TyBody::TyParamRef { .. } = &receiver_class.body {
+        // eg. `other: Self` of `Comparable#<=>` is a `T` when called on `T: Comparable`
+        Ok(sig.replace_self(receiver_class))
+    } else if sig.params.iter().any(|param| param.ty.contains_self()) {
+        // The classes which implement the interface may differ between
+        // the receiver and the args (eg. `Version` and `Money`)
+        Err(error::type_error(&format!(
+            "cannot call {} on an interface type `{}' because it takes `Self' (use a type parameter like `<T: {}>' instead)",
+            sig, interface, interface
+        )))
+    } else {
+        Ok(sig.replace_self(interface))
+    }
+}
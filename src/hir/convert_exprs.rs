@@ -1,6 +1,7 @@
 use crate::ast::*;
 use crate::error;
 use crate::error::Error;
+use crate::hir::class_dict::ClassDict;
 use crate::hir::hir_maker::HirMaker;
 use crate::hir::hir_maker_context::*;
 use crate::hir::*;
@@ -13,10 +14,18 @@ impl HirMaker {
         ctx: &mut HirMakerContext,
         exprs: &[AstExpression],
     ) -> Result<HirExpressions, Error> {
-        let hir_exprs = exprs
-            .iter()
-            .map(|expr| self.convert_expr(ctx, expr))
-            .collect::<Result<Vec<_>, _>>()?;
+        let narrowed_vars = ctx.narrowed_vars.clone();
+        let mut hir_exprs = vec![];
+        for expr in exprs {
+            let hir_expr = self.convert_expr(ctx, expr)?;
+            // eg. `x` is not nil after `return if x == nil`
+            if let Some(name) = narrowed_after(expr, &hir_expr) {
+                ctx.narrowed_vars.push(name.to_string());
+            }
+            hir_exprs.push(hir_expr);
+        }
+        // (Keep the variables reassigned in this block un-narrowed)
+        ctx.narrowed_vars.retain(|s| narrowed_vars.contains(s));
 
         Ok(HirExpressions::new(hir_exprs))
     }
@@ -45,8 +54,28 @@ impl HirMaker {
                 body_exprs,
             } => self.convert_while_expr(ctx, cond_expr, body_exprs),
 
+            AstExpressionBody::Case {
+                cond_expr,
+                when_clauses,
+                else_exprs,
+            } => self.convert_case_expr(ctx, cond_expr, when_clauses, else_exprs),
+
+            AstExpressionBody::Match { cond_expr, clauses } => {
+                self.convert_match_expr(ctx, cond_expr, clauses)
+            }
+
             AstExpressionBody::Break => self.convert_break_expr(),
 
+            AstExpressionBody::Return { arg } => self.convert_return_expr(ctx, arg),
+
+            AstExpressionBody::Begin {
+                body_exprs,
+                rescue_clauses,
+                ensure_exprs,
+            } => self.convert_begin_expr(ctx, body_exprs, rescue_clauses, ensure_exprs),
+
+            AstExpressionBody::Raise { expr } => self.convert_raise_expr(ctx, expr),
+
             AstExpressionBody::LVarAssign { name, rhs, is_var } => {
                 self.convert_lvar_assign(ctx, name, &*rhs, is_var)
             }
@@ -66,24 +95,42 @@ impl HirMaker {
                 ..
             } => self.convert_method_call(ctx, receiver_expr, method_name, arg_exprs),
 
+            AstExpressionBody::SafeMethodCall {
+                receiver_expr,
+                method_name,
+                arg_exprs,
+            } => self.convert_safe_method_call(ctx, receiver_expr, method_name, arg_exprs),
+
             AstExpressionBody::Lambda { params, exprs } => self.convert_lambda(ctx, params, exprs),
 
+            AstExpressionBody::Block { .. } => Err(error::syntax_error(
+                "a block can only be given to a method call",
+            )),
+
             AstExpressionBody::BareName(name) => self.convert_bare_name(ctx, name),
 
             AstExpressionBody::IVarRef(names) => self.convert_ivar_ref(ctx, names),
 
             AstExpressionBody::ConstRef(names) => self.convert_const_ref(ctx, names),
 
+            AstExpressionBody::SpecializeExpression { names, typ_args } => {
+                self.convert_specialize_expr(ctx, names, typ_args)
+            }
+
             AstExpressionBody::PseudoVariable(token) => self.convert_pseudo_variable(ctx, token),
 
             AstExpressionBody::ArrayLiteral(exprs) => self.convert_array_literal(ctx, exprs),
+            AstExpressionBody::HashLiteral(pairs) => self.convert_hash_literal(ctx, pairs),
 
             AstExpressionBody::FloatLiteral { value } => Ok(Hir::float_literal(*value)),
 
             AstExpressionBody::DecimalLiteral { value } => Ok(Hir::decimal_literal(*value)),
 
             AstExpressionBody::StringLiteral { content } => self.convert_string_literal(content),
-            //x => panic!("TODO: {:?}", x)
+
+            AstExpressionBody::StringInterpolation { parts } => {
+                self.convert_string_interpolation(ctx, parts)
+            } //x => panic!("TODO: {:?}", x)
         }
     }
 
@@ -133,18 +180,84 @@ impl HirMaker {
         let cond_hir = self.convert_expr(ctx, cond_expr)?;
         type_checking::check_condition_ty(&cond_hir.ty, "if")?;
 
-        let then_hirs = self.convert_exprs(ctx, then_exprs)?;
-        let else_hirs = match else_exprs {
+        // `x` is not nil in the then clause of `if x != nil` (or the else
+        // clause of `if x == nil`)
+        let nil_check = nil_check_target(cond_expr);
+        let narrowed_vars = ctx.narrowed_vars.clone();
+        if let Some((name, false)) = nil_check {
+            ctx.narrowed_vars.push(name.to_string());
+        }
+        let mut then_hirs = self.convert_exprs(ctx, then_exprs)?;
+        ctx.narrowed_vars.retain(|s| narrowed_vars.contains(s));
+        if let Some((name, true)) = nil_check {
+            ctx.narrowed_vars.push(name.to_string());
+        }
+        let mut else_hirs = match else_exprs {
+            Some(exprs) => Some(self.convert_exprs(ctx, exprs)?),
+            None => None,
+        };
+        ctx.narrowed_vars.retain(|s| narrowed_vars.contains(s));
+
+        let ty = self.unify_if_branches(&mut then_hirs, &mut else_hirs)?;
+        Ok(Hir::if_expression(ty, cond_hir, then_hirs, else_hirs))
+    }
+
+    /// Convert `case` into a chain of `if`s. eg.
+    ///
+    /// ```text
+    /// case x
+    /// when 1, 2 then a
+    /// else b
+    /// end
+    /// ```
+    ///
+    /// becomes `if 1 === (tmp = x) || 2 === tmp then a else b end`
+    fn convert_case_expr(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        cond_expr: &AstExpression,
+        when_clauses: &[AstWhenClause],
+        else_exprs: &Option<Vec<AstExpression>>,
+    ) -> Result<HirExpression, Error> {
+        let cond_hir = self.convert_expr(ctx, cond_expr)?;
+        let value_ty = cond_hir.ty.clone();
+        let tmp_name = self.gensym();
+        let mut value_hir = Some(Hir::assign_lvar(&tmp_name, cond_hir));
+
+        let mut tests = vec![];
+        for clause in when_clauses {
+            let mut test: Option<HirExpression> = None;
+            for expr in &clause.exprs {
+                let pattern_hir = self.convert_expr(ctx, expr)?;
+                // The value is evaluated in the first test
+                let arg_hir = value_hir
+                    .take()
+                    .unwrap_or_else(|| Hir::lvar_ref(value_ty.clone(), tmp_name.clone()));
+                let call_hir =
+                    self.make_method_call(pattern_hir, &method_firstname("==="), vec![arg_hir])?;
+                type_checking::check_condition_ty(&call_hir.ty, "when")?;
+                test = Some(match test {
+                    Some(left) => Hir::logical_or(left, call_hir),
+                    None => call_hir,
+                });
+            }
+            let body_hirs = self.convert_exprs(ctx, &clause.body_exprs)?;
+            tests.push((test.unwrap(), body_hirs));
+        }
+        let mut else_hirs = match else_exprs {
             Some(exprs) => Some(self.convert_exprs(ctx, exprs)?),
             None => None,
         };
-        // TODO: then and else must have conpatible type
-        Ok(Hir::if_expression(
-            then_hirs.ty.clone(),
-            cond_hir,
-            then_hirs,
-            else_hirs,
-        ))
+
+        let (last_test, mut last_then_hirs) = tests.pop().unwrap();
+        let ty = self.unify_if_branches(&mut last_then_hirs, &mut else_hirs)?;
+        let mut if_hir = Hir::if_expression(ty, last_test, last_then_hirs, else_hirs);
+        while let Some((test, mut then_hirs)) = tests.pop() {
+            let mut else_hirs = Some(HirExpressions::new(vec![if_hir]));
+            let ty = self.unify_if_branches(&mut then_hirs, &mut else_hirs)?;
+            if_hir = Hir::if_expression(ty, test, then_hirs, else_hirs);
+        }
+        Ok(if_hir)
     }
 
     fn convert_while_expr(
@@ -156,7 +269,16 @@ impl HirMaker {
         let cond_hir = self.convert_expr(ctx, cond_expr)?;
         type_checking::check_condition_ty(&cond_hir.ty, "while")?;
 
+        // `x` is not nil in the body of `while x != nil`.
+        // Variables declared with `var` may become nil in the previous
+        // iteration, so they are narrowed only by the condition
+        let narrowed_vars = ctx.narrowed_vars.clone();
+        ctx.narrowed_vars = readonly_vars(ctx, &narrowed_vars);
+        if let Some((name, false)) = nil_check_target(cond_expr) {
+            ctx.narrowed_vars.push(name.to_string());
+        }
         let body_hirs = self.convert_exprs(ctx, body_exprs)?;
+        ctx.narrowed_vars = readonly_vars(ctx, &narrowed_vars);
         Ok(Hir::while_expression(cond_hir, body_hirs))
     }
 
@@ -164,6 +286,168 @@ impl HirMaker {
         Ok(Hir::break_expression())
     }
 
+    fn convert_return_expr(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        arg: &Option<Box<AstExpression>>,
+    ) -> Result<HirExpression, Error> {
+        let mut arg_hir = match arg {
+            Some(expr) => self.convert_expr(ctx, expr)?,
+            None => Hir::const_ref(ty::raw("Void"), const_fullname("::Void")),
+        };
+        if ctx.outer.is_some() {
+            // Checked later because the return type of a lambda is
+            // not known yet (see `make_lambda`)
+            ctx.return_tys.push(arg_hir.ty.clone());
+        } else {
+            match &ctx.method_sig {
+                Some(sig) => {
+                    type_checking::check_return_value(&self.class_dict, sig, &arg_hir.ty)?;
+                    if !sig.ret_ty.is_void_type() {
+                        arg_hir = Hir::upcast(&sig.ret_ty, arg_hir);
+                    }
+                }
+                None => return Err(error::program_error("return outside of a method")),
+            }
+        }
+        Ok(Hir::return_expression(arg_hir))
+    }
+
+    fn convert_begin_expr(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        body_exprs: &[AstExpression],
+        rescue_clauses: &[AstRescueClause],
+        ensure_exprs: &Option<Vec<AstExpression>>,
+    ) -> Result<HirExpression, Error> {
+        let mut body_hirs = self.convert_exprs(ctx, body_exprs)?;
+        let mut clauses = rescue_clauses
+            .iter()
+            .map(|clause| self.convert_rescue_clause(ctx, clause))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ensure_hirs = match ensure_exprs {
+            Some(exprs) => Some(self.convert_exprs(ctx, exprs)?),
+            None => None,
+        };
+        let ty = self.begin_expr_ty(&body_hirs, &clauses)?;
+        if !ty.is_void_type() {
+            body_hirs.upcast(&ty);
+            for clause in &mut clauses {
+                clause.exprs.upcast(&ty);
+            }
+        }
+        Ok(Hir::begin_expression(ty, body_hirs, clauses, ensure_hirs))
+    }
+
+    /// Type of a `begin` expression (decided like `if_expr_ty`)
+    fn begin_expr_ty(
+        &self,
+        body_hirs: &HirExpressions,
+        clauses: &[HirRescueClause],
+    ) -> Result<TermTy, Error> {
+        let mut ty = body_hirs.ty.clone();
+        for clause in clauses {
+            let t = &clause.exprs.ty;
+            if t.is_never_type() {
+                continue;
+            } else if ty.is_never_type() {
+                ty = t.clone();
+            } else if ty.is_void_type() || t.is_void_type() {
+                ty = ty::raw("Void");
+            } else {
+                ty = self.nearest_common_ancestor_type(&ty, t).map_err(|_| {
+                    error::type_error(&format!(
+                        "the clauses of `begin' have incompatible types ({} and {})",
+                        ty, t
+                    ))
+                })?;
+            }
+        }
+        Ok(ty)
+    }
+
+    fn convert_rescue_clause(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        clause: &AstRescueClause,
+    ) -> Result<HirRescueClause, Error> {
+        let ty = match &clause.typ {
+            Some(typ) => {
+                if !self.class_dict.class_exists(&typ.name) {
+                    return Err(error::program_error(&format!(
+                        "unknown class `{}'",
+                        typ.name
+                    )));
+                }
+                ty::raw(&typ.name)
+            }
+            None => ty::raw("Exception"),
+        };
+        if !self.is_exception_type(&ty) {
+            return Err(error::type_error(&format!(
+                "cannot rescue {} (not an Exception)",
+                ty.fullname
+            )));
+        }
+
+        let mut hirs = vec![];
+        if let Some(name) = &clause.var_name {
+            if ctx.lvars.contains_key(name) {
+                return Err(error::program_error(&format!(
+                    "variable `{}' already exists",
+                    name
+                )));
+            }
+            ctx.lvars.insert(
+                name.to_string(),
+                CtxLVar {
+                    name: name.to_string(),
+                    ty: ty.clone(),
+                    readonly: true,
+                },
+            );
+            hirs.push(Hir::assign_lvar(name, Hir::caught_exception(ty.clone())));
+        }
+        for expr in &clause.exprs {
+            hirs.push(self.convert_expr(ctx, expr)?);
+        }
+        if let Some(name) = &clause.var_name {
+            ctx.lvars.remove(name);
+        }
+
+        Ok(HirRescueClause {
+            class_fullnames: self.class_dict.descendant_classes(&ty.fullname),
+            var_name: clause.var_name.clone(),
+            exprs: HirExpressions::new(hirs),
+        })
+    }
+
+    fn convert_raise_expr(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        expr: &AstExpression,
+    ) -> Result<HirExpression, Error> {
+        let expr_hir = self.convert_expr(ctx, expr)?;
+        if !self.is_exception_type(&expr_hir.ty) {
+            return Err(error::type_error(&format!(
+                "cannot raise {} (not an Exception)",
+                expr_hir.ty.fullname
+            )));
+        }
+        Ok(Hir::raise_expression(expr_hir))
+    }
+
+    /// Return true if `ty` is Exception or its subclass
+    fn is_exception_type(&self, ty: &TermTy) -> bool {
+        match ty.body {
+            TyBody::TyRaw => self
+                .class_dict
+                .ancestor_types(ty)
+                .contains(&ty::raw("Exception")),
+            _ => false,
+        }
+    }
+
     fn convert_lvar_assign(
         &mut self,
         ctx: &mut HirMakerContext,
@@ -171,7 +455,27 @@ impl HirMaker {
         rhs: &AstExpression,
         is_var: &bool,
     ) -> Result<HirExpression, Error> {
-        let expr = self.convert_expr(ctx, rhs)?;
+        let mut expr = self.convert_expr(ctx, rhs)?;
+        // It may be nil again
+        ctx.narrowed_vars.retain(|s| s != name);
+        let is_param = ctx
+            .method_sig
+            .as_ref()
+            .map_or(false, |sig| sig.find_param(name).is_some());
+        if !*is_var && !is_param && !ctx.lvars.contains_key(name) {
+            if let Some((cidx, cap)) = ctx.capture_var(name) {
+                // Reassigning to a variable of the outer scope
+                if cap.readonly {
+                    return Err(error::program_error(&format!(
+                        "cannot reassign to {} (Hint: declare it with `var')",
+                        name
+                    )));
+                }
+                type_checking::check_reassign_var(&self.class_dict, &cap.ty, &expr.ty, name)?;
+                let cap_ty = cap.ty.clone();
+                return Ok(Hir::lambda_capture_write(cidx, Hir::upcast(&cap_ty, expr)));
+            }
+        }
         match ctx.lvars.get(name) {
             Some(lvar) => {
                 // Reassigning
@@ -186,7 +490,8 @@ impl HirMaker {
                         name
                     )));
                 } else {
-                    type_checking::check_reassign_var(&lvar.ty, &expr.ty, name)?;
+                    type_checking::check_reassign_var(&self.class_dict, &lvar.ty, &expr.ty, name)?;
+                    expr = Hir::upcast(&lvar.ty, expr);
                 }
             }
             None => {
@@ -226,14 +531,18 @@ impl HirMaker {
                     name
                 )));
             }
-            if !ivar.ty.equals_to(&expr.ty) {
-                // TODO: Subtype (@obj = 1, etc.)
+            if !expr.ty.conforms_to(&ivar.ty, &self.class_dict) {
                 return Err(error::type_error(&format!(
                     "instance variable `{}' has type {:?} but tried to assign a {:?}",
                     name, ivar.ty, expr.ty
                 )));
             }
-            Ok(Hir::assign_ivar(name, ivar.idx, expr, false))
+            Ok(Hir::assign_ivar(
+                name,
+                ivar.idx,
+                Hir::upcast(&ivar.ty, expr),
+                false,
+            ))
         } else {
             Err(error::program_error(&format!(
                 "instance variable `{}' not found",
@@ -298,34 +607,293 @@ impl HirMaker {
         method_name: &MethodFirstname,
         arg_exprs: &[AstExpression],
     ) -> Result<HirExpression, Error> {
+        // `x == nil`, `nil == x`
+        if method_name.0 == "==" && arg_exprs.len() == 1 {
+            if let Some(expr) = receiver_expr {
+                if is_nil_literal(&arg_exprs[0]) {
+                    return Ok(Hir::is_nil(self.convert_expr(ctx, expr)?));
+                } else if is_nil_literal(expr) {
+                    return Ok(Hir::is_nil(self.convert_expr(ctx, &arg_exprs[0])?));
+                }
+            }
+        }
+
         let receiver_hir = match receiver_expr {
             Some(expr) => self.convert_expr(ctx, &expr)?,
             // Implicit self
             _ => self.convert_self_expr(ctx)?,
         };
-        // TODO: arg types must match with method signature
-        let arg_hirs = arg_exprs
+        let is_self_call = match receiver_expr {
+            Some(expr) => is_self_expr(expr),
+            None => true,
+        };
+        self.check_method_visibility(ctx, &receiver_hir, method_name, is_self_call)?;
+        self.convert_method_call_on(ctx, receiver_hir, method_name, arg_exprs)
+    }
+
+    /// Check the method can be called here
+    /// (Private methods can only be called on `self`. Protected ones can
+    /// also be called on other objects in the methods of the class)
+    fn check_method_visibility(
+        &self,
+        ctx: &HirMakerContext,
+        receiver_hir: &HirExpression,
+        method_name: &MethodFirstname,
+        is_self_call: bool,
+    ) -> Result<(), Error> {
+        let (sig, found_class_name) =
+            match self.class_dict.lookup_method(&receiver_hir.ty, method_name) {
+                Ok(x) => x,
+                // Will be reported by make_method_call
+                Err(_) => return Ok(()),
+            };
+        let visible = match sig.visibility {
+            Visibility::Public => true,
+            // Private class methods can also be called as `Foo._helper` in `Foo`
+            Visibility::Private => {
+                is_self_call
+                    || (found_class_name.is_meta()
+                        && (ctx.self_ty.fullname == found_class_name
+                            || ctx.self_ty.fullname.meta_name() == found_class_name))
+            }
+            Visibility::Protected => {
+                is_self_call || self.class_dict.is_kind_of(&ctx.self_ty, &found_class_name)
+            }
+        };
+        if visible {
+            Ok(())
+        } else {
+            Err(error::program_error(&format!(
+                "{} method `{}' called for {}",
+                if sig.visibility == Visibility::Private {
+                    "private"
+                } else {
+                    "protected"
+                },
+                sig.fullname,
+                receiver_hir.ty
+            )))
+        }
+    }
+
+    /// Convert `x&.foo(args)` into
+    /// `if (tmp = x) == nil then nil else tmp.foo(args) end`
+    fn convert_safe_method_call(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        receiver_expr: &AstExpression,
+        method_name: &MethodFirstname,
+        arg_exprs: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        let receiver_hir = self.convert_expr(ctx, receiver_expr)?;
+        if !receiver_hir.ty.is_nullable() {
+            let is_self_call = is_self_expr(receiver_expr);
+            self.check_method_visibility(ctx, &receiver_hir, method_name, is_self_call)?;
+            return self.convert_method_call_on(ctx, receiver_hir, method_name, arg_exprs);
+        }
+        let nullable_ty = receiver_hir.ty.clone();
+        let tmp_name = self.gensym();
+        let cond_hir = Hir::is_nil(Hir::assign_lvar(&tmp_name, receiver_hir));
+        let non_nil_hir = Hir::bit_cast(
+            nullable_ty.non_nullable().clone(),
+            Hir::lvar_ref(nullable_ty, tmp_name),
+        );
+        self.check_method_visibility(ctx, &non_nil_hir, method_name, false)?;
+        let call_hir = self.convert_method_call_on(ctx, non_nil_hir, method_name, arg_exprs)?;
+        if call_hir.ty.is_void_type() {
+            let then_hirs = HirExpressions::new(vec![call_hir]);
+            return Ok(Hir::if_expression(
+                ty::raw("Void"),
+                Hir::logical_not(cond_hir),
+                then_hirs,
+                None,
+            ));
+        }
+        let ty = ty::nullable(call_hir.ty.clone());
+        let then_hirs = HirExpressions::new(vec![Hir::upcast(&ty, Hir::nil_literal())]);
+        let else_hirs = HirExpressions::new(vec![Hir::upcast(&ty, call_hir)]);
+        Ok(Hir::if_expression(ty, cond_hir, then_hirs, Some(else_hirs)))
+    }
+
+    /// Convert the args and make a method call on `receiver_hir`
+    fn convert_method_call_on(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        receiver_hir: HirExpression,
+        method_name: &MethodFirstname,
+        arg_exprs: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        // Blocks are converted after the other args so that the types of
+        // the block params can be inferred from them
+        // (eg. `acc` of `reduce(0){|acc, x| ...}`)
+        let mut arg_hirs = arg_exprs
             .iter()
-            .map(|arg_expr| self.convert_expr(ctx, arg_expr))
+            .map(|arg_expr| match &arg_expr.body {
+                AstExpressionBody::Block { .. } => Ok(None),
+                _ => self.convert_expr(ctx, arg_expr).map(Some),
+            })
             .collect::<Result<Vec<_>, _>>()?;
+        for (i, arg_expr) in arg_exprs.iter().enumerate() {
+            if let AstExpressionBody::Block { param_names, exprs } = &arg_expr.body {
+                let arg_tys = arg_hirs
+                    .iter()
+                    .map(|arg_hir| arg_hir.as_ref().map(|x| &x.ty))
+                    .collect::<Vec<_>>();
+                let block_hir = self.convert_block(
+                    ctx,
+                    &receiver_hir.ty,
+                    method_name,
+                    i,
+                    &arg_tys,
+                    param_names,
+                    exprs,
+                )?;
+                arg_hirs[i] = Some(block_hir);
+            }
+        }
+        let arg_hirs = arg_hirs.into_iter().map(|x| x.unwrap()).collect();
 
-        self.make_method_call(receiver_hir, &method_name, arg_hirs)
+        self.make_method_call(receiver_hir, method_name, arg_hirs)
     }
 
-    fn make_method_call(
+    /// Convert a block given as the `idx`-th arg of a method call.
+    /// The types of the block params are taken from the type of the
+    /// method param (eg. `Fn1<Int, Void>`)
+    #[allow(clippy::too_many_arguments)]
+    fn convert_block(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        receiver_ty: &TermTy,
+        method_name: &MethodFirstname,
+        idx: usize,
+        arg_tys: &[Option<&TermTy>],
+        param_names: &[String],
+        exprs: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        let (sig, _) = self.class_dict.lookup_method(receiver_ty, method_name)?;
+        // Apply the type arguments inferred from the other args
+        let tyargs = infer_method_tyargs(&sig, arg_tys)
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| {
+                t.unwrap_or_else(|| {
+                    let typaram = &sig.typarams[i];
+                    ty::typaram(
+                        &typaram.name,
+                        TyParamKind::Method,
+                        i,
+                        typaram.upper_bound.clone(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let sig = sig.specialize(&[], &tyargs);
+        let fn_ty = match sig.params.get(idx) {
+            Some(param) => &param.ty,
+            None => {
+                return Err(error::program_error(&format!(
+                    "{} does not take a block",
+                    sig.fullname
+                )))
+            }
+        };
+        let fn_tyargs = match &fn_ty.body {
+            TyBody::TySpe {
+                base_name,
+                type_args,
+            } if *base_name == format!("Fn{}", param_names.len()) => type_args,
+            _ => {
+                return Err(error::type_error(&format!(
+                    "{} takes {} but got a block with {} params",
+                    sig.fullname,
+                    fn_ty,
+                    param_names.len()
+                )))
+            }
+        };
+        let params = param_names
+            .iter()
+            .zip(fn_tyargs.iter())
+            .map(|(name, ty)| MethodParam {
+                name: name.to_string(),
+                ty: ty.clone(),
+            })
+            .collect();
+        let ret_ty = fn_tyargs.last().unwrap();
+        self.make_lambda(ctx, params, exprs, Some(ret_ty))
+    }
+
+    pub(super) fn make_method_call(
         &self,
         receiver_hir: HirExpression,
         method_name: &MethodFirstname,
         arg_hirs: Vec<HirExpression>,
     ) -> Result<HirExpression, Error> {
+        // eg. `Shape.new` where `Shape` is an abstract class
+        if method_name.0 == "new" {
+            if let TyBody::TyMeta {
+                base_fullname: base_name,
+            }
+            | TyBody::TyGenMeta { base_name, .. }
+            | TyBody::TySpeMeta { base_name, .. } = &receiver_hir.ty.body
+            {
+                let base = class_fullname(base_name);
+                if self
+                    .class_dict
+                    .get_class(&base, "make_method_call")
+                    .is_abstract
+                {
+                    return Err(error::program_error(&format!(
+                        "cannot instantiate abstract class `{}'",
+                        base
+                    )));
+                }
+            }
+        }
         let class_fullname = &receiver_hir.ty.fullname;
         let (sig, found_class_name) = self
             .class_dict
             .lookup_method(&receiver_hir.ty, method_name)?;
+        let sig = self
+            .specialize_new(&receiver_hir.ty, &sig, &arg_hirs)?
+            .unwrap_or(sig);
+        let is_generic_method = !sig.typarams.is_empty();
+        let sig = if is_generic_method {
+            let arg_tys = arg_hirs
+                .iter()
+                .map(|expr| Some(&expr.ty))
+                .collect::<Vec<_>>();
+            let tyargs = infer_method_tyargs(&sig, &arg_tys);
+            match tyargs.into_iter().collect::<Option<Vec<_>>>() {
+                Some(tyargs) => {
+                    type_checking::check_tyarg_bounds(
+                        &self.class_dict,
+                        &sig.fullname.to_string(),
+                        &sig.typarams,
+                        &tyargs,
+                    )?;
+                    sig.specialize(&[], &tyargs)
+                }
+                None => {
+                    return Err(error::type_error(&format!(
+                        "cannot infer the type arguments of {}",
+                        sig.fullname
+                    )))
+                }
+            }
+        } else {
+            sig
+        };
 
         let param_tys = arg_hirs.iter().map(|expr| &expr.ty).collect::<Vec<_>>();
-        type_checking::check_method_args(&sig, &param_tys, &receiver_hir, &arg_hirs)?;
-        let bitcast_needed = receiver_hir.ty.is_specialized();
+        type_checking::check_method_args(
+            &self.class_dict,
+            &sig,
+            &param_tys,
+            &receiver_hir,
+            &arg_hirs,
+        )?;
+        let bitcast_needed = receiver_hir.ty.is_specialized() || is_generic_method;
 
         let receiver = if &found_class_name != class_fullname {
             // Upcast needed
@@ -341,51 +909,154 @@ impl HirMaker {
         Ok(ret)
     }
 
+    /// Infer the type arguments of a generic class from the args of `.new`
+    /// (eg. `Pair.new(1, true)` creates a `Pair<Int, Bool>`)
+    /// Returns None if not applicable
+    fn specialize_new(
+        &self,
+        receiver_ty: &TermTy,
+        sig: &MethodSignature,
+        arg_hirs: &[HirExpression],
+    ) -> Result<Option<MethodSignature>, Error> {
+        if sig.first_name().0 != "new" {
+            return Ok(None);
+        }
+        let base_name = match &receiver_ty.body {
+            TyBody::TyMeta { base_fullname } => base_fullname,
+            // The type arguments are given explicitly (eg. `Array<Int>.new`)
+            TyBody::TySpeMeta {
+                base_name,
+                type_args,
+            } => {
+                let mut spe_sig = sig.clone();
+                spe_sig.ret_ty = ty::spe(base_name, type_args.clone());
+                return Ok(Some(spe_sig));
+            }
+            _ => return Ok(None),
+        };
+        let typarams = match self.class_dict.find_class(&class_fullname(base_name)) {
+            Some(cls) => &cls.typarams,
+            None => return Ok(None),
+        };
+        if typarams.is_empty() {
+            return Ok(None);
+        }
+        let mut type_args = vec![None; typarams.len()];
+        for (param, arg_hir) in sig.params.iter().zip(arg_hirs.iter()) {
+            if let TyBody::TyParamRef {
+                kind: TyParamKind::Class,
+                idx,
+                ..
+            } = &param.ty.body
+            {
+                type_args[*idx] = Some(arg_hir.ty.clone());
+            }
+        }
+        // Give up if some of the type arguments are unknown
+        let type_args = match type_args.into_iter().collect::<Option<Vec<_>>>() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        type_checking::check_tyarg_bounds(&self.class_dict, base_name, typarams, &type_args)?;
+        let mut spe_sig = sig.specialize(&type_args, &[]);
+        spe_sig.ret_ty = ty::spe(base_name, type_args);
+        Ok(Some(spe_sig))
+    }
+
     fn convert_lambda(
         &mut self,
         ctx: &mut HirMakerContext,
         params: &[ast::Param],
         exprs: &[AstExpression],
     ) -> Result<HirExpression, Error> {
+        if params.len() > LAMBDA_MAX_ARITY {
+            return Err(error::program_error(&format!(
+                "a lambda cannot take more than {} parameters",
+                LAMBDA_MAX_ARITY
+            )));
+        }
         let hir_params = signature::convert_params(params, &[]);
+        self.make_lambda(ctx, hir_params, exprs, None)
+    }
+
+    /// Create HirLambda. If `ret_ty` is Void, the value of the body is
+    /// discarded
+    fn make_lambda(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        hir_params: Vec<MethodParam>,
+        exprs: &[AstExpression],
+        ret_ty: Option<&TermTy>,
+    ) -> Result<HirExpression, Error> {
         // REFACTOR: consider changing ctx.method_sig to just ctx.method_params
         // (because properties other than `params` are not used)
         let sig = MethodSignature {
             fullname: method_fullname(&class_fullname("(anon)"), "(anon)"),
             ret_ty: ty::raw("(dummy)"),
             params: hir_params.clone(),
+            typarams: vec![],
+            visibility: Visibility::Public,
         };
-        let mut lambda_ctx = HirMakerContext::lambda_ctx(ctx, sig);
-        let hir_exprs = exprs
-            .iter()
-            .map(|expr| self.convert_expr(&mut lambda_ctx, expr))
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(Hir::lambda(hir_params, HirExpressions::new(hir_exprs)))
+        // The lambda ctx owns the current ctx while converting the body
+        // so that it can look up (and capture) the outer variables
+        let outer = std::mem::replace(ctx, HirMakerContext::toplevel());
+        let mut lambda_ctx = HirMakerContext::lambda_ctx(outer, sig);
+        let result = self.convert_exprs(&mut lambda_ctx, exprs);
+        *ctx = lambda_ctx.take_outer();
+        let mut hir_exprs = result?;
+        if ret_ty.map_or(false, |t| t.is_void_type()) {
+            hir_exprs.ty = ty::raw("Void");
+        } else {
+            check_lambda_returns(&self.class_dict, &mut hir_exprs, &lambda_ctx.return_tys)?;
+        }
+        Ok(Hir::lambda(
+            hir_params,
+            hir_exprs,
+            lambda_ctx.captures,
+            lambda_ctx.captured_lvars,
+        ))
     }
 
     /// Generate local variable reference or method call with implicit receiver(self)
-    fn convert_bare_name(&self, ctx: &HirMakerContext, name: &str) -> Result<HirExpression, Error> {
+    fn convert_bare_name(
+        &self,
+        ctx: &mut HirMakerContext,
+        name: &str,
+    ) -> Result<HirExpression, Error> {
+        let expr = self.convert_var_ref(ctx, name)?;
+        if expr.ty.is_nullable()
+            && ctx.narrowed_vars.iter().any(|s| s == name)
+            && !ctx.lambda_written_vars.iter().any(|s| s == name)
+        {
+            // Known to be non-nil
+            let ty = expr.ty.non_nullable().clone();
+            Ok(Hir::bit_cast(ty, expr))
+        } else {
+            Ok(expr)
+        }
+    }
+
+    fn convert_var_ref(
+        &self,
+        ctx: &mut HirMakerContext,
+        name: &str,
+    ) -> Result<HirExpression, Error> {
         // It is a local variable
         if let Some(lvar) = ctx.lvars.get(name) {
             return Ok(Hir::lvar_ref(lvar.ty.clone(), name.to_string()));
         }
         // It is a method parameter
-        let method_sig = match &ctx.method_sig {
-            Some(x) => x,
-            None => {
-                return Err(error::program_error(&format!(
-                    "variable not found: `{}'",
-                    name
-                )))
-            }
-        };
-        match &method_sig.find_param(name) {
-            Some((idx, param)) => Ok(Hir::hir_arg_ref(param.ty.clone(), *idx)),
-            None => Err(error::program_error(&format!(
-                "variable `{}' was not found",
-                name
-            ))),
+        if let Some((idx, param)) = ctx.method_sig.as_ref().and_then(|sig| sig.find_param(name)) {
+            return Ok(Hir::hir_arg_ref(param.ty.clone(), idx));
+        }
+        // It is a variable of the outer scope of the lambda
+        if let Some((idx, cap)) = ctx.capture_var(name) {
+            return Ok(Hir::lambda_capture_ref(cap.ty.clone(), idx));
         }
+        Err(error::program_error(&format!(
+            "variable `{}' was not found",
+            name
+        )))
         // TODO: It may be a nullary method call
     }
 
@@ -399,30 +1070,133 @@ impl HirMaker {
         }
     }
 
-    /// Resolve constant name
+    /// Resolve a constant reference (eg. `FOO`, `A::FOO`).
+    /// The name is searched in the lexical scope (from the innermost
+    /// namespace outwards), then in the superclass chain and finally
+    /// in the toplevel
     fn convert_const_ref(
         &self,
-        _ctx: &HirMakerContext,
+        ctx: &HirMakerContext,
         names: &[String],
     ) -> Result<HirExpression, Error> {
-        // TODO: Resolve using ctx
-        let fullname = ConstFullname("::".to_string() + &names.join("::"));
-        match self.constants.get(&fullname) {
-            Some(ty) => Ok(Hir::const_ref(ty.clone(), fullname)),
-            None => {
-                let c = class_fullname(&names.join("::"));
-                if self.class_dict.class_exists(&c.0) {
-                    Ok(Hir::const_ref(c.class_ty(), fullname))
-                } else {
-                    Err(error::program_error(&format!(
-                        "constant `{:?}' was not found",
-                        fullname
-                    )))
-                }
+        let name = names.join("::");
+        let mut namespace = if ctx.namespace.0.is_empty() {
+            vec![]
+        } else {
+            ctx.namespace.0.split("::").collect::<Vec<_>>()
+        };
+        while !namespace.is_empty() {
+            let mut candidate = namespace.clone();
+            candidate.push(&name);
+            if let Some(found) = self.find_const(&candidate.join("::")) {
+                return Ok(found);
             }
+            namespace.pop();
+        }
+
+        // `A::FOO` may be defined in a superclass of `A`
+        let (owner, const_name) = match names.split_last() {
+            Some((last, init)) if !init.is_empty() => {
+                let owner = self.resolve_const_owner(ctx, &init.join("::"));
+                (owner, last.as_str())
+            }
+            _ => (Some(ctx.namespace.clone()), name.as_str()),
+        };
+        let mut cls = owner.and_then(|o| self.class_dict.find_class(&o));
+        while let Some(c) = cls {
+            let fullname = format!("{}::{}", c.fullname.0, const_name);
+            if let Some(found) = self.find_const(&fullname) {
+                return Ok(found);
+            }
+            cls = c
+                .superclass_fullname
+                .as_ref()
+                .and_then(|s| self.class_dict.find_class(s));
+        }
+
+        if let Some(found) = self.find_const(&name) {
+            return Ok(found);
+        }
+        Err(error::program_error(&format!(
+            "constant `{}' was not found",
+            name
+        )))
+    }
+
+    /// Convert `A<B>` (eg. the receiver of `Array<Int>.new`) into the
+    /// class object typed as `Meta:A<B>`
+    fn convert_specialize_expr(
+        &self,
+        ctx: &HirMakerContext,
+        names: &[String],
+        typ_args: &[ast::Typ],
+    ) -> Result<HirExpression, Error> {
+        let base_hir = self.convert_const_ref(ctx, names)?;
+        let base_name = match &base_hir.ty.body {
+            TyBody::TyMeta { base_fullname } => base_fullname.clone(),
+            _ => {
+                return Err(error::type_error(&format!(
+                    "`{}' is not a class",
+                    names.join("::")
+                )))
+            }
+        };
+        let typarams = &self
+            .class_dict
+            .get_class(&class_fullname(&base_name), "convert_specialize_expr")
+            .typarams;
+        if typarams.len() != typ_args.len() {
+            return Err(error::type_error(&format!(
+                "class `{}' takes {} type argument(s) but got {}",
+                base_name,
+                typarams.len(),
+                typ_args.len()
+            )));
+        }
+        let class_typarams = self
+            .class_dict
+            .find_class(&ctx.namespace)
+            .map_or(&[][..], |cls| &cls.typarams);
+        let type_args = typ_args
+            .iter()
+            .map(|typ| signature::convert_typ(typ, class_typarams, ctx.method_typarams()))
+            .collect::<Vec<_>>();
+        type_checking::check_tyarg_bounds(&self.class_dict, &base_name, typarams, &type_args)?;
+        Ok(Hir::bit_cast(ty::spe_meta(&base_name, type_args), base_hir))
+    }
+
+    /// Return the class named `name` seen from the current namespace
+    fn resolve_const_owner(&self, ctx: &HirMakerContext, name: &str) -> Option<ClassFullname> {
+        let mut namespace = if ctx.namespace.0.is_empty() {
+            vec![]
+        } else {
+            ctx.namespace.0.split("::").collect::<Vec<_>>()
+        };
+        loop {
+            let mut candidate = namespace.clone();
+            candidate.push(name);
+            let fullname = candidate.join("::");
+            if self.class_dict.class_exists(&fullname) {
+                return Some(class_fullname(fullname));
+            }
+            namespace.pop()?;
         }
     }
 
+    /// Return a reference to the constant or the class named `fullname`
+    /// (without the leading `::`), if any
+    fn find_const(&self, fullname: &str) -> Option<HirExpression> {
+        let const_name = const_fullname(&format!("::{}", fullname));
+        if let Some(ty) = self.constants.get(&const_name) {
+            return Some(Hir::const_ref(ty.clone(), const_name));
+        }
+        if self.class_dict.class_exists(fullname) {
+            let c = class_fullname(fullname);
+            return Some(Hir::const_ref(c.class_ty(), const_name));
+        }
+        None
+    }
+
     fn convert_pseudo_variable(
         &self,
         ctx: &HirMakerContext,
@@ -432,6 +1206,7 @@ impl HirMaker {
             Token::KwSelf => self.convert_self_expr(ctx),
             Token::KwTrue => Ok(Hir::boolean_literal(true)),
             Token::KwFalse => Ok(Hir::boolean_literal(false)),
+            Token::KwNil => Ok(Hir::nil_literal()),
             _ => panic!("[BUG] not a pseudo variable token: {:?}", token),
         }
     }
@@ -451,7 +1226,7 @@ impl HirMaker {
         // TODO #102: Support empty array literal
         let mut item_ty = item_exprs[0].ty.clone();
         for expr in &item_exprs {
-            item_ty = self.nearest_common_ancestor_type(&item_ty, &expr.ty)
+            item_ty = self.nearest_common_ancestor_type(&item_ty, &expr.ty)?
         }
         let ary_ty = ty::spe("Array", vec![item_ty]);
         let upper_bound_ty = ty::raw("Object");
@@ -466,7 +1241,7 @@ impl HirMaker {
                 ary_ty.clone(),
                 Hir::const_ref(ty::meta("Array"), const_fullname("::Array")),
                 method_fullname(&class_fullname("Meta:Array"), "new"),
-                vec![Hir::decimal_literal(item_exprs.len() as i32)],
+                vec![Hir::decimal_literal(item_exprs.len() as i64)],
             ),
         ));
         // `tmp.push(item)`
@@ -483,6 +1258,62 @@ impl HirMaker {
         Ok(Hir::array_literal(exprs, ary_ty))
     }
 
+    /// Generate HIR for a hash literal
+    /// `{k => v}` is expanded into `tmp = Hash<K, V>.new; tmp[k] = v`
+    fn convert_hash_literal(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        pairs: &[(AstExpression, AstExpression)],
+    ) -> Result<HirExpression, Error> {
+        let pairs = pairs
+            .iter()
+            .map(|(k, v)| Ok((self.convert_expr(ctx, k)?, self.convert_expr(ctx, v)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if pairs.is_empty() {
+            return Err(error::type_error(
+                "cannot infer the type of an empty hash literal `{}'",
+            ));
+        }
+        let mut key_ty = pairs[0].0.ty.clone();
+        let mut value_ty = pairs[0].1.ty.clone();
+        for (k, v) in &pairs {
+            key_ty = self.nearest_common_ancestor_type(&key_ty, &k.ty)?;
+            value_ty = self.nearest_common_ancestor_type(&value_ty, &v.ty)?;
+        }
+        let hash_ty = ty::spe("Hash", vec![key_ty, value_ty]);
+        let upper_bound_ty = ty::raw("Object");
+
+        let tmp = self.gensym();
+        let mut exprs = vec![];
+
+        // `tmp = Hash.new`
+        exprs.push(Hir::assign_lvar(
+            &tmp,
+            Hir::method_call(
+                hash_ty.clone(),
+                Hir::const_ref(ty::meta("Hash"), const_fullname("::Hash")),
+                method_fullname(&class_fullname("Meta:Hash"), "new"),
+                vec![],
+            ),
+        ));
+        // `tmp[key] = value`
+        for (k, v) in pairs {
+            exprs.push(Hir::method_call(
+                ty::raw("Void"),
+                Hir::lvar_ref(hash_ty.clone(), tmp.clone()),
+                method_fullname(&class_fullname("Hash"), "[]="),
+                vec![
+                    Hir::bit_cast(upper_bound_ty.clone(), k),
+                    Hir::bit_cast(upper_bound_ty.clone(), v),
+                ],
+            ))
+        }
+        exprs.push(Hir::lvar_ref(hash_ty.clone(), tmp));
+
+        Ok(Hir::hash_literal(exprs, hash_ty))
+    }
+
     fn convert_self_expr(&self, ctx: &HirMakerContext) -> Result<HirExpression, Error> {
         Ok(Hir::self_expression(ctx.self_ty.clone()))
     }
@@ -492,6 +1323,70 @@ impl HirMaker {
         Ok(Hir::string_literal(idx))
     }
 
+    /// Generate HIR for a string with interpolation
+    /// `"a#{b}"` is expanded into
+    /// `tmp = MutableString.new; tmp.append("a"); tmp.append(b.to_s); tmp._unsafe_to_s`
+    fn convert_string_interpolation(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        parts: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        let str_ty = ty::raw("String");
+        let mut_str_ty = ty::raw("MutableString");
+        let capa = parts
+            .iter()
+            .map(|part| match &part.body {
+                AstExpressionBody::StringLiteral { content } => content.len(),
+                _ => 0,
+            })
+            .sum::<usize>();
+
+        let tmp = self.gensym();
+        let mut exprs = vec![];
+
+        // `tmp = MutableString.new`
+        exprs.push(Hir::assign_lvar(
+            &tmp,
+            Hir::method_call(
+                mut_str_ty.clone(),
+                Hir::const_ref(ty::meta("MutableString"), const_fullname("::MutableString")),
+                method_fullname(&class_fullname("Meta:MutableString"), "new"),
+                vec![Hir::decimal_literal(capa as i64)],
+            ),
+        ));
+        // `tmp.append(part.to_s)`
+        for part in parts {
+            let part_hir = self.convert_expr(ctx, part)?;
+            let str_hir = if part_hir.ty == str_ty {
+                part_hir
+            } else {
+                self.convert_method_call_on(ctx, part_hir, &method_firstname("to_s"), &[])?
+            };
+            if str_hir.ty != str_ty {
+                return Err(error::type_error(&format!(
+                    "to_s must return String but returns {}",
+                    str_hir.ty
+                )));
+            }
+            exprs.push(Hir::method_call(
+                ty::raw("Void"),
+                Hir::lvar_ref(mut_str_ty.clone(), tmp.clone()),
+                method_fullname(&class_fullname("MutableString"), "append"),
+                vec![str_hir],
+            ));
+        }
+        // The buffer is not modified after this, so copying is not needed.
+        // (`_unsafe_to_s` is private but calls made here are not checked)
+        exprs.push(Hir::method_call(
+            str_ty.clone(),
+            Hir::lvar_ref(mut_str_ty, tmp),
+            method_fullname(&class_fullname("MutableString"), "_unsafe_to_s"),
+            vec![],
+        ));
+
+        Ok(Hir::string_interpolation(exprs, str_ty))
+    }
+
     pub(super) fn register_string_literal(&mut self, content: &str) -> usize {
         let idx = self.str_literals.len();
         self.str_literals.push(content.to_string());
@@ -499,14 +1394,251 @@ impl HirMaker {
     }
 
     /// Return the nearest common ancestor of the classes
-    fn nearest_common_ancestor_type(&self, ty1: &TermTy, ty2: &TermTy) -> TermTy {
+    /// Returns Err if there is none
+    pub(super) fn nearest_common_ancestor_type(
+        &self,
+        ty1: &TermTy,
+        ty2: &TermTy,
+    ) -> Result<TermTy, Error> {
+        // eg. `Int` and `nil` makes `Int?`
+        if ty1.is_nullable() || ty2.is_nullable() {
+            let t1 = ty1.non_nullable();
+            let t2 = ty2.non_nullable();
+            return Ok(if t1.is_never_type() {
+                ty::nullable(t2.clone())
+            } else if t2.is_never_type() {
+                ty::nullable(t1.clone())
+            } else {
+                ty::nullable(self.nearest_common_ancestor_type(t1, t2)?)
+            });
+        }
         let ancestors1 = self.class_dict.ancestor_types(ty1);
         let ancestors2 = self.class_dict.ancestor_types(ty2);
         for t2 in ancestors2 {
             if let Some(eq) = ancestors1.iter().find(|t1| t1.equals_to(&t2)) {
-                return eq.clone();
+                return Ok(eq.clone());
+            }
+        }
+        Err(error::type_error(&format!(
+            "incompatible types: {} and {}",
+            ty1, ty2
+        )))
+    }
+
+    /// Decide the type of an `if` expression and upcast the value of each
+    /// clause to it
+    fn unify_if_branches(
+        &self,
+        then_hirs: &mut HirExpressions,
+        else_hirs: &mut Option<HirExpressions>,
+    ) -> Result<TermTy, Error> {
+        let ty = self.if_expr_ty(then_hirs, else_hirs)?;
+        if !ty.is_void_type() {
+            then_hirs.upcast(&ty);
+            if let Some(hirs) = else_hirs {
+                hirs.upcast(&ty);
+            }
+        }
+        Ok(ty)
+    }
+
+    /// Type of an `if` expression
+    fn if_expr_ty(
+        &self,
+        then_hirs: &HirExpressions,
+        else_hirs: &Option<HirExpressions>,
+    ) -> Result<TermTy, Error> {
+        let else_hirs = match else_hirs {
+            Some(hirs) => hirs,
+            // eg. `return 0 if n < 1`
+            None if then_hirs.ty.is_never_type() => return Ok(ty::raw("Void")),
+            None => return Ok(then_hirs.ty.clone()),
+        };
+        let (t1, t2) = (&then_hirs.ty, &else_hirs.ty);
+        if t1.is_never_type() {
+            Ok(t2.clone())
+        } else if t2.is_never_type() {
+            Ok(t1.clone())
+        } else if t1.is_void_type() || t2.is_void_type() {
+            // The value is not used (eg. `if a then puts "x" else b = 1 end`)
+            Ok(ty::raw("Void"))
+        } else {
+            // eg. `if a then 1 else nil end` is `Int?`
+            self.nearest_common_ancestor_type(t1, t2).map_err(|_| {
+                error::type_error(&format!(
+                    "the clauses of `if' have incompatible types ({} and {})",
+                    t1, t2
+                ))
+            })
+        }
+    }
+}
+
+/// Infer the type arguments of a generic method from the types of the
+/// args (None for the args not known yet.)
+/// Returns None for the type arguments which cannot be inferred
+fn infer_method_tyargs(sig: &MethodSignature, arg_tys: &[Option<&TermTy>]) -> Vec<Option<TermTy>> {
+    let mut tyargs = vec![None; sig.typarams.len()];
+    for (param, arg_ty) in sig.params.iter().zip(arg_tys.iter()) {
+        if let Some(t) = arg_ty {
+            infer_tyargs(&param.ty, t, &mut tyargs);
+        }
+    }
+    tyargs
+}
+
+/// Find the method type parameters in `param_ty` and set the
+/// corresponding part of `arg_ty` to `tyargs`
+/// (eg. `U` of `Fn1<T, U>` is `String` if the arg is `Fn1<Int, String>`)
+fn infer_tyargs(param_ty: &TermTy, arg_ty: &TermTy, tyargs: &mut [Option<TermTy>]) {
+    match (&param_ty.body, &arg_ty.body) {
+        (
+            TyBody::TyParamRef {
+                kind: TyParamKind::Method,
+                idx,
+                ..
+            },
+            _,
+        ) => {
+            if tyargs[*idx].is_none() && !arg_ty.is_never_type() {
+                tyargs[*idx] = Some(arg_ty.clone());
+            }
+        }
+        (
+            TyBody::TySpe {
+                base_name: base1,
+                type_args: args1,
+            },
+            TyBody::TySpe {
+                base_name: base2,
+                type_args: args2,
+            },
+        ) if base1 == base2 => {
+            for (p, a) in args1.iter().zip(args2.iter()) {
+                infer_tyargs(p, a, tyargs);
+            }
+        }
+        // eg. `T` of `T?` is `Int` if the arg is `Int` or `Int?`
+        (TyBody::TyNullable { inner }, _) => {
+            infer_tyargs(inner, arg_ty.non_nullable(), tyargs);
+        }
+        _ => (),
+    }
+}
+
+/// Returns the variables in `names` which cannot be reassigned
+/// (`names` are local variables or method parameters)
+/// Return the names of the variables reassigned in the lambdas in `exprs`
+pub(super) fn lambda_written_vars(exprs: &[AstExpression]) -> Vec<String> {
+    fn collect(expr: &AstExpression, in_lambda: bool, names: &mut Vec<String>) {
+        let in_lambda = in_lambda
+            || match expr.body {
+                AstExpressionBody::Lambda { .. } | AstExpressionBody::Block { .. } => true,
+                _ => false,
+            };
+        if let AstExpressionBody::LVarAssign { name, is_var, .. } = &expr.body {
+            if in_lambda && !is_var && !names.contains(name) {
+                names.push(name.to_string());
             }
         }
-        panic!("[BUG] nearest_common_ancestor_type not found");
+        for child in expr.children() {
+            collect(child, in_lambda, names);
+        }
+    }
+    let mut names = vec![];
+    for expr in exprs {
+        collect(expr, false, &mut names);
+    }
+    names
+}
+
+fn readonly_vars(ctx: &HirMakerContext, names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| ctx.lvars.get(*name).map_or(true, |lvar| lvar.readonly))
+        .cloned()
+        .collect()
+}
+
+fn is_self_expr(expr: &AstExpression) -> bool {
+    match expr.body {
+        AstExpressionBody::PseudoVariable(Token::KwSelf) => true,
+        _ => false,
+    }
+}
+
+fn is_nil_literal(expr: &AstExpression) -> bool {
+    match expr.body {
+        AstExpressionBody::PseudoVariable(Token::KwNil) => true,
+        _ => false,
+    }
+}
+
+/// If `cond_expr` is `x == nil` or `x != nil`, returns `x` and whether
+/// the condition holds when `x` is nil
+fn nil_check_target(cond_expr: &AstExpression) -> Option<(&str, bool)> {
+    match &cond_expr.body {
+        AstExpressionBody::LogicalNot { expr } => {
+            nil_check_target(expr).map(|(name, when_nil)| (name, !when_nil))
+        }
+        AstExpressionBody::MethodCall {
+            receiver_expr: Some(receiver_expr),
+            method_name,
+            arg_exprs,
+            ..
+        } if method_name.0 == "==" && arg_exprs.len() == 1 => {
+            match (&receiver_expr.body, &arg_exprs[0].body) {
+                (AstExpressionBody::BareName(name), _) if is_nil_literal(&arg_exprs[0]) => {
+                    Some((name.as_str(), true))
+                }
+                (_, AstExpressionBody::BareName(name)) if is_nil_literal(receiver_expr) => {
+                    Some((name.as_str(), true))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns `x` if `expr` is like `return if x == nil`
+fn narrowed_after<'a>(expr: &'a AstExpression, hir_expr: &HirExpression) -> Option<&'a str> {
+    match (&expr.body, &hir_expr.node) {
+        (
+            AstExpressionBody::If {
+                cond_expr,
+                else_exprs: None,
+                ..
+            },
+            HirExpressionBase::HirIfExpression { then_exprs, .. },
+        ) if then_exprs.ty.is_never_type() => match nil_check_target(cond_expr) {
+            Some((name, true)) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check the values of `return` in a lambda conform to the value of
+/// its body. If the body ends with `return`, its type is decided by
+/// the value of `return`
+fn check_lambda_returns(
+    class_dict: &ClassDict,
+    hir_exprs: &mut HirExpressions,
+    return_tys: &[TermTy],
+) -> Result<(), Error> {
+    if hir_exprs.ty.is_never_type() {
+        if let Some(t) = return_tys.iter().find(|t| !t.is_never_type()) {
+            hir_exprs.ty = t.clone();
+        }
+    }
+    for t in return_tys {
+        if !t.conforms_to(&hir_exprs.ty, class_dict) {
+            return Err(error::type_error(&format!(
+                "the lambda should return {} but returns {}",
+                hir_exprs.ty.fullname, t.fullname
+            )));
+        }
     }
+    Ok(())
 }
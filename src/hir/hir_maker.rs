@@ -3,6 +3,7 @@ use crate::code_gen::CodeGen;
 use crate::error::Error;
 use crate::hir;
 use crate::hir::class_dict::ClassDict;
+use crate::hir::convert_exprs::lambda_written_vars;
 use crate::hir::hir_maker_context::*;
 use crate::hir::method_dict::MethodDict;
 use crate::hir::*;
@@ -37,8 +38,11 @@ pub fn make_hir(ast: ast::Program, corelib: Corelib) -> Result<Hir, Error> {
 fn convert_program(class_dict: ClassDict, prog: ast::Program) -> Result<Hir, Error> {
     let mut hir_maker = HirMaker::new(class_dict);
     hir_maker.register_class_consts();
-    let main_exprs = hir_maker.convert_toplevel_items(&prog.toplevel_items)?;
-    Ok(hir_maker.extract_hir(main_exprs))
+    let (main_exprs, main_captured_lvars) =
+        hir_maker.convert_toplevel_items(&prog.toplevel_items)?;
+    // Checked here because accessors may implement the abstract methods
+    hir_maker.class_dict.check_abstract_methods()?;
+    Ok(hir_maker.extract_hir(main_exprs, main_captured_lvars))
 }
 
 impl HirMaker {
@@ -54,7 +58,7 @@ impl HirMaker {
     }
 
     /// Destructively convert self to Hir
-    fn extract_hir(&mut self, main_exprs: HirExpressions) -> Hir {
+    fn extract_hir(&mut self, main_exprs: HirExpressions, main_captured_lvars: Vec<String>) -> Hir {
         // Extract data from self
         let sk_classes = std::mem::replace(&mut self.class_dict.sk_classes, HashMap::new());
         let sk_methods = std::mem::take(&mut self.method_dict.sk_methods);
@@ -75,6 +79,7 @@ impl HirMaker {
             str_literals,
             const_inits,
             main_exprs,
+            main_captured_lvars,
         }
     }
 
@@ -93,7 +98,7 @@ impl HirMaker {
     fn register_class_const(&mut self, fullname: &ClassFullname) {
         let instance_ty = ty::raw(&fullname.0);
         let class_ty = instance_ty.meta_ty();
-        let const_name = const_fullname(&format!("::{}", &fullname.0));
+        let const_name = fullname.to_const_fullname();
 
         // eg. Constant `A` holds the class A
         self.constants.insert(const_name.clone(), class_ty);
@@ -107,10 +112,18 @@ impl HirMaker {
     fn convert_toplevel_items(
         &mut self,
         items: &[ast::TopLevelItem],
-    ) -> Result<HirExpressions, Error> {
+    ) -> Result<(HirExpressions, Vec<String>), Error> {
         let mut main_exprs = vec![];
         // Contains local vars defined at toplevel
         let mut top_ctx = HirMakerContext::toplevel();
+        let top_exprs = items
+            .iter()
+            .filter_map(|item| match item {
+                ast::TopLevelItem::Expr(expr) => Some(expr.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        top_ctx.lambda_written_vars = lambda_written_vars(&top_exprs);
         for item in items {
             match item {
                 ast::TopLevelItem::Def(def) => {
@@ -121,7 +134,7 @@ impl HirMaker {
                 }
             }
         }
-        Ok(HirExpressions::new(main_exprs))
+        Ok((HirExpressions::new(main_exprs), top_ctx.captured_lvars))
     }
 
     fn process_toplevel_def(
@@ -131,10 +144,21 @@ impl HirMaker {
     ) -> Result<(), Error> {
         match def {
             // Extract instance/class methods
-            ast::Definition::ClassDefinition { name, defs, .. } => {
+            ast::Definition::ClassDefinition { name, defs, .. }
+            | ast::Definition::ModuleDefinition { name, defs } => {
                 let full = name.add_namespace("");
                 self.collect_sk_methods(&full, defs)?;
             }
+            ast::Definition::EnumDefinition {
+                name, cases, defs, ..
+            } => {
+                let full = name.add_namespace("");
+                self.collect_enum_methods(&full, cases, defs)?;
+            }
+            // Interfaces have no methods to convert
+            ast::Definition::InterfaceDefinition { name, .. } => {
+                self.register_meta_ivar(&name.add_namespace(""))?;
+            }
             ast::Definition::ConstDefinition { name, expr } => {
                 self.register_const(ctx, name, expr)?;
             }
@@ -154,6 +178,21 @@ impl HirMaker {
         Ok(())
     }
 
+    /// Extract methods of an enum and its cases
+    fn collect_enum_methods(
+        &mut self,
+        fullname: &ClassFullname,
+        cases: &[ast::EnumCase],
+        defs: &[ast::Definition],
+    ) -> Result<(), Error> {
+        self.collect_sk_methods(&fullname, defs)?;
+        for case in cases {
+            let full = case.name.add_namespace(&fullname.0);
+            self.collect_sk_methods(&full, &case.class_defs())?;
+        }
+        Ok(())
+    }
+
     fn register_meta_ivar(&mut self, name: &ClassFullname) -> Result<(), Error> {
         let mut meta_ivars = HashMap::new();
         meta_ivars.insert(
@@ -178,6 +217,14 @@ impl HirMaker {
         let meta_name = fullname.meta_name();
         let mut ctx = HirMakerContext::class_ctx(&fullname);
 
+        // Register constants first so that methods can refer to them
+        // regardless of the order of definition
+        for def in defs {
+            if let ast::Definition::ConstDefinition { name, expr } = def {
+                self.register_const(&mut ctx, name, expr)?;
+            }
+        }
+
         // Add `#initialize`
         let mut own_ivars = HashMap::default();
         if let Some(ast::Definition::InstanceMethodDefinition {
@@ -190,9 +237,17 @@ impl HirMaker {
             own_ivars = found_ivars;
         }
         self.define_ivars(fullname, own_ivars, defs)?;
-
-        // Add `.new`
-        if has_new(&fullname) {
+        type_checking::check_variance(
+            &self.class_dict,
+            self.class_dict
+                .get_class(&fullname, "HirMaker::process_defs"),
+        )?;
+
+        // Add `.new` (modules and abstract classes cannot be instantiated)
+        let class = self
+            .class_dict
+            .get_class(&fullname, "HirMaker::process_defs");
+        if has_new(&fullname) && !class.is_module && !class.is_abstract {
             self.method_dict
                 .add_method(&meta_name, self.create_new(&fullname)?);
         }
@@ -213,13 +268,28 @@ impl HirMaker {
                         self.convert_method_def(&ctx, &meta_name, &sig.name, &body_exprs)?;
                     self.method_dict.add_method(&meta_name, method);
                 }
-                ast::Definition::ConstDefinition { name, expr } => {
-                    self.register_const(&mut ctx, name, expr)?;
+                ast::Definition::AbstractMethodDefinition { sig } => {
+                    let method = self.create_abstract_method(&fullname, &sig.name);
+                    self.method_dict.add_method(&fullname, method);
                 }
-                ast::Definition::ClassDefinition { name, defs, .. } => {
+                // Already registered above
+                ast::Definition::ConstDefinition { .. } => (),
+                ast::Definition::ClassDefinition { name, defs, .. }
+                | ast::Definition::ModuleDefinition { name, defs } => {
                     let full = name.add_namespace(&fullname.0);
                     self.collect_sk_methods(&full, defs)?;
                 }
+                ast::Definition::EnumDefinition {
+                    name, cases, defs, ..
+                } => {
+                    let full = name.add_namespace(&fullname.0);
+                    self.collect_enum_methods(&full, cases, defs)?;
+                }
+                ast::Definition::InterfaceDefinition { name, .. } => {
+                    self.register_meta_ivar(&name.add_namespace(&fullname.0))?;
+                }
+                // Already processed in hir::class_dict
+                ast::Definition::Include { .. } => (),
             }
         }
         Ok(())
@@ -325,15 +395,19 @@ impl HirMaker {
         ))
     }
 
-    /// Register a constant
+    /// Register a constant under the current namespace
+    /// (eg. `FOO` in `class A` is registered as `::A::FOO`)
     pub(super) fn register_const(
         &mut self,
         ctx: &mut HirMakerContext,
         name: &ConstFirstname,
         expr: &AstExpression,
     ) -> Result<ConstFullname, Error> {
-        // TODO: resolve name using ctx
-        let fullname = const_fullname(&format!("{}::{}", ctx.namespace.0, &name.0));
+        let fullname = if ctx.namespace.0.is_empty() {
+            const_fullname(&format!("::{}", &name.0))
+        } else {
+            const_fullname(&format!("::{}::{}", ctx.namespace.0, &name.0))
+        };
         let hir_expr = self.convert_expr(ctx, expr)?;
         self.constants.insert(fullname.clone(), hir_expr.ty.clone());
         let op = Hir::assign_const(fullname.clone(), hir_expr);
@@ -378,15 +452,43 @@ impl HirMaker {
         if let Some(x) = super_ivars {
             method_ctx.super_ivars = x;
         }
+        method_ctx.lambda_written_vars = lambda_written_vars(body_exprs);
 
-        let body_exprs = self.convert_exprs(&mut method_ctx, body_exprs)?;
-        type_checking::check_return_value(&signature, &body_exprs.ty)?;
+        let mut body_exprs = self.convert_exprs(&mut method_ctx, body_exprs)?;
+        type_checking::check_return_value(&self.class_dict, &signature, &body_exprs.ty)?;
+        if !signature.ret_ty.is_void_type() {
+            body_exprs.upcast(&signature.ret_ty);
+        }
 
-        let body = SkMethodBody::ShiikaMethodBody { exprs: body_exprs };
+        let body = SkMethodBody::ShiikaMethodBody {
+            exprs: body_exprs,
+            captured_lvars: method_ctx.captured_lvars,
+        };
 
         Ok((SkMethod { signature, body }, method_ctx.iivars))
     }
 
+    /// Create an abstract method
+    /// (It is never called because abstract classes have no instances and
+    /// the subclasses must override it)
+    fn create_abstract_method(
+        &self,
+        class_fullname: &ClassFullname,
+        name: &MethodFirstname,
+    ) -> SkMethod {
+        let signature = self
+            .class_dict
+            .find_method(class_fullname, name)
+            .expect("[BUG] signature of abstract method not found")
+            .clone();
+        SkMethod {
+            signature,
+            body: SkMethodBody::RustMethodBody {
+                gen: gen_abstract_method_body,
+            },
+        }
+    }
+
     /// Generate unique variable name
     pub(super) fn gensym(&mut self) -> String {
         self.gensym_ct += 1;
@@ -395,6 +497,14 @@ impl HirMaker {
     }
 }
 
+fn gen_abstract_method_body(
+    code_gen: &CodeGen,
+    _function: &inkwell::values::FunctionValue,
+) -> Result<(), Error> {
+    code_gen.builder.build_unreachable();
+    Ok(())
+}
+
 // Whether the class has .new
 fn has_new(fullname: &ClassFullname) -> bool {
     // TODO: maybe more?
@@ -16,6 +16,24 @@ pub struct HirMakerContext {
     pub namespace: ClassFullname,
     /// Current local variables
     pub lvars: HashMap<String, CtxLVar>,
+    /// Local variables captured (by reference) by lambdas
+    pub captured_lvars: Vec<String>,
+    /// Nullable variables known to be non-nil here
+    /// (eg. `x` in `if x != nil ... end`)
+    pub narrowed_vars: Vec<String>,
+    /// Variables reassigned in a lambda. They are never narrowed because
+    /// calling the lambda may make them nil
+    pub lambda_written_vars: Vec<String>,
+
+    //
+    // lambda-related stuffs
+    //
+    /// The context surrounding the lambda. None if not in a lambda
+    pub outer: Option<Box<HirMakerContext>>,
+    /// Variables of the outer scopes used in the lambda
+    pub captures: Vec<HirLambdaCapture>,
+    /// Types of the values of `return` in the lambda
+    pub return_tys: Vec<TermTy>,
 
     //
     // ivar-related stuffs
@@ -36,6 +54,12 @@ impl HirMakerContext {
             self_ty: ty::raw("Object"),
             namespace: ClassFullname("".to_string()),
             lvars: HashMap::new(),
+            captured_lvars: vec![],
+            narrowed_vars: vec![],
+            lambda_written_vars: vec![],
+            outer: None,
+            captures: vec![],
+            return_tys: vec![],
             iivars: HashMap::new(),
             is_initializer: false,
             super_ivars: HashMap::new(),
@@ -49,6 +73,12 @@ impl HirMakerContext {
             self_ty: ty::raw("Object"),
             namespace: fullname.clone(),
             lvars: HashMap::new(),
+            captured_lvars: vec![],
+            narrowed_vars: vec![],
+            lambda_written_vars: vec![],
+            outer: None,
+            captures: vec![],
+            return_tys: vec![],
             iivars: HashMap::new(),
             is_initializer: false,
             super_ivars: HashMap::new(),
@@ -66,6 +96,12 @@ impl HirMakerContext {
             self_ty: ty::raw(&class_ctx.namespace.0),
             namespace: class_ctx.namespace.clone(),
             lvars: HashMap::new(),
+            captured_lvars: vec![],
+            narrowed_vars: vec![],
+            lambda_written_vars: vec![],
+            outer: None,
+            captures: vec![],
+            return_tys: vec![],
             iivars: HashMap::new(),
             is_initializer,
             super_ivars: HashMap::new(),
@@ -73,20 +109,82 @@ impl HirMakerContext {
     }
 
     /// Create a ctx for lambda
-    pub fn lambda_ctx(
-        method_ctx: &HirMakerContext,
-        lambda_sig: MethodSignature,
-    ) -> HirMakerContext {
+    /// (`outer` is given back by `HirMakerContext::take_outer`)
+    pub fn lambda_ctx(outer: HirMakerContext, lambda_sig: MethodSignature) -> HirMakerContext {
         HirMakerContext {
             method_sig: Some(lambda_sig),
-            self_ty: method_ctx.self_ty.clone(),
-            namespace: method_ctx.namespace.clone(),
+            self_ty: outer.self_ty.clone(),
+            namespace: outer.namespace.clone(),
             lvars: HashMap::new(),
+            captured_lvars: vec![],
+            narrowed_vars: vec![],
+            lambda_written_vars: outer.lambda_written_vars.clone(),
+            outer: Some(Box::new(outer)),
+            captures: vec![],
+            return_tys: vec![],
             iivars: HashMap::new(),
             is_initializer: false,
             super_ivars: HashMap::new(),
         }
     }
+
+    /// Return the type parameters of the current method
+    /// (For a lambda, those of the method it is in)
+    pub fn method_typarams(&self) -> &[TyParam] {
+        match &self.outer {
+            Some(outer) => outer.method_typarams(),
+            None => self.method_sig.as_ref().map_or(&[], |sig| &sig.typarams),
+        }
+    }
+
+    /// Take the outer ctx out of a lambda ctx
+    pub fn take_outer(&mut self) -> HirMakerContext {
+        *self.outer.take().expect("[BUG] not a lambda ctx")
+    }
+
+    /// Find a variable named `name` in the outer scopes of this lambda and
+    /// capture it. Returns the index in `captures` and the capture
+    pub fn capture_var(&mut self, name: &str) -> Option<(usize, &HirLambdaCapture)> {
+        if let Some(idx) = self.captures.iter().position(|c| c.name == name) {
+            return Some((idx, &self.captures[idx]));
+        }
+        let outer = self.outer.as_mut()?;
+        let capture = if let Some(lvar) = outer.lvars.get(name) {
+            // Writable lvars are shared with the outer scope
+            if !lvar.readonly && !outer.captured_lvars.iter().any(|s| s == name) {
+                outer.captured_lvars.push(name.to_string());
+            }
+            HirLambdaCapture {
+                name: name.to_string(),
+                ty: lvar.ty.clone(),
+                readonly: lvar.readonly,
+                detail: HirLambdaCaptureDetail::CaptureLVar,
+            }
+        } else if let Some((idx, param)) = outer
+            .method_sig
+            .as_ref()
+            .and_then(|sig| sig.find_param(name))
+        {
+            HirLambdaCapture {
+                name: name.to_string(),
+                ty: param.ty.clone(),
+                readonly: true,
+                detail: HirLambdaCaptureDetail::CaptureArg { idx },
+            }
+        } else {
+            // Capture it in the outer lambda too, if any
+            let (cidx, cap) = outer.capture_var(name)?;
+            HirLambdaCapture {
+                name: name.to_string(),
+                ty: cap.ty.clone(),
+                readonly: cap.readonly,
+                detail: HirLambdaCaptureDetail::CaptureFwd { cidx },
+            }
+        };
+        self.captures.push(capture);
+        let idx = self.captures.len() - 1;
+        Some((idx, &self.captures[idx]))
+    }
 }
 
 /// A local variable
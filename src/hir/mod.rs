@@ -4,6 +4,7 @@ mod convert_exprs;
 mod hir_maker;
 mod hir_maker_context;
 mod method_dict;
+mod pattern_match;
 pub mod signature;
 mod sk_class;
 use crate::ast;
@@ -22,6 +23,8 @@ pub struct Hir {
     pub str_literals: Vec<String>,
     pub const_inits: Vec<HirExpression>,
     pub main_exprs: HirExpressions,
+    /// Local variables of the toplevel captured by lambdas
+    pub main_captured_lvars: Vec<String>,
 }
 
 pub fn build(ast: ast::Program, corelib: Corelib) -> Result<Hir, crate::error::Error> {
@@ -60,9 +63,17 @@ pub struct SkMethod {
 }
 
 pub enum SkMethodBody {
-    ShiikaMethodBody { exprs: HirExpressions },
-    RustMethodBody { gen: GenMethodBody },
-    RustClosureMethodBody { boxed_gen: Box<ClosureMethodBody> },
+    ShiikaMethodBody {
+        exprs: HirExpressions,
+        /// Local variables captured by lambdas in this method
+        captured_lvars: Vec<String>,
+    },
+    RustMethodBody {
+        gen: GenMethodBody,
+    },
+    RustClosureMethodBody {
+        boxed_gen: Box<ClosureMethodBody>,
+    },
 }
 // Manually deriving because GenMethodBody is a function (auto-deriving seems unsupported)
 impl std::fmt::Debug for SkMethodBody {
@@ -98,6 +109,15 @@ impl HirExpressions {
 
         HirExpressions { ty, exprs }
     }
+
+    /// Cast the value of the last expression to `ty`
+    pub fn upcast(&mut self, ty: &TermTy) {
+        if let Some(last_expr) = self.exprs.pop() {
+            let expr = Hir::upcast(ty, last_expr);
+            self.ty = expr.ty.clone();
+            self.exprs.push(expr);
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -106,6 +126,56 @@ pub struct HirExpression {
     pub node: HirExpressionBase,
 }
 
+/// `rescue e: SomeError`
+#[derive(Debug, PartialEq)]
+pub struct HirRescueClause {
+    /// The rescued class and its subclasses
+    pub class_fullnames: Vec<ClassFullname>,
+    /// The variable bound to the exception (its scope is this clause)
+    pub var_name: Option<String>,
+    pub exprs: HirExpressions,
+}
+
+/// `when` clause of `match`
+#[derive(Debug, PartialEq)]
+pub struct HirMatchClause {
+    /// Tests and bindings of the pattern (evaluated in order)
+    pub components: Vec<HirMatchComponent>,
+    pub body_hir: HirExpressions,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HirMatchComponent {
+    /// Go to the next clause if this is false
+    Test(HirExpression),
+    /// Assign the value to a local variable (its scope is this clause)
+    Bind(String, HirExpression),
+}
+
+/// A variable captured by a lambda
+///
+/// Each captured variable is passed to the lambda as a pointer to the
+/// memory which holds its value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HirLambdaCapture {
+    pub name: String,
+    pub ty: TermTy,
+    /// True if the variable is never reassigned. Readonly variables are
+    /// captured by copying their value
+    pub readonly: bool,
+    pub detail: HirLambdaCaptureDetail,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum HirLambdaCaptureDetail {
+    /// A local variable of the enclosing scope
+    CaptureLVar,
+    /// A parameter of the enclosing method (or lambda)
+    CaptureArg { idx: usize },
+    /// A variable the enclosing lambda captures
+    CaptureFwd { cidx: usize },
+}
+
 #[derive(Debug, PartialEq)]
 pub enum HirExpressionBase {
     HirLogicalNot {
@@ -129,6 +199,30 @@ pub enum HirExpressionBase {
         body_exprs: Box<HirExpressions>,
     },
     HirBreakExpression,
+    HirReturnExpression {
+        arg: Box<HirExpression>,
+    },
+    HirBeginExpression {
+        body_exprs: Box<HirExpressions>,
+        rescue_clauses: Vec<HirRescueClause>,
+        ensure_exprs: Box<Option<HirExpressions>>,
+    },
+    HirRaiseExpression {
+        expr: Box<HirExpression>,
+    },
+    /// The exception being rescued (only appears in a rescue clause)
+    HirCaughtException,
+    /// `match` expression (see hir::pattern_match)
+    HirMatchExpression {
+        /// Assigns the value to be matched to a temporary variable
+        cond_assign_expr: Box<HirExpression>,
+        clauses: Vec<HirMatchClause>,
+    },
+    /// True if the class of `expr` is one of `class_fullnames`
+    HirIsA {
+        expr: Box<HirExpression>,
+        class_fullnames: Vec<ClassFullname>,
+    },
     HirLVarAssign {
         name: String,
         rhs: Box<HirExpression>,
@@ -164,16 +258,36 @@ pub enum HirExpressionBase {
     HirLambda {
         params: Vec<MethodParam>,
         exprs: HirExpressions,
+        /// Variables of the outer scopes used in this lambda
+        captures: Vec<HirLambdaCapture>,
+        /// Local variables of this lambda captured by inner lambdas
+        captured_lvars: Vec<String>,
+    },
+    /// Reference to a captured variable
+    HirLambdaCaptureRef {
+        idx: usize,
+    },
+    /// Reassignment to a captured variable
+    HirLambdaCaptureWrite {
+        cidx: usize,
+        rhs: Box<HirExpression>,
     },
     HirSelfExpression,
     HirArrayLiteral {
         exprs: HirExpressions,
     },
+    HirHashLiteral {
+        exprs: HirExpressions,
+    },
+    /// `"...#{}..."` (expanded into the calls of `MutableString#append`)
+    HirStringInterpolation {
+        exprs: HirExpressions,
+    },
     HirFloatLiteral {
         value: f64,
     },
     HirDecimalLiteral {
-        value: i32,
+        value: i64,
     },
     /// A string literal. Its body is stored in str_literals
     HirStringLiteral {
@@ -182,6 +296,12 @@ pub enum HirExpressionBase {
     HirBooleanLiteral {
         value: bool,
     },
+    /// `nil` (a null pointer)
+    HirNilLiteral,
+    /// Test if the value is nil (`x == nil`)
+    HirIsNil {
+        expr: Box<HirExpression>,
+    },
 
     /// Represents bitcast of an object
     HirBitCast {
@@ -259,6 +379,71 @@ impl Hir {
         }
     }
 
+    pub fn return_expression(arg_hir: HirExpression) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Never"),
+            node: HirExpressionBase::HirReturnExpression {
+                arg: Box::new(arg_hir),
+            },
+        }
+    }
+
+    pub fn begin_expression(
+        ty: TermTy,
+        body_hirs: HirExpressions,
+        rescue_clauses: Vec<HirRescueClause>,
+        ensure_hirs: Option<HirExpressions>,
+    ) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirBeginExpression {
+                body_exprs: Box::new(body_hirs),
+                rescue_clauses,
+                ensure_exprs: Box::new(ensure_hirs),
+            },
+        }
+    }
+
+    pub fn raise_expression(expr: HirExpression) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Never"),
+            node: HirExpressionBase::HirRaiseExpression {
+                expr: Box::new(expr),
+            },
+        }
+    }
+
+    pub fn caught_exception(ty: TermTy) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirCaughtException,
+        }
+    }
+
+    pub fn match_expression(
+        ty: TermTy,
+        cond_assign_hir: HirExpression,
+        clauses: Vec<HirMatchClause>,
+    ) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirMatchExpression {
+                cond_assign_expr: Box::new(cond_assign_hir),
+                clauses,
+            },
+        }
+    }
+
+    pub fn is_a(expr: HirExpression, class_fullnames: Vec<ClassFullname>) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Bool"),
+            node: HirExpressionBase::HirIsA {
+                expr: Box::new(expr),
+                class_fullnames,
+            },
+        }
+    }
+
     pub fn assign_lvar(name: &str, rhs: HirExpression) -> HirExpression {
         HirExpression {
             ty: rhs.ty.clone(),
@@ -341,11 +526,38 @@ impl Hir {
         }
     }
 
-    pub fn lambda(params: Vec<MethodParam>, exprs: HirExpressions) -> HirExpression {
+    pub fn lambda(
+        params: Vec<MethodParam>,
+        exprs: HirExpressions,
+        captures: Vec<HirLambdaCapture>,
+        captured_lvars: Vec<String>,
+    ) -> HirExpression {
         let ty = lambda_ty(&params, &exprs.ty);
         HirExpression {
             ty,
-            node: HirExpressionBase::HirLambda { params, exprs },
+            node: HirExpressionBase::HirLambda {
+                params,
+                exprs,
+                captures,
+                captured_lvars,
+            },
+        }
+    }
+
+    pub fn lambda_capture_ref(ty: TermTy, idx: usize) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirLambdaCaptureRef { idx },
+        }
+    }
+
+    pub fn lambda_capture_write(cidx: usize, rhs: HirExpression) -> HirExpression {
+        HirExpression {
+            ty: rhs.ty.clone(),
+            node: HirExpressionBase::HirLambdaCaptureWrite {
+                cidx,
+                rhs: Box::new(rhs),
+            },
         }
     }
 
@@ -365,6 +577,24 @@ impl Hir {
         }
     }
 
+    pub fn hash_literal(exprs: Vec<HirExpression>, ty: TermTy) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirHashLiteral {
+                exprs: HirExpressions::new(exprs),
+            },
+        }
+    }
+
+    pub fn string_interpolation(exprs: Vec<HirExpression>, ty: TermTy) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirStringInterpolation {
+                exprs: HirExpressions::new(exprs),
+            },
+        }
+    }
+
     pub fn float_literal(value: f64) -> HirExpression {
         HirExpression {
             ty: ty::raw("Float"),
@@ -372,7 +602,7 @@ impl Hir {
         }
     }
 
-    pub fn decimal_literal(value: i32) -> HirExpression {
+    pub fn decimal_literal(value: i64) -> HirExpression {
         HirExpression {
             ty: ty::raw("Int"),
             node: HirExpressionBase::HirDecimalLiteral { value },
@@ -393,6 +623,22 @@ impl Hir {
         }
     }
 
+    pub fn nil_literal() -> HirExpression {
+        HirExpression {
+            ty: ty::nil(),
+            node: HirExpressionBase::HirNilLiteral,
+        }
+    }
+
+    pub fn is_nil(expr: HirExpression) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Bool"),
+            node: HirExpressionBase::HirIsNil {
+                expr: Box::new(expr),
+            },
+        }
+    }
+
     pub fn bit_cast(ty: TermTy, expr: HirExpression) -> HirExpression {
         HirExpression {
             ty,
@@ -402,6 +648,15 @@ impl Hir {
         }
     }
 
+    /// Cast `expr` to `ty` if it is a subtype of `ty`
+    pub fn upcast(ty: &TermTy, expr: HirExpression) -> HirExpression {
+        if expr.ty == *ty || expr.ty.is_never_type() {
+            expr
+        } else {
+            Hir::bit_cast(ty.clone(), expr)
+        }
+    }
+
     pub fn class_literal(fullname: ClassFullname, str_literal_idx: usize) -> HirExpression {
         HirExpression {
             ty: ty::meta(&fullname.0),
@@ -413,9 +668,12 @@ impl Hir {
     }
 }
 
-fn lambda_ty(params: &Vec<MethodParam>, ret_ty: &TermTy) -> TermTy {
-    if params.len() != 1 {
-        panic!("TODO")
-    }
-    ty::spe("Fn1", vec![params[0].ty.clone(), ret_ty.clone()])
+/// Lambdas can take at most this number of parameters (Fn0 .. Fn9)
+pub const LAMBDA_MAX_ARITY: usize = 9;
+
+/// Return the type of a lambda (eg. `Fn1<Int, Bool>`)
+fn lambda_ty(params: &[MethodParam], ret_ty: &TermTy) -> TermTy {
+    let mut tyargs = params.iter().map(|x| x.ty.clone()).collect::<Vec<_>>();
+    tyargs.push(ret_ty.clone());
+    ty::spe(&format!("Fn{}", params.len()), tyargs)
 }
@@ -0,0 +1,298 @@
+/// Conversion of `match` expressions
+///
+/// Each pattern is converted into a list of tests and bindings. eg.
+/// `when Shape::Circle(r)` becomes
+///
+/// ```text
+/// test: tmp1 is a Shape::Circle
+/// bind: tmp2 = tmp1 as Shape::Circle
+/// bind: tmp3 = tmp2.r
+/// bind: r = tmp3
+/// ```
+use crate::ast::*;
+use crate::error;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::hir_maker_context::*;
+use crate::hir::*;
+use crate::type_checking;
+
+impl HirMaker {
+    pub(super) fn convert_match_expr(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        cond_expr: &AstExpression,
+        clauses: &[AstMatchClause],
+    ) -> Result<HirExpression, Error> {
+        let cond_hir = self.convert_expr(ctx, cond_expr)?;
+        let value_ty = cond_hir.ty.clone();
+        let tmp_name = self.gensym();
+        let cond_assign_hir = Hir::assign_lvar(&tmp_name, cond_hir);
+
+        let mut hir_clauses = vec![];
+        let mut covered = vec![];
+        for clause in clauses {
+            let mut components = vec![];
+            let mut bound_names = vec![];
+            let covered_class = self.convert_pattern(
+                ctx,
+                &clause.pattern,
+                &tmp_name,
+                &value_ty,
+                &mut components,
+                &mut bound_names,
+            )?;
+            let body_hir = self.convert_exprs(ctx, &clause.body_exprs)?;
+            // The variables are only visible in the clause
+            for name in &bound_names {
+                ctx.lvars.remove(name);
+            }
+            if let Some(cls) = covered_class {
+                covered.push(cls);
+            }
+            hir_clauses.push(HirMatchClause {
+                components,
+                body_hir,
+            });
+        }
+
+        let value_class = class_of(&value_ty);
+        let enum_cases = self
+            .class_dict
+            .find_class(&value_class)
+            .and_then(|cls| cls.enum_cases.as_ref());
+        type_checking::check_match_exhaustiveness(&value_ty, &value_class, enum_cases, &covered)?;
+
+        let ty = self.match_result_ty(&hir_clauses)?;
+        Ok(Hir::match_expression(ty, cond_assign_hir, hir_clauses))
+    }
+
+    /// Convert a pattern into tests and bindings for the value stored in
+    /// the variable `value_name`.
+    /// Returns the class if all of its instances matches this pattern
+    fn convert_pattern(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        pattern: &AstPattern,
+        value_name: &str,
+        value_ty: &TermTy,
+        components: &mut Vec<HirMatchComponent>,
+        bound_names: &mut Vec<String>,
+    ) -> Result<Option<ClassFullname>, Error> {
+        match pattern {
+            AstPattern::Wildcard => Ok(Some(class_of(value_ty))),
+            AstPattern::Binding(name) => {
+                if ctx.lvars.contains_key(name) || bound_names.contains(name) {
+                    return Err(error::program_error(&format!(
+                        "variable `{}' already exists",
+                        name
+                    )));
+                }
+                ctx.lvars.insert(
+                    name.to_string(),
+                    CtxLVar {
+                        name: name.to_string(),
+                        ty: value_ty.clone(),
+                        readonly: true,
+                    },
+                );
+                bound_names.push(name.to_string());
+                components.push(HirMatchComponent::Bind(
+                    name.to_string(),
+                    Hir::lvar_ref(value_ty.clone(), value_name.to_string()),
+                ));
+                Ok(Some(class_of(value_ty)))
+            }
+            AstPattern::Literal(expr) => {
+                let literal_hir = self.convert_expr(ctx, expr)?;
+                let test = self.make_method_call(
+                    Hir::lvar_ref(value_ty.clone(), value_name.to_string()),
+                    &method_firstname("=="),
+                    vec![literal_hir],
+                )?;
+                type_checking::check_condition_ty(&test.ty, "match")?;
+                components.push(HirMatchComponent::Test(test));
+                Ok(None)
+            }
+            AstPattern::TypedBinding { name, names } => {
+                let value_class = class_of(value_ty);
+                let pattern_class = self.resolve_pattern_class(&value_class, names)?;
+                let (casted_name, casted_ty) =
+                    self.cast_to_pattern_class(value_name, value_ty, &pattern_class, components)?;
+                self.convert_pattern(
+                    ctx,
+                    &AstPattern::Binding(name.to_string()),
+                    &casted_name,
+                    &casted_ty,
+                    components,
+                    bound_names,
+                )?;
+                Ok(Some(pattern_class))
+            }
+            AstPattern::ExtractorPattern { names, params } => self.convert_extractor_pattern(
+                ctx,
+                names,
+                params,
+                value_name,
+                value_ty,
+                components,
+                bound_names,
+            ),
+        }
+    }
+
+    /// Convert a pattern like `Shape::Rect(w, h)`.
+    /// The params are matched with the params of `initialize`
+    #[allow(clippy::too_many_arguments)]
+    fn convert_extractor_pattern(
+        &mut self,
+        ctx: &mut HirMakerContext,
+        names: &[String],
+        params: &[AstPattern],
+        value_name: &str,
+        value_ty: &TermTy,
+        components: &mut Vec<HirMatchComponent>,
+        bound_names: &mut Vec<String>,
+    ) -> Result<Option<ClassFullname>, Error> {
+        let value_class = class_of(value_ty);
+        let pattern_class = self.resolve_pattern_class(&value_class, names)?;
+        let (casted_name, casted_ty) =
+            self.cast_to_pattern_class(value_name, value_ty, &pattern_class, components)?;
+        if params.is_empty() {
+            return Ok(Some(pattern_class));
+        }
+
+        // Extract the fields
+        let (init_sig, _) = self
+            .class_dict
+            .lookup_method(&casted_ty, &method_firstname("initialize"))?;
+        if init_sig.params.len() != params.len() {
+            return Err(error::type_error(&format!(
+                "{} has {} fields but the pattern has {}",
+                pattern_class,
+                init_sig.params.len(),
+                params.len()
+            )));
+        }
+        let mut irrefutable = true;
+        for (param, sub_pattern) in init_sig.params.iter().zip(params.iter()) {
+            let field_hir = self.make_method_call(
+                Hir::lvar_ref(casted_ty.clone(), casted_name.clone()),
+                &method_firstname(&param.name),
+                vec![],
+            )?;
+            let field_ty = field_hir.ty.clone();
+            let field_name = self.gensym();
+            components.push(HirMatchComponent::Bind(field_name.clone(), field_hir));
+            let covered = self.convert_pattern(
+                ctx,
+                sub_pattern,
+                &field_name,
+                &field_ty,
+                components,
+                bound_names,
+            )?;
+            if covered != Some(class_of(&field_ty)) {
+                irrefutable = false;
+            }
+        }
+        if irrefutable {
+            Ok(Some(pattern_class))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Add the test that the value is an instance of `pattern_class` and
+    /// return the name and the type of the casted value
+    fn cast_to_pattern_class(
+        &mut self,
+        value_name: &str,
+        value_ty: &TermTy,
+        pattern_class: &ClassFullname,
+        components: &mut Vec<HirMatchComponent>,
+    ) -> Result<(String, TermTy), Error> {
+        let value_class = class_of(value_ty);
+        if !self
+            .class_dict
+            .descendant_classes(&value_class)
+            .contains(pattern_class)
+        {
+            return Err(error::type_error(&format!(
+                "pattern `{}' never matches {}",
+                pattern_class, value_ty
+            )));
+        }
+        if *pattern_class == value_class {
+            return Ok((value_name.to_string(), value_ty.clone()));
+        }
+        let casted_ty = match &value_ty.body {
+            // TODO #115: Assumes that the subclass takes the same type
+            // arguments (eg. `Maybe::Some<T> : Maybe<T>`)
+            TyBody::TySpe { type_args, .. } => ty::spe(&pattern_class.0, type_args.clone()),
+            _ => ty::raw(&pattern_class.0),
+        };
+        let value_ref = Hir::lvar_ref(value_ty.clone(), value_name.to_string());
+        let subclasses = self.class_dict.descendant_classes(pattern_class);
+        components.push(HirMatchComponent::Test(Hir::is_a(value_ref, subclasses)));
+        let casted_name = self.gensym();
+        let value_ref = Hir::lvar_ref(value_ty.clone(), value_name.to_string());
+        components.push(HirMatchComponent::Bind(
+            casted_name.clone(),
+            Hir::bit_cast(casted_ty.clone(), value_ref),
+        ));
+        Ok((casted_name, casted_ty))
+    }
+
+    /// Find the class of an extractor pattern. The name is looked up from
+    /// the namespace of the value, so that the cases of an enum can be
+    /// written without the enum name (eg. `Circle` for a `Shape`)
+    fn resolve_pattern_class(
+        &self,
+        value_class: &ClassFullname,
+        names: &[String],
+    ) -> Result<ClassFullname, Error> {
+        let name = names.join("::");
+        let mut namespace = value_class.0.split("::").collect::<Vec<_>>();
+        loop {
+            let mut candidate = namespace.clone();
+            candidate.push(&name);
+            let fullname = candidate.join("::");
+            if self.class_dict.class_exists(&fullname) {
+                return Ok(class_fullname(fullname));
+            }
+            if namespace.pop().is_none() {
+                return Err(error::name_error(&format!(
+                    "unknown class in pattern: {}",
+                    name
+                )));
+            }
+        }
+    }
+
+    /// Type of the `match` expression (the nearest common ancestor of the
+    /// clauses, ignoring the ones which do not return)
+    fn match_result_ty(&self, clauses: &[HirMatchClause]) -> Result<TermTy, Error> {
+        let mut result: Option<TermTy> = None;
+        for ty in clauses
+            .iter()
+            .map(|clause| &clause.body_hir.ty)
+            .filter(|ty| !ty.is_never_type())
+        {
+            result = Some(match result {
+                Some(t) => self.nearest_common_ancestor_type(&t, ty)?,
+                None => ty.clone(),
+            });
+        }
+        Ok(result.unwrap_or_else(|| ty::raw("Never")))
+    }
+}
+
+/// Return the class of the values of `ty`
+fn class_of(ty: &TermTy) -> ClassFullname {
+    match &ty.body {
+        TyBody::TySpe { base_name, .. } => class_fullname(base_name),
+        TyBody::TyParamRef { upper_bound, .. } => class_of(upper_bound),
+        _ => ty.fullname.clone(),
+    }
+}
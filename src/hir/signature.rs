@@ -7,34 +7,85 @@ use crate::ty::*;
 pub fn create_signature(
     class_fullname: &ClassFullname,
     sig: &ast::AstMethodSignature,
-    typarams: &[String],
+    class_typarams: &[TyParam],
 ) -> MethodSignature {
     let fullname = method_fullname(class_fullname, &sig.name.0);
-    let ret_ty = convert_typ(&sig.ret_typ, typarams);
-    let params = convert_params(&sig.params, typarams);
+    let typarams = convert_typarams(&sig.typarams, class_typarams);
+    let ret_ty = convert_typ(&sig.ret_typ, class_typarams, &typarams);
+    let params = convert_params_(&sig.params, class_typarams, &typarams);
     MethodSignature {
         fullname,
         ret_ty,
         params,
+        typarams,
+        visibility: sig.visibility.clone(),
     }
 }
 
+/// Create `ty::TyParam`s from `ast::AstTyParam`s
+/// (The upper bounds may refer to the class type parameters)
+pub fn convert_typarams(typarams: &[ast::AstTyParam], class_typarams: &[TyParam]) -> Vec<TyParam> {
+    typarams
+        .iter()
+        .map(|typaram| TyParam {
+            name: typaram.name.to_string(),
+            variance: typaram.variance.clone(),
+            upper_bound: match &typaram.upper_bound {
+                Some(typ) => convert_typ(typ, class_typarams, &[]),
+                None => ty::raw("Object"),
+            },
+        })
+        .collect()
+}
+
 // TODO: pass the list of visible classes
-fn convert_typ(typ: &ast::Typ, typarams: &[String]) -> TermTy {
-    let found = typarams.iter().enumerate().find(|(_, s)| **s == typ.name);
-    if let Some((idx, _)) = found {
-        ty::typaram(&typ.name, idx)
+pub fn convert_typ(
+    typ: &ast::Typ,
+    class_typarams: &[TyParam],
+    method_typarams: &[TyParam],
+) -> TermTy {
+    let t = convert_typ_(typ, class_typarams, method_typarams);
+    if typ.is_nullable {
+        ty::nullable(t)
+    } else {
+        t
+    }
+}
+
+fn convert_typ_(typ: &ast::Typ, class_typarams: &[TyParam], method_typarams: &[TyParam]) -> TermTy {
+    // Method type parameters shadow the class ones
+    if let Some(idx) = method_typarams.iter().position(|t| t.name == typ.name) {
+        let bound = method_typarams[idx].upper_bound.clone();
+        ty::typaram(&typ.name, TyParamKind::Method, idx, bound)
+    } else if let Some(idx) = class_typarams.iter().position(|t| t.name == typ.name) {
+        let bound = class_typarams[idx].upper_bound.clone();
+        ty::typaram(&typ.name, TyParamKind::Class, idx, bound)
+    } else if !typ.typ_args.is_empty() {
+        let type_args = typ
+            .typ_args
+            .iter()
+            .map(|t| convert_typ(t, class_typarams, method_typarams))
+            .collect();
+        ty::spe(&typ.name, type_args)
     } else {
         ty::raw(&typ.name)
     }
 }
 
-pub fn convert_params(params: &[ast::Param], typarams: &[String]) -> Vec<MethodParam> {
+pub fn convert_params(params: &[ast::Param], typarams: &[TyParam]) -> Vec<MethodParam> {
+    convert_params_(params, typarams, &[])
+}
+
+fn convert_params_(
+    params: &[ast::Param],
+    class_typarams: &[TyParam],
+    method_typarams: &[TyParam],
+) -> Vec<MethodParam> {
     params
         .iter()
         .map(|param| MethodParam {
             name: param.name.to_string(),
-            ty: convert_typ(&param.typ, typarams),
+            ty: convert_typ(&param.typ, class_typarams, method_typarams),
         })
         .collect()
 }
@@ -48,5 +99,7 @@ pub fn signature_of_new(
         fullname: method_fullname(metaclass_fullname, "new"),
         ret_ty: instance_ty.clone(),
         params: initialize_params,
+        typarams: vec![],
+        visibility: Visibility::Public,
     }
 }
@@ -2,12 +2,26 @@ use crate::names::*;
 use crate::ty::*;
 use std::collections::HashMap;
 
-/// A Shiika class, possibly generic
+/// A Shiika class, possibly generic (or a module, or an interface)
 #[derive(Debug, PartialEq, Clone)]
 pub struct SkClass {
     pub fullname: ClassFullname,
     pub typarams: Vec<TyParam>,
     pub superclass_fullname: Option<ClassFullname>,
+    /// True if this is a module (modules have no instances)
+    pub is_module: bool,
+    /// True if this is an interface (interfaces only have signatures)
+    pub is_interface: bool,
+    /// True if this is an abstract class (abstract classes have no instances)
+    pub is_abstract: bool,
+    /// Modules included by this class (in the order of `include`)
+    pub includes: Vec<ClassFullname>,
+    /// Interfaces implemented by this class (`implements Foo`)
+    pub interfaces: Vec<ClassFullname>,
+    /// Methods declared with `abstract def` in this class
+    pub abstract_methods: Vec<MethodFirstname>,
+    /// Cases of this enum (None if this is not an enum)
+    pub enum_cases: Option<Vec<ClassFullname>>,
     pub instance_ty: TermTy,
     pub ivars: HashMap<String, super::SkIVar>,
     pub method_sigs: HashMap<MethodFirstname, MethodSignature>,
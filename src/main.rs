@@ -8,14 +8,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Some(ref matches) = matches.subcommand_matches("compile") {
         let filepath = matches.value_of("INPUT").unwrap();
-        runner::compile(filepath)?;
+        compile(filepath, matches.is_present("checked"))?;
     }
 
     if let Some(ref matches) = matches.subcommand_matches("run") {
         let filepath = matches.value_of("INPUT").unwrap();
-        runner::compile(filepath)?;
+        compile(filepath, matches.is_present("checked"))?;
         runner::run(filepath)?;
     }
 
     Ok(())
 }
+
+fn compile(filepath: &str, checked: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if checked {
+        runner::compile_checked(filepath)
+    } else {
+        runner::compile(filepath)
+    }
+}
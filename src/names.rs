@@ -61,6 +61,11 @@ impl ClassFullname {
     pub fn meta_name(&self) -> ClassFullname {
         ClassFullname("Meta:".to_string() + &self.0)
     }
+
+    /// Name of the constant which holds the class object
+    pub fn to_const_fullname(&self) -> ConstFullname {
+        ConstFullname("::".to_string() + &self.0)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
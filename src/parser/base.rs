@@ -4,7 +4,7 @@ pub use crate::ast::*;
 pub use crate::error::*;
 pub use crate::parser::lexer;
 pub use crate::parser::lexer::*;
-pub use crate::parser::token::Token;
+pub use crate::parser::token::{StrLiteralPart, Token};
 pub use crate::parser::Parser;
 
 impl<'a> Parser<'a> {
@@ -2,6 +2,7 @@ use super::super::ast;
 use super::base::*;
 use super::Parser; // REFACTOR: use crate:: instead of super
 use crate::names::*;
+use crate::ty::{Variance, Visibility};
 
 impl<'a> Parser<'a> {
     pub fn parse_definitions(&mut self) -> Result<Vec<ast::Definition>, Error> {
@@ -15,18 +16,44 @@ impl<'a> Parser<'a> {
 
     fn parse_definition(&mut self) -> Result<Option<ast::Definition>, Error> {
         match self.current_token() {
+            Token::KwAbstract => Ok(Some(self.parse_abstract_definition()?)),
             Token::KwClass => Ok(Some(self.parse_class_definition()?)),
+            Token::KwModule => Ok(Some(self.parse_module_definition()?)),
+            Token::KwInterface => Ok(Some(self.parse_interface_definition()?)),
+            Token::KwEnum => Ok(Some(self.parse_enum_definition()?)),
+            Token::LowerWord(s) if s == "include" => Ok(Some(self.parse_include()?)),
+            Token::LowerWord(s) if s == "private" || s == "protected" => {
+                Ok(Some(self.parse_method_definition_with_visibility()?))
+            }
             Token::KwDef => Ok(Some(self.parse_method_definition()?)),
             Token::UpperWord(_) => Ok(Some(self.parse_const_definition()?)),
             _ => Ok(None),
         }
     }
 
+    /// Parse `abstract class ...` or `abstract def ...`
+    pub fn parse_abstract_definition(&mut self) -> Result<ast::Definition, Error> {
+        assert!(self.consume(Token::KwAbstract));
+        self.skip_ws();
+        match self.current_token() {
+            Token::KwClass => self.parse_class_definition_(true),
+            Token::KwDef => self.parse_abstract_method_definition(),
+            token => Err(parse_error!(
+                self,
+                "expected `class' or `def' after `abstract' but got {:?}",
+                token
+            )),
+        }
+    }
+
     pub fn parse_class_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.parse_class_definition_(false)
+    }
+
+    fn parse_class_definition_(&mut self, is_abstract: bool) -> Result<ast::Definition, Error> {
         self.debug_log("parse_class_definition");
         self.lv += 1;
         let name;
-        let mut typarams = vec![];
         let defs;
 
         // `class'
@@ -49,6 +76,101 @@ impl<'a> Parser<'a> {
         }
 
         // Type parameters (optional)
+        let typarams = self.parse_typarams()?;
+
+        // Superclass name (optional)
+        let mut super_name = class_fullname("Object");
+        self.skip_ws();
+        if self.current_token_is(Token::Colon) {
+            self.consume_token();
+            self.skip_wsn();
+            match self.current_token() {
+                Token::UpperWord(s) => {
+                    super_name = class_fullname(s);
+                    self.consume_token();
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "superclass name must start with A-Z but got {:?}",
+                        token
+                    ))
+                }
+            }
+        }
+
+        // Interfaces (optional)
+        let interfaces = self.parse_implements()?;
+
+        self.expect_sep()?;
+
+        // Internal definitions
+        defs = self.parse_definitions()?;
+
+        // `end'
+        match self.current_token() {
+            Token::KwEnd => {
+                self.consume_token();
+            }
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "missing `end' for class {:?}; got {:?}",
+                    name,
+                    token
+                ))
+            }
+        }
+
+        self.lv -= 1;
+        Ok(ast::Definition::ClassDefinition {
+            name,
+            typarams,
+            super_name,
+            interfaces,
+            is_abstract,
+            defs,
+        })
+    }
+
+    /// Parse `implements A, B` (if any)
+    fn parse_implements(&mut self) -> Result<Vec<ClassFullname>, Error> {
+        let mut names = vec![];
+        self.skip_ws();
+        match self.current_token() {
+            Token::LowerWord(s) if s == "implements" => {
+                self.consume_token();
+            }
+            _ => return Ok(names),
+        }
+        loop {
+            self.skip_ws();
+            match self.current_token() {
+                Token::UpperWord(s) => {
+                    names.push(class_fullname(s));
+                    self.consume_token();
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "interface name must start with A-Z but got {:?}",
+                        token
+                    ))
+                }
+            }
+            self.skip_ws();
+            if !self.consume(Token::Comma) {
+                break;
+            }
+        }
+        Ok(names)
+    }
+
+    /// Parse `<S, T>` (if any)
+    /// Each of them may have a variance (`in T`, `out T`) and an upper
+    /// bound (`T: Comparable`)
+    fn parse_typarams(&mut self) -> Result<Vec<ast::AstTyParam>, Error> {
+        let mut typarams = vec![];
         if self.current_token_is(Token::LessThan) {
             self.consume_token();
             self.skip_wsn();
@@ -58,9 +180,19 @@ impl<'a> Parser<'a> {
                         self.consume_token();
                         break;
                     }
-                    Token::UpperWord(s) => {
-                        typarams.push(s.to_string());
+                    Token::LowerWord(s) if s == "in" || s == "out" => {
+                        let variance = if s == "in" {
+                            Variance::Contravariant
+                        } else {
+                            Variance::Covariant
+                        };
                         self.consume_token();
+                        self.skip_ws();
+                        typarams.push(self.parse_typaram(variance)?);
+                        self.skip_wsn();
+                    }
+                    Token::UpperWord(_) => {
+                        typarams.push(self.parse_typaram(Variance::Invariant)?);
                         self.skip_wsn();
                     }
                     Token::Comma => {
@@ -77,32 +209,191 @@ impl<'a> Parser<'a> {
                 }
             }
         }
+        Ok(typarams)
+    }
 
-        // Superclass name (optional)
-        let mut super_name = class_fullname("Object");
+    /// Parse a type parameter (after the variance)
+    fn parse_typaram(&mut self, variance: Variance) -> Result<ast::AstTyParam, Error> {
+        let name = match self.current_token() {
+            Token::UpperWord(s) => s.to_string(),
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "type parameter name must start with A-Z but got {:?}",
+                    token
+                ))
+            }
+        };
+        self.consume_token();
         self.skip_ws();
-        if self.current_token_is(Token::Colon) {
-            self.consume_token();
-            self.skip_wsn();
+        let upper_bound = if self.consume(Token::Colon) {
+            self.skip_ws();
+            Some(self.parse_ty()?)
+        } else {
+            None
+        };
+        Ok(ast::AstTyParam {
+            name,
+            variance,
+            upper_bound,
+        })
+    }
+
+    pub fn parse_module_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_module_definition");
+        self.lv += 1;
+        let name;
+
+        // `module'
+        assert!(self.consume(Token::KwModule));
+        self.skip_ws();
+
+        // Module name
+        match self.current_token() {
+            Token::UpperWord(s) => {
+                name = class_firstname(s);
+                self.consume_token();
+            }
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "module name must start with A-Z but got {:?}",
+                    token
+                ))
+            }
+        }
+        self.skip_ws();
+        self.expect_sep()?;
+
+        // Internal definitions
+        let defs = self.parse_definitions()?;
+
+        // `end'
+        match self.current_token() {
+            Token::KwEnd => {
+                self.consume_token();
+            }
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "missing `end' for module {:?}; got {:?}",
+                    name,
+                    token
+                ))
+            }
+        }
+
+        self.lv -= 1;
+        Ok(ast::Definition::ModuleDefinition { name, defs })
+    }
+
+    /// Parse `interface Foo ... end`, which only has method signatures
+    pub fn parse_interface_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_interface_definition");
+        self.lv += 1;
+        let name;
+        let mut sigs = vec![];
+
+        // `interface'
+        assert!(self.consume(Token::KwInterface));
+        self.skip_ws();
+
+        // Interface name
+        match self.current_token() {
+            Token::UpperWord(s) => {
+                name = class_firstname(s);
+                self.consume_token();
+            }
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "interface name must start with A-Z but got {:?}",
+                    token
+                ))
+            }
+        }
+        self.skip_ws();
+        self.expect_sep()?;
+
+        // Method signatures and `end'
+        loop {
             match self.current_token() {
-                Token::UpperWord(s) => {
-                    super_name = class_fullname(s);
+                Token::KwDef => {
+                    self.set_lexer_state(LexerState::MethodName);
                     self.consume_token();
+                    self.skip_ws();
+                    let (sig, is_class_method) = self.parse_method_signature()?;
+                    if is_class_method {
+                        return Err(parse_error!(
+                            self,
+                            "interface {:?} cannot have class methods",
+                            name
+                        ));
+                    }
+                    sigs.push(sig);
+                    self.expect_sep()?;
+                }
+                Token::KwEnd => {
+                    self.consume_token();
+                    break;
                 }
                 token => {
                     return Err(parse_error!(
                         self,
-                        "superclass name must start with A-Z but got {:?}",
+                        "missing `end' for interface {:?}; got {:?}",
+                        name,
                         token
                     ))
                 }
             }
         }
 
+        self.lv -= 1;
+        Ok(ast::Definition::InterfaceDefinition { name, sigs })
+    }
+
+    pub fn parse_enum_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_enum_definition");
+        self.lv += 1;
+        let name;
+        let mut cases = vec![];
+        let mut defs = vec![];
+
+        // `enum'
+        assert!(self.consume(Token::KwEnum));
+        self.skip_ws();
+
+        // Enum name
+        match self.current_token() {
+            Token::UpperWord(s) => {
+                name = class_firstname(s);
+                self.consume_token();
+            }
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "enum name must start with A-Z but got {:?}",
+                    token
+                ))
+            }
+        }
+
+        // Type parameters (optional)
+        let typarams = self.parse_typarams()?;
+        self.skip_ws();
         self.expect_sep()?;
 
-        // Internal definitions
-        defs = self.parse_definitions()?;
+        // Cases and internal definitions
+        loop {
+            if self.current_token_is(Token::KwCase) {
+                cases.push(self.parse_enum_case()?);
+            } else if let Some(def) = self.parse_definition()? {
+                defs.push(def);
+            } else {
+                break;
+            }
+            self.skip_wsn();
+        }
 
         // `end'
         match self.current_token() {
@@ -112,7 +403,7 @@ impl<'a> Parser<'a> {
             token => {
                 return Err(parse_error!(
                     self,
-                    "missing `end' for class {:?}; got {:?}",
+                    "missing `end' for enum {:?}; got {:?}",
                     name,
                     token
                 ))
@@ -120,14 +411,76 @@ impl<'a> Parser<'a> {
         }
 
         self.lv -= 1;
-        Ok(ast::Definition::ClassDefinition {
+        Ok(ast::Definition::EnumDefinition {
             name,
             typarams,
-            super_name,
+            cases,
             defs,
         })
     }
 
+    /// Parse `case Foo` or `case Foo(a: Int, b: T)`
+    fn parse_enum_case(&mut self) -> Result<ast::EnumCase, Error> {
+        self.debug_log("parse_enum_case");
+        assert!(self.consume(Token::KwCase));
+        self.skip_ws();
+
+        let name = match self.current_token() {
+            Token::UpperWord(s) => {
+                let name = class_firstname(s);
+                self.consume_token();
+                name
+            }
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "enum case name must start with A-Z but got {:?}",
+                    token
+                ))
+            }
+        };
+
+        let params = if self.consume(Token::LParen) {
+            self.skip_wsn();
+            self.parse_params()?
+        } else {
+            vec![]
+        };
+        self.skip_ws();
+        self.expect_sep()?;
+
+        Ok(ast::EnumCase { name, params })
+    }
+
+    /// Parse `include Foo` or `include Foo::Bar`
+    fn parse_include(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_include");
+        self.consume_token();
+        self.skip_ws();
+        let mut names = vec![];
+        loop {
+            match self.current_token() {
+                Token::UpperWord(s) => {
+                    names.push(s.to_string());
+                    self.consume_token();
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "module name must start with A-Z but got {:?}",
+                        token
+                    ))
+                }
+            }
+            if !self.consume(Token::ColonColon) {
+                break;
+            }
+        }
+        Ok(ast::Definition::Include {
+            module_name: class_fullname(names.join("::")),
+        })
+    }
+
     pub fn parse_method_definition(&mut self) -> Result<ast::Definition, Error> {
         self.debug_log("parse_method_definition");
         self.lv += 1;
@@ -167,6 +520,51 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse `private def ...` or `protected def ...`
+    fn parse_method_definition_with_visibility(&mut self) -> Result<ast::Definition, Error> {
+        let visibility = match self.current_token() {
+            Token::LowerWord(s) if s == "private" => Visibility::Private,
+            _ => Visibility::Protected,
+        };
+        self.consume_token();
+        self.skip_ws();
+        if !self.current_token_is(Token::KwDef) {
+            return Err(parse_error!(
+                self,
+                "expected `def' after {:?} but got {:?}",
+                visibility,
+                self.current_token()
+            ));
+        }
+        let mut def = self.parse_method_definition()?;
+        match &mut def {
+            ast::Definition::InstanceMethodDefinition { sig, .. }
+            | ast::Definition::ClassMethodDefinition { sig, .. } => sig.visibility = visibility,
+            _ => panic!("[BUG] unexpected definition"),
+        }
+        Ok(def)
+    }
+
+    /// Parse `def foo -> Bar` (after `abstract`), which has no body
+    fn parse_abstract_method_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_abstract_method_definition");
+        // `def'
+        self.set_lexer_state(LexerState::MethodName);
+        assert!(self.consume(Token::KwDef));
+        self.skip_ws();
+
+        // `foo(bar) -> Baz`
+        let (sig, is_class_method) = self.parse_method_signature()?;
+        if is_class_method {
+            return Err(parse_error!(
+                self,
+                "class method {:?} cannot be abstract",
+                sig.name
+            ));
+        }
+        Ok(ast::Definition::AbstractMethodDefinition { sig })
+    }
+
     pub fn parse_method_signature(&mut self) -> Result<(ast::AstMethodSignature, bool), Error> {
         let mut name = None;
         let params;
@@ -192,6 +590,10 @@ impl<'a> Parser<'a> {
         }
         self.skip_ws();
 
+        // Type parameters (optional)
+        let typarams = self.parse_typarams()?;
+        self.skip_ws();
+
         // Params (optional)
         match self.current_token() {
             Token::LParen => {
@@ -216,6 +618,8 @@ impl<'a> Parser<'a> {
             _ => {
                 ret_typ = ast::Typ {
                     name: "Void".to_string(),
+                    typ_args: vec![],
+                    is_nullable: false,
                 };
                 self.skip_ws();
             }
@@ -223,20 +627,27 @@ impl<'a> Parser<'a> {
 
         let sig = ast::AstMethodSignature {
             name: name.unwrap(),
+            typarams,
             params,
             ret_typ,
+            visibility: Visibility::Public,
         };
         Ok((sig, is_class_method))
     }
 
     fn get_method_name(&mut self) -> Result<&str, Error> {
+        if self.current_token_is(Token::LSqBracket) {
+            return self.get_index_method_name();
+        }
         let name = match self.current_token() {
             Token::LowerWord(s) => s,
+            Token::KwClass => "class",
             Token::UPlusMethod => "+@",
             Token::UMinusMethod => "-@",
             Token::BinaryPlus => "+",
             Token::BinaryMinus => "-",
             Token::Mul => "*",
+            Token::Pow => "**",
             Token::Div => "/",
             Token::Mod => "%",
             Token::And => "&",
@@ -248,13 +659,34 @@ impl<'a> Parser<'a> {
             Token::LessEq => "<=",
             Token::GreaterThan => ">",
             Token::GreaterEq => ">=",
+            Token::Spaceship => "<=>",
             Token::EqEq => "==",
+            Token::EqEqEq => "===",
             Token::NotEq => "!=",
             token => return Err(parse_error!(self, "invalid method name {:?}", token)),
         };
         Ok(name)
     }
 
+    /// Parse `[]` or `[]=`. The last token is left unconsumed like other
+    /// method names
+    fn get_index_method_name(&mut self) -> Result<&str, Error> {
+        assert!(self.consume(Token::LSqBracket));
+        if !self.current_token_is(Token::RSqBracket) {
+            return Err(parse_error!(
+                self,
+                "invalid method name: expected `]' but got {:?}",
+                self.current_token()
+            ));
+        }
+        if self.peek_next_token() == Token::Equal {
+            self.consume_token();
+            Ok("[]=")
+        } else {
+            Ok("[]")
+        }
+    }
+
     // Parse parameters
     // The `(` should be consumed beforehand
     pub(super) fn parse_params(&mut self) -> Result<Vec<ast::Param>, Error> {
@@ -326,7 +758,7 @@ impl<'a> Parser<'a> {
         Ok(ast::Param { name, typ })
     }
 
-    fn parse_ty(&mut self) -> Result<ast::Typ, Error> {
+    pub(super) fn parse_ty(&mut self) -> Result<ast::Typ, Error> {
         let mut name = String::new();
         loop {
             match self.current_token() {
@@ -342,11 +774,59 @@ impl<'a> Parser<'a> {
                     if name.is_empty() {
                         return Err(parse_error!(self, "invalid token as type: {:?}", token));
                     } else {
-                        return Ok(ast::Typ { name });
+                        break;
                     }
                 }
             }
         }
+
+        // Type arguments (optional) eg. `Fn1<Int, Bool>`
+        let typ_args = if self.current_token_is(Token::LessThan) {
+            self.parse_typ_args()?
+        } else {
+            vec![]
+        };
+        // `?` (optional) eg. `Int?`
+        let is_nullable = self.consume(Token::Question);
+        Ok(ast::Typ {
+            name,
+            typ_args,
+            is_nullable,
+        })
+    }
+
+    /// Parse type arguments eg. `<Int, Bool>`
+    pub(super) fn parse_typ_args(&mut self) -> Result<Vec<ast::Typ>, Error> {
+        assert!(self.consume(Token::LessThan));
+        let mut typ_args = vec![];
+        loop {
+            self.skip_ws();
+            typ_args.push(self.parse_ty()?);
+            self.skip_ws();
+            match self.current_token() {
+                Token::Comma => {
+                    self.consume_token();
+                }
+                Token::GreaterThan => {
+                    self.consume_token();
+                    break;
+                }
+                // eg. `Array<Array<Int>>`
+                Token::RShift => {
+                    self.lexer.split_rshift();
+                    self.consume_token();
+                    break;
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "unexpected token `{:?}' in type arguments",
+                        token
+                    ))
+                }
+            }
+        }
+        Ok(typ_args)
     }
 
     fn parse_const_definition(&mut self) -> Result<ast::Definition, Error> {
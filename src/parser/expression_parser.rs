@@ -25,7 +25,30 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_expr(&mut self) -> Result<AstExpression, Error> {
-        self.parse_var_decl()
+        let expr = self.parse_var_decl()?;
+        self.parse_modifier(expr)
+    }
+
+    /// Parse `if` or `unless` modifier after `expr` (if any)
+    /// eg. `return 0 if n < 1`
+    fn parse_modifier(&mut self, expr: AstExpression) -> Result<AstExpression, Error> {
+        self.skip_ws();
+        match self.current_token() {
+            Token::KwIf => {
+                self.consume_token();
+                self.skip_ws();
+                let cond_expr = self.parse_var_decl()?;
+                self.parse_modifier(ast::if_expr(cond_expr, vec![expr], None))
+            }
+            Token::KwUnless => {
+                self.consume_token();
+                self.skip_ws();
+                let cond_expr = self.parse_var_decl()?;
+                let not_cond = ast::logical_not(cond_expr);
+                self.parse_modifier(ast::if_expr(not_cond, vec![expr], None))
+            }
+            _ => Ok(expr),
+        }
     }
 
     pub fn parse_var_decl(&mut self) -> Result<AstExpression, Error> {
@@ -129,8 +152,9 @@ impl<'a> Parser<'a> {
                 let args = self.parse_operator_exprs()?;
                 self.debug_log(&format!("tried/args: {:?}", args));
                 if !args.is_empty() {
+                    let expr = ast::method_call(None, &s, args, false, false);
                     self.lv -= 1;
-                    return Ok(ast::method_call(None, &s, args, false, false));
+                    return self.parse_do_block(expr);
                 }
                 self.rewind_to(cur)
             }
@@ -144,7 +168,20 @@ impl<'a> Parser<'a> {
             }
         }
         self.lv -= 1;
-        Ok(expr)
+        self.parse_do_block(expr)
+    }
+
+    /// Parse `do ... end` given to the method call `expr` (if any)
+    fn parse_do_block(&mut self, expr: AstExpression) -> Result<AstExpression, Error> {
+        if self.next_nonspace_token() != Token::KwDo {
+            return Ok(expr);
+        }
+        if !expr.may_take_block() {
+            return Err(parse_error!(self, "unexpected `do'"));
+        }
+        self.skip_ws();
+        let block = self.parse_block()?;
+        Ok(ast::add_block_arg(expr, block))
     }
 
     fn parse_args(&mut self) -> Result<Vec<AstExpression>, Error> {
@@ -301,8 +338,10 @@ impl<'a> Parser<'a> {
         self.debug_log("parse_equality_expr");
         let left = self.parse_relational_expr()?;
         let op = match self.next_nonspace_token() {
-            // TODO: <=> === =~ !~
+            // TODO: =~ !~
             Token::EqEq => "==",
+            Token::Spaceship => "<=>",
+            Token::EqEqEq => "===",
             Token::NotEq => "!=",
             _ => {
                 self.lv -= 1;
@@ -314,11 +353,15 @@ impl<'a> Parser<'a> {
         self.consume_token();
         self.skip_wsn();
         let right = self.parse_relational_expr()?;
-        let call_eq = ast::method_call(Some(left), "==", vec![right], false, false);
-        let expr = if op == "!=" {
-            ast::logical_not(call_eq)
-        } else {
-            call_eq
+        let expr = match op {
+            "!=" => ast::logical_not(ast::method_call(
+                Some(left),
+                "==",
+                vec![right],
+                false,
+                false,
+            )),
+            _ => ast::method_call(Some(left), op, vec![right], false, false),
         };
         self.lv -= 1;
         Ok(expr)
@@ -404,15 +447,31 @@ impl<'a> Parser<'a> {
         self.lv += 1;
         self.debug_log("parse_unary_minus_expr");
         //TODO:
-        //  parse_unary_minus_expr
-        //  parse_power_expr
         //  parse_unary_expr
-        //  parse_secondary_expr
         let expr = if self.consume(Token::UnaryMinus) {
-            let target = self.parse_secondary_expr()?;
+            let target = self.parse_power_expr()?;
             ast::unary_expr(target, "-@")
         } else {
-            self.parse_secondary_expr()?
+            self.parse_power_expr()?
+        };
+        self.lv -= 1;
+        Ok(expr)
+    }
+
+    /// `a ** b` (right-associative and stronger than unary minus,
+    /// i.e. `-2 ** 2` is `-(2 ** 2)`)
+    fn parse_power_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_power_expr");
+        let left = self.parse_secondary_expr()?;
+        let expr = if self.next_nonspace_token() == Token::Pow {
+            self.skip_ws();
+            self.consume_token();
+            self.skip_wsn();
+            let right = self.parse_unary_minus_expr()?;
+            ast::bin_op_expr(left, "**", right)
+        } else {
+            left
         };
         self.lv -= 1;
         Ok(expr)
@@ -429,9 +488,14 @@ impl<'a> Parser<'a> {
         self.debug_log("parse_secondary_expr");
         let expr = match self.current_token() {
             Token::KwBreak => self.parse_break_expr(),
+            Token::KwReturn => self.parse_return_expr(),
             Token::KwIf => self.parse_if_expr(),
             Token::KwUnless => self.parse_unless_expr(),
             Token::KwWhile => self.parse_while_expr(),
+            Token::KwBegin => self.parse_begin_expr(),
+            Token::KwRaise => self.parse_raise_expr(),
+            Token::KwCase => self.parse_case_expr(),
+            Token::KwMatch => self.parse_match_expr(),
             _ => self.parse_primary_expr(),
         }?;
         self.lv -= 1;
@@ -445,6 +509,20 @@ impl<'a> Parser<'a> {
         Ok(ast::break_expr())
     }
 
+    fn parse_return_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_return_expr");
+        assert!(self.consume(Token::KwReturn));
+        let arg = if self.next_nonspace_token().value_starts() {
+            self.skip_ws();
+            Some(self.parse_operator_expr()?)
+        } else {
+            None
+        };
+        self.lv -= 1;
+        Ok(ast::return_expr(arg))
+    }
+
     fn parse_if_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_if_expr");
@@ -511,6 +589,233 @@ impl<'a> Parser<'a> {
         Ok(ast::while_expr(cond_expr, body_exprs))
     }
 
+    fn parse_begin_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_begin_expr");
+        assert!(self.consume(Token::KwBegin));
+        self.skip_wsn();
+        let clause_ends = vec![Token::KwRescue, Token::KwEnsure, Token::KwEnd];
+        let body_exprs = self.parse_exprs(clause_ends.clone())?;
+        let mut rescue_clauses = vec![];
+        while self.consume(Token::KwRescue) {
+            self.skip_ws();
+            let (var_name, typ) = self.parse_rescue_target()?;
+            self.skip_ws();
+            self.expect_sep()?;
+            let exprs = self.parse_exprs(clause_ends.clone())?;
+            rescue_clauses.push(ast::AstRescueClause {
+                var_name,
+                typ,
+                exprs,
+            });
+        }
+        let ensure_exprs = if self.consume(Token::KwEnsure) {
+            self.skip_wsn();
+            Some(self.parse_exprs(vec![Token::KwEnd])?)
+        } else {
+            None
+        };
+        self.expect(Token::KwEnd)?;
+        self.lv -= 1;
+        Ok(ast::begin_expr(body_exprs, rescue_clauses, ensure_exprs))
+    }
+
+    /// Parse `e: SomeError`, `e`, `SomeError` or nothing after `rescue`
+    fn parse_rescue_target(&mut self) -> Result<(Option<String>, Option<ast::Typ>), Error> {
+        match self.current_token() {
+            Token::LowerWord(s) => {
+                let name = s.to_string();
+                self.consume_token();
+                if self.consume(Token::Colon) {
+                    self.skip_ws();
+                    Ok((Some(name), Some(self.parse_ty()?)))
+                } else {
+                    Ok((Some(name), None))
+                }
+            }
+            Token::UpperWord(_) => Ok((None, Some(self.parse_ty()?))),
+            _ => Ok((None, None)),
+        }
+    }
+
+    fn parse_raise_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_raise_expr");
+        assert!(self.consume(Token::KwRaise));
+        self.skip_ws();
+        let expr = self.parse_operator_expr()?;
+        self.lv -= 1;
+        Ok(ast::raise_expr(expr))
+    }
+
+    fn parse_case_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_case_expr");
+        assert!(self.consume(Token::KwCase));
+        self.skip_ws();
+        let cond_expr = self.parse_expr()?;
+        self.skip_ws();
+        self.expect(Token::Separator)?;
+        self.skip_wsn();
+        let mut when_clauses = vec![];
+        while self.consume(Token::KwWhen) {
+            self.skip_ws();
+            let mut exprs = vec![self.parse_expr()?];
+            self.skip_ws();
+            while self.consume(Token::Comma) {
+                self.skip_wsn();
+                exprs.push(self.parse_expr()?);
+                self.skip_ws();
+            }
+            if self.consume(Token::KwThen) {
+                self.skip_wsn();
+            } else {
+                self.expect(Token::Separator)?;
+            }
+            let body_exprs = self.parse_exprs(vec![Token::KwWhen, Token::KwElse, Token::KwEnd])?;
+            when_clauses.push(ast::AstWhenClause { exprs, body_exprs });
+        }
+        if when_clauses.is_empty() {
+            return Err(parse_error!(self, "case must have at least one `when'"));
+        }
+        let else_exprs = if self.consume(Token::KwElse) {
+            self.skip_wsn();
+            Some(self.parse_exprs(vec![Token::KwEnd])?)
+        } else {
+            None
+        };
+        self.expect(Token::KwEnd)?;
+        self.lv -= 1;
+        Ok(ast::case_expr(cond_expr, when_clauses, else_exprs))
+    }
+
+    fn parse_match_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_match_expr");
+        assert!(self.consume(Token::KwMatch));
+        self.skip_ws();
+        let cond_expr = self.parse_expr()?;
+        self.skip_ws();
+        self.expect(Token::Separator)?;
+        self.skip_wsn();
+        let mut clauses = vec![];
+        while self.consume(Token::KwWhen) {
+            self.skip_ws();
+            let pattern = self.parse_pattern()?;
+            self.skip_ws();
+            if self.consume(Token::KwThen) {
+                self.skip_wsn();
+            } else {
+                self.expect(Token::Separator)?;
+            }
+            let body_exprs = self.parse_exprs(vec![Token::KwWhen, Token::KwEnd])?;
+            clauses.push(ast::AstMatchClause {
+                pattern,
+                body_exprs,
+            });
+        }
+        if clauses.is_empty() {
+            return Err(parse_error!(self, "match must have at least one `when'"));
+        }
+        self.expect(Token::KwEnd)?;
+        self.lv -= 1;
+        Ok(ast::match_expr(cond_expr, clauses))
+    }
+
+    /// Parse a pattern of `match`
+    /// eg. `_`, `x`, `1`, `"foo"`, `Foo::Bar`, `Bar(x, _)`
+    fn parse_pattern(&mut self) -> Result<ast::AstPattern, Error> {
+        self.lv += 1;
+        self.debug_log("parse_pattern");
+        let pattern = match self.current_token() {
+            Token::LowerWord(s) => {
+                let name = s.to_string();
+                self.consume_token();
+                if self.consume(Token::Colon) {
+                    // `x: Foo`
+                    self.skip_ws();
+                    let names = self.parse_pattern_class_name()?;
+                    if name == "_" {
+                        ast::AstPattern::ExtractorPattern {
+                            names,
+                            params: vec![],
+                        }
+                    } else {
+                        ast::AstPattern::TypedBinding { name, names }
+                    }
+                } else if name == "_" {
+                    ast::AstPattern::Wildcard
+                } else {
+                    ast::AstPattern::Binding(name)
+                }
+            }
+            Token::UpperWord(_) => self.parse_extractor_pattern()?,
+            Token::Number(_) => ast::AstPattern::Literal(Box::new(self.parse_decimal_literal()?)),
+            Token::UnaryMinus => {
+                self.consume_token();
+                let lit = self.parse_decimal_literal()?;
+                ast::AstPattern::Literal(Box::new(ast::unary_expr(lit, "-@")))
+            }
+            Token::Str(_) => ast::AstPattern::Literal(Box::new(self.parse_string()?)),
+            Token::KwTrue | Token::KwFalse => {
+                let t = self.consume_token();
+                ast::AstPattern::Literal(Box::new(ast::pseudo_variable(t)))
+            }
+            token => return Err(parse_error!(self, "invalid pattern: {:?}", token)),
+        };
+        self.lv -= 1;
+        Ok(pattern)
+    }
+
+    /// Parse `Foo::Bar(x, y)` or `Foo::Bar`
+    fn parse_extractor_pattern(&mut self) -> Result<ast::AstPattern, Error> {
+        let names = self.parse_pattern_class_name()?;
+        let mut params = vec![];
+        if self.consume(Token::LParen) {
+            self.skip_wsn();
+            loop {
+                params.push(self.parse_pattern()?);
+                self.skip_wsn();
+                match self.current_token() {
+                    Token::Comma => {
+                        self.consume_token();
+                        self.skip_wsn();
+                    }
+                    Token::RParen => {
+                        self.consume_token();
+                        break;
+                    }
+                    token => {
+                        return Err(parse_error!(
+                            self,
+                            "unexpected token `{:?}' in a pattern",
+                            token
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(ast::AstPattern::ExtractorPattern { names, params })
+    }
+
+    /// Parse a class name in a pattern (eg. `Foo::Bar`)
+    fn parse_pattern_class_name(&mut self) -> Result<Vec<String>, Error> {
+        let mut names = vec![];
+        loop {
+            match self.current_token() {
+                Token::UpperWord(s) => {
+                    names.push(s.to_string());
+                    self.consume_token();
+                }
+                token => return Err(parse_error!(self, "unexpected token: {:?}", token)),
+            }
+            if !self.consume(Token::ColonColon) {
+                break;
+            }
+        }
+        Ok(names)
+    }
+
     // prim . methodName argumentWithParentheses? block?
     // prim [ indexingArgumentList? ] not(EQUAL)
     fn parse_primary_expr(&mut self) -> Result<AstExpression, Error> {
@@ -518,10 +823,14 @@ impl<'a> Parser<'a> {
         self.debug_log("parse_primary_expr");
         let mut expr = self.parse_atomic()?;
         loop {
-            if self.next_nonspace_token() == Token::Dot {
+            let next = self.next_nonspace_token();
+            if next == Token::Dot || next == Token::AndDot {
                 // TODO: Newline should also be allowed here (but Semicolon is not)
                 self.skip_ws();
                 expr = self.parse_method_chain(expr)?;
+            } else if self.current_token_is(Token::LSqBracket) {
+                // `foo[1]` (but not `foo [1]`)
+                expr = self.parse_subscript(expr)?;
             } else {
                 break;
             }
@@ -530,17 +839,38 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    /// Parse `.foo(args)`
+    /// Parse `[args]` of `foo[args]` (call of the method `[]`)
+    fn parse_subscript(&mut self, expr: AstExpression) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_subscript");
+        assert!(self.consume(Token::LSqBracket));
+        self.skip_wsn();
+        let args = self.parse_args()?;
+        if args.is_empty() {
+            return Err(parse_error!(self, "missing index"));
+        }
+        self.skip_wsn();
+        self.expect(Token::RSqBracket)?;
+        self.lv -= 1;
+        Ok(ast::method_call(Some(expr), "[]", args, true, false))
+    }
+
+    /// Parse `.foo(args)` or `&.foo(args)`
     fn parse_method_chain(&mut self, expr: AstExpression) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_method_chain");
-        // .
-        assert!(self.consume(Token::Dot));
+        // . or &.
+        let is_safe = self.consume(Token::AndDot);
+        if !is_safe {
+            assert!(self.consume(Token::Dot));
+        }
         self.skip_wsn();
 
         // Method name
         let method_name = match self.current_token() {
             Token::LowerWord(s) => s.clone(),
+            // `obj.class`
+            Token::KwClass => "class".to_string(),
             token => return Err(parse_error!(self, "invalid method name: {:?}", token)),
         };
         self.consume_token();
@@ -552,15 +882,25 @@ impl<'a> Parser<'a> {
             // .foo
             _ => (vec![], true),
         };
+        let call = ast::method_call(Some(expr), &method_name, args, true, may_have_paren_wo_args);
 
         self.lv -= 1;
-        Ok(ast::method_call(
-            Some(expr),
-            &method_name,
-            args,
-            true,
-            may_have_paren_wo_args,
-        ))
+        let call = self.parse_brace_block(call)?;
+        if is_safe {
+            Ok(ast::safe_method_call(call))
+        } else {
+            Ok(call)
+        }
+    }
+
+    /// Parse `{ |x| ... }` given to the method call `expr` (if any)
+    fn parse_brace_block(&mut self, expr: AstExpression) -> Result<AstExpression, Error> {
+        if self.next_nonspace_token() != Token::LBrace {
+            return Ok(expr);
+        }
+        self.skip_ws();
+        let block = self.parse_block()?;
+        Ok(ast::add_block_arg(expr, block))
     }
 
     fn parse_paren_and_args(&mut self) -> Result<Vec<AstExpression>, Error> {
@@ -595,7 +935,7 @@ impl<'a> Parser<'a> {
                 self.parse_const_ref(name)
             }
             Token::KwFn => self.parse_lambda(),
-            Token::KwSelf | Token::KwTrue | Token::KwFalse => {
+            Token::KwSelf | Token::KwTrue | Token::KwFalse | Token::KwNil => {
                 let t = token.clone();
                 self.consume_token();
                 Ok(ast::pseudo_variable(t))
@@ -606,8 +946,9 @@ impl<'a> Parser<'a> {
                 Ok(ast::ivar_ref(name))
             }
             Token::LSqBracket => self.parse_array_literal(),
+            Token::LBrace => self.parse_hash_literal(),
             Token::Number(_) => self.parse_decimal_literal(),
-            Token::Str(_) => self.parse_string(),
+            Token::Str(_) | Token::StrWithInterpolation(_) => self.parse_string(),
             Token::LParen => self.parse_parenthesized_expr(),
             token => Err(parse_error!(self, "unexpected token: {:?}", token)),
         }?;
@@ -622,13 +963,14 @@ impl<'a> Parser<'a> {
         let expr = match self.current_token() {
             Token::LParen => {
                 let arg_exprs = self.parse_paren_and_args()?;
-                ast::method_call(
+                let call = ast::method_call(
                     None, // receiver_expr
                     bare_name_str,
                     arg_exprs,
                     true,  // primary
                     false, // may_have_paren_wo_args
-                )
+                );
+                self.parse_brace_block(call)?
             }
             _ => ast::bare_name(&bare_name_str),
         };
@@ -651,6 +993,17 @@ impl<'a> Parser<'a> {
                 token => return Err(parse_error!(self, "unexpected token: {:?}", token)),
             }
         }
+        // Parse `A<B>` followed by a method call (eg. `Array<Int>.new`).
+        // Otherwise `<` is the operator (eg. `A<b`)
+        if self.current_token_is(Token::LessThan) {
+            let cur = self.current_position();
+            match self.parse_typ_args() {
+                Ok(typ_args) if self.current_token_is(Token::Dot) => {
+                    return Ok(ast::specialize_expr(names, typ_args));
+                }
+                _ => self.rewind_to(cur),
+            }
+        }
         Ok(ast::const_ref(names))
     }
 
@@ -669,6 +1022,53 @@ impl<'a> Parser<'a> {
         Ok(ast::lambda_expr(params, exprs))
     }
 
+    /// Parse `do |a, b| ... end` or `{ |a, b| ... }`
+    fn parse_block(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_block");
+        let end_token = match self.consume_token() {
+            Token::KwDo => Token::KwEnd,
+            Token::LBrace => Token::RBrace,
+            token => panic!("parse_block called on {:?}", token),
+        };
+        self.skip_ws();
+        let param_names = self.parse_block_params()?;
+        let exprs = self.parse_exprs(vec![end_token.clone()])?;
+        self.expect(end_token)?;
+        self.lv -= 1;
+        Ok(ast::block_expr(param_names, exprs))
+    }
+
+    /// Parse `|a, b|` (may be omitted)
+    fn parse_block_params(&mut self) -> Result<Vec<String>, Error> {
+        let mut names = vec![];
+        if self.consume(Token::OrOr) || !self.consume(Token::Or) {
+            return Ok(names);
+        }
+        loop {
+            self.skip_ws();
+            match self.current_token() {
+                Token::LowerWord(s) => {
+                    names.push(s.to_string());
+                    self.consume_token();
+                }
+                token => return Err(parse_error!(self, "invalid block parameter: {:?}", token)),
+            }
+            self.skip_ws();
+            match self.current_token() {
+                Token::Comma => {
+                    self.consume_token();
+                }
+                Token::Or => {
+                    self.consume_token();
+                    break;
+                }
+                token => return Err(parse_error!(self, "invalid block parameter: {:?}", token)),
+            }
+        }
+        Ok(names)
+    }
+
     fn parse_parenthesized_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_parenthesized_expr");
@@ -721,6 +1121,50 @@ impl<'a> Parser<'a> {
         Ok(ast::array_literal(exprs))
     }
 
+    fn parse_hash_literal(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_hash_literal");
+        assert!(self.consume(Token::LBrace));
+        let mut pairs = vec![];
+        self.skip_wsn();
+        loop {
+            match self.current_token() {
+                Token::RBrace => {
+                    self.consume_token();
+                    break;
+                }
+                Token::Comma => {
+                    return Err(parse_error!(self, "unexpected comma in a hash literal"))
+                }
+                _ => {
+                    let key = self.parse_expr()?;
+                    self.skip_wsn();
+                    self.expect(Token::FatArrow)?;
+                    self.skip_wsn();
+                    let value = self.parse_expr()?;
+                    pairs.push((key, value));
+                    self.skip_wsn();
+                    match self.current_token() {
+                        Token::Comma => {
+                            self.consume_token();
+                            self.skip_wsn();
+                        }
+                        Token::RBrace => (),
+                        token => {
+                            return Err(parse_error!(
+                                self,
+                                "unexpected token `{:?}' in a hash literal",
+                                token
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        self.lv -= 1;
+        Ok(ast::hash_literal(pairs))
+    }
+
     fn parse_decimal_literal(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_decimal_literal");
@@ -730,8 +1174,13 @@ impl<'a> Parser<'a> {
                     let value = s.parse().unwrap();
                     ast::float_literal(value)
                 } else {
-                    let value = s.parse().unwrap();
-                    ast::decimal_literal(value)
+                    match s.parse() {
+                        Ok(value) => ast::decimal_literal(value),
+                        Err(_) => {
+                            self.lv -= 1;
+                            return Err(parse_error!(self, "integer literal too large: {}", s));
+                        }
+                    }
                 }
             }
             _ => {
@@ -748,12 +1197,34 @@ impl<'a> Parser<'a> {
         self.debug_log("parse_string");
         let expr = match self.consume_token() {
             Token::Str(content) => ast::string_literal(content),
+            Token::StrWithInterpolation(parts) => {
+                let exprs = parts
+                    .into_iter()
+                    .map(|part| match part {
+                        StrLiteralPart::Str(content) => Ok(ast::string_literal(content)),
+                        StrLiteralPart::Expr(src) => Parser::parse_interpolated_expr(&src),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                ast::string_interpolation(exprs)
+            }
             _ => panic!("parse_string called on non-string token"),
         };
         self.lv -= 1;
         Ok(expr)
     }
 
+    /// Parse the source of `#{...}` in a string literal
+    fn parse_interpolated_expr(src: &str) -> Result<AstExpression, Error> {
+        let mut parser = Parser::new(src);
+        parser.skip_wsn();
+        let result = parser.parse_expr().and_then(|expr| {
+            parser.skip_wsn();
+            parser.expect_eof()?;
+            Ok(expr)
+        });
+        parser.lexer_error().map_or(result, Err)
+    }
+
     // func: parse_xx
     // Parse `xx op xx op ... xx`
     fn parse_binary_operator<F: Fn(&mut Self) -> Result<AstExpression, Error>>(
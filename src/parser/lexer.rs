@@ -1,4 +1,5 @@
-use super::token::Token;
+use super::token::{StrLiteralPart, Token};
+use crate::error::{Error, ErrorDetails};
 
 #[derive(Debug)]
 pub struct Lexer<'a> {
@@ -9,6 +10,9 @@ pub struct Lexer<'a> {
     space_seen: bool,
     pub current_token: Token,
     next_cur: Option<Cursor>,
+    /// The first error found (eg. an unterminated string). Reported by
+    /// `Parser::parse` instead of the errors caused by it
+    pub error: Option<Error>,
 }
 
 /// Flags to decide a `-`, `+`, etc. is unary or binary.
@@ -111,6 +115,7 @@ impl<'a> Lexer<'a> {
             space_seen: false,
             next_cur: None,
             current_token: Token::Bof,
+            error: None,
         };
         lexer.read_token();
         lexer
@@ -173,6 +178,31 @@ impl<'a> Lexer<'a> {
         self.read_token();
     }
 
+    /// Replace the current `>>` with `>` so that the second `>` will be the
+    /// next token (used for nested type arguments eg. `Array<Array<Int>>`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use shiika::parser::lexer::Lexer;
+    /// use shiika::parser::token::Token;
+    ///
+    /// let src = ">>";
+    /// let mut lexer = Lexer::new(src);
+    ///
+    /// lexer.split_rshift();
+    /// assert_eq!(lexer.current_token, Token::GreaterThan);
+    /// lexer.consume_token();
+    /// assert_eq!(lexer.current_token, Token::GreaterThan);
+    /// ```
+    pub fn split_rshift(&mut self) {
+        debug_assert!(self.current_token == Token::RShift);
+        let mut next_cur = self.cur.clone();
+        next_cur.proceed(self.src);
+        self.current_token = Token::GreaterThan;
+        self.next_cur = Some(next_cur);
+    }
+
     /// Return the next token while keeping the current one
     ///
     /// # Examples
@@ -209,7 +239,11 @@ impl<'a> Lexer<'a> {
             CharType::IVar => (self.read_ivar(&mut next_next_cur, Some(&next_cur)), None),
             CharType::Symbol => self.read_symbol(&mut next_next_cur),
             CharType::Number => (self.read_number(&mut next_next_cur, Some(&next_cur)), None),
-            CharType::Str => (self.read_str(&mut next_next_cur, Some(&next_cur)), None),
+            // (The error is recorded when the string is actually read)
+            CharType::Str => (
+                self.read_str(&mut next_next_cur).unwrap_or(Token::Eof),
+                None,
+            ),
             CharType::Eof => (self.read_eof(), None),
         };
         token
@@ -221,8 +255,15 @@ impl<'a> Lexer<'a> {
         let mut next_cur = self.cur.clone();
         let (token, new_state) = match self.char_type(c) {
             CharType::Space => (self.read_space(&mut next_cur), None),
-            CharType::Separator => (self.read_separator(&mut next_cur), None),
-            CharType::Comment => (self.read_comment(&mut next_cur), None),
+            // A new expression begins after a newline (eg. `-1` is unary)
+            CharType::Separator => (
+                self.read_separator(&mut next_cur),
+                Some(LexerState::ExprBegin),
+            ),
+            CharType::Comment => (
+                self.read_comment(&mut next_cur),
+                Some(LexerState::ExprBegin),
+            ),
             CharType::UpperWord => (
                 self.read_upper_word(&mut next_cur, None),
                 Some(LexerState::ExprEnd),
@@ -237,10 +278,13 @@ impl<'a> Lexer<'a> {
                 self.read_number(&mut next_cur, None),
                 Some(LexerState::ExprEnd),
             ),
-            CharType::Str => (
-                self.read_str(&mut next_cur, None),
-                Some(LexerState::ExprEnd),
-            ),
+            CharType::Str => match self.read_str(&mut next_cur) {
+                Ok(token) => (token, Some(LexerState::ExprEnd)),
+                Err(msg) => {
+                    self.set_error(msg, &next_cur);
+                    (Token::Eof, None)
+                }
+            },
             CharType::Eof => (self.read_eof(), None),
         };
         self.set_current_token(token);
@@ -250,6 +294,20 @@ impl<'a> Lexer<'a> {
         self.next_cur = Some(next_cur)
     }
 
+    /// Record an error at `cur` (unless another is already found)
+    fn set_error(&mut self, msg: String, cur: &Cursor) {
+        if self.error.is_none() {
+            self.error = Some(Error {
+                msg,
+                backtrace: backtrace::Backtrace::new(),
+                details: ErrorDetails::ParseError {
+                    location: cur.clone(),
+                },
+                source: None,
+            });
+        }
+    }
+
     fn read_space(&mut self, next_cur: &mut Cursor) -> Token {
         while let CharType::Space = self.char_type(next_cur.peek(self.src)) {
             next_cur.proceed(self.src);
@@ -305,6 +363,13 @@ impl<'a> Lexer<'a> {
                     }
                     break;
                 }
+                // `foo?`, `foo!` (but not `foo!=`)
+                CharType::Symbol if (c == Some('?') || c == Some('!')) => {
+                    if next_cur.peek2(self.src) != Some('=') {
+                        next_cur.proceed(self.src);
+                    }
+                    break;
+                }
                 _ => break,
             }
         }
@@ -314,7 +379,14 @@ impl<'a> Lexer<'a> {
         };
         let s = &self.src[begin..next_cur.pos];
         let (token, state) = match s {
+            "abstract" => (Token::KwAbstract, LexerState::ExprBegin),
             "class" => (Token::KwClass, LexerState::ExprBegin),
+            "module" => (Token::KwModule, LexerState::ExprBegin),
+            "interface" => (Token::KwInterface, LexerState::ExprBegin),
+            "enum" => (Token::KwEnum, LexerState::ExprBegin),
+            "case" => (Token::KwCase, LexerState::ExprBegin),
+            "match" => (Token::KwMatch, LexerState::ExprBegin),
+            "when" => (Token::KwWhen, LexerState::ExprBegin),
             "end" => (Token::KwEnd, LexerState::ExprEnd),
             "def" => (Token::KwDef, LexerState::ExprBegin),
             "var" => (Token::KwVar, LexerState::ExprBegin),
@@ -324,13 +396,20 @@ impl<'a> Lexer<'a> {
             "if" => (Token::KwIf, LexerState::ExprBegin),
             "unless" => (Token::KwUnless, LexerState::ExprBegin),
             "while" => (Token::KwWhile, LexerState::ExprBegin),
+            "do" => (Token::KwDo, LexerState::ExprBegin),
             "break" => (Token::KwBreak, LexerState::ExprEnd),
+            "return" => (Token::KwReturn, LexerState::ExprBegin),
+            "begin" => (Token::KwBegin, LexerState::ExprBegin),
+            "rescue" => (Token::KwRescue, LexerState::ExprBegin),
+            "ensure" => (Token::KwEnsure, LexerState::ExprBegin),
+            "raise" => (Token::KwRaise, LexerState::ExprBegin),
             "then" => (Token::KwThen, LexerState::ExprBegin),
             "else" => (Token::KwElse, LexerState::ExprBegin),
             "fn" => (Token::KwFn, LexerState::ExprBegin),
             "self" => (Token::KwSelf, LexerState::ExprEnd),
             "true" => (Token::KwTrue, LexerState::ExprEnd),
             "false" => (Token::KwFalse, LexerState::ExprEnd),
+            "nil" => (Token::KwNil, LexerState::ExprEnd),
             _ => (Token::LowerWord(s.to_string()), LexerState::ExprEnd),
         };
         (token, Some(state))
@@ -386,13 +465,28 @@ impl<'a> Lexer<'a> {
                     (Token::BinaryMinus, LexerState::ExprBegin)
                 }
             }
-            '*' => (Token::Mul, LexerState::ExprBegin),
+            '*' => {
+                if c2 == Some('*') {
+                    next_cur.proceed(self.src);
+                    (Token::Pow, LexerState::ExprBegin)
+                } else {
+                    (Token::Mul, LexerState::ExprBegin)
+                }
+            }
             '/' => (Token::Div, LexerState::ExprBegin),
             '%' => (Token::Mod, LexerState::ExprBegin),
             '=' => {
                 if c2 == Some('=') {
                     next_cur.proceed(self.src);
-                    (Token::EqEq, LexerState::ExprBegin)
+                    if next_cur.peek(self.src) == Some('=') {
+                        next_cur.proceed(self.src);
+                        (Token::EqEqEq, LexerState::ExprBegin)
+                    } else {
+                        (Token::EqEq, LexerState::ExprBegin)
+                    }
+                } else if c2 == Some('>') {
+                    next_cur.proceed(self.src);
+                    (Token::FatArrow, LexerState::ExprBegin)
                 } else {
                     (Token::Equal, LexerState::ExprBegin)
                 }
@@ -408,7 +502,12 @@ impl<'a> Lexer<'a> {
             '<' => {
                 if c2 == Some('=') {
                     next_cur.proceed(self.src);
-                    (Token::LessEq, LexerState::ExprBegin)
+                    if next_cur.peek(self.src) == Some('>') {
+                        next_cur.proceed(self.src);
+                        (Token::Spaceship, LexerState::ExprBegin)
+                    } else {
+                        (Token::LessEq, LexerState::ExprBegin)
+                    }
                 } else if c2 == Some('<') {
                     next_cur.proceed(self.src);
                     (Token::LShift, LexerState::ExprBegin)
@@ -444,6 +543,9 @@ impl<'a> Lexer<'a> {
                 if c2 == Some('&') {
                     next_cur.proceed(self.src);
                     (Token::AndAnd, LexerState::ExprBegin)
+                } else if c2 == Some('.') {
+                    next_cur.proceed(self.src);
+                    (Token::AndDot, LexerState::ExprBegin)
                 } else {
                     (Token::And, LexerState::ExprBegin)
                 }
@@ -506,28 +608,126 @@ impl<'a> Lexer<'a> {
         Token::Number(self.src[begin..next_cur.pos].to_string())
     }
 
-    fn read_str(&mut self, next_cur: &mut Cursor, cur: Option<&Cursor>) -> Token {
+    /// Read a string literal. Escape sequences are decoded here and
+    /// the source of each `#{...}` is kept as is (parsed by the parser)
+    fn read_str(&mut self, next_cur: &mut Cursor) -> Result<Token, String> {
         next_cur.proceed(self.src);
+        let mut parts = vec![];
+        let mut buf = String::new();
         loop {
             match next_cur.peek(self.src) {
-                None => {
-                    // TODO: should be a LexError
-                    panic!("found unterminated string");
-                }
+                None => return Err("found unterminated string".to_string()),
                 Some('"') => {
                     next_cur.proceed(self.src);
                     break;
                 }
-                _ => {
+                Some('\\') => {
+                    next_cur.proceed(self.src);
+                    buf.push(self.read_escape_sequence(next_cur)?);
+                }
+                Some('#') if next_cur.peek2(self.src) == Some('{') => {
+                    next_cur.proceed(self.src);
+                    next_cur.proceed(self.src);
+                    if !buf.is_empty() {
+                        parts.push(StrLiteralPart::Str(std::mem::take(&mut buf)));
+                    }
+                    parts.push(StrLiteralPart::Expr(self.read_interpolation(next_cur)?));
+                }
+                Some(c) => {
+                    buf.push(c);
                     next_cur.proceed(self.src);
                 }
             }
         }
-        let begin = match cur {
-            Some(c) => c.pos,
-            None => self.cur.pos,
+        if parts.is_empty() {
+            Ok(Token::Str(buf))
+        } else {
+            if !buf.is_empty() {
+                parts.push(StrLiteralPart::Str(buf));
+            }
+            Ok(Token::StrWithInterpolation(parts))
+        }
+    }
+
+    /// Read the char after a backslash and return the char it represents
+    fn read_escape_sequence(&self, next_cur: &mut Cursor) -> Result<char, String> {
+        let c = match next_cur.peek(self.src) {
+            Some(c) => c,
+            None => return Err("found unterminated string".to_string()),
+        };
+        next_cur.proceed(self.src);
+        let decoded = match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            's' => ' ',
+            'e' => '\x1b',
+            'a' => '\x07',
+            'b' => '\x08',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            'u' => self.read_unicode_escape(next_cur)?,
+            // `\\`, `\"`, `\#`, etc.
+            _ => c,
         };
-        Token::Str(self.src[(begin + 1)..(next_cur.pos - 1)].to_string())
+        Ok(decoded)
+    }
+
+    /// Read `{XXXX}` of `\u{XXXX}` (or `XXXX` of `\uXXXX`)
+    fn read_unicode_escape(&self, next_cur: &mut Cursor) -> Result<char, String> {
+        let braced = next_cur.peek(self.src) == Some('{');
+        if braced {
+            next_cur.proceed(self.src);
+        }
+        let mut hex = String::new();
+        while let Some(c) = next_cur.peek(self.src) {
+            if !c.is_ascii_hexdigit() || (!braced && hex.len() == 4) {
+                break;
+            }
+            hex.push(c);
+            next_cur.proceed(self.src);
+        }
+        if braced {
+            if next_cur.peek(self.src) != Some('}') {
+                return Err("unterminated unicode escape".to_string());
+            }
+            next_cur.proceed(self.src);
+        }
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(|| format!("invalid unicode escape: \\u{{{}}}", hex))
+    }
+
+    /// Read the source of `#{...}` up to the matching `}`
+    fn read_interpolation(&mut self, next_cur: &mut Cursor) -> Result<String, String> {
+        let begin = next_cur.pos;
+        let mut depth = 0;
+        loop {
+            match next_cur.peek(self.src) {
+                None => return Err("unterminated `#{' in a string literal".to_string()),
+                Some('}') if depth == 0 => break,
+                Some('}') => {
+                    depth -= 1;
+                    next_cur.proceed(self.src);
+                }
+                Some('{') => {
+                    depth += 1;
+                    next_cur.proceed(self.src);
+                }
+                // Skip nested string (which may contain `}`)
+                Some('"') => {
+                    self.read_str(next_cur)?;
+                }
+                Some(_) => {
+                    next_cur.proceed(self.src);
+                }
+            }
+        }
+        let src = self.src[begin..next_cur.pos].to_string();
+        next_cur.proceed(self.src);
+        Ok(src)
     }
 
     fn read_eof(&mut self) -> Token {
@@ -44,7 +44,14 @@ impl<'a> Parser<'a> {
 
     pub fn parse(src: &str) -> Result<ast::Program, Error> {
         let mut parser = Parser::new(src);
-        parser.parse_program()
+        let result = parser.parse_program();
+        parser.lexer_error().map_or(result, Err)
+    }
+
+    /// Return the error found by the lexer, if any. It should be reported
+    /// rather than the parse error caused by it
+    fn lexer_error(&mut self) -> Option<Error> {
+        self.lexer.error.take()
     }
 
     fn parse_program(&mut self) -> Result<ast::Program, Error> {
@@ -69,9 +76,21 @@ impl<'a> Parser<'a> {
         let mut items = vec![];
         loop {
             match self.current_token() {
+                Token::KwAbstract => {
+                    items.push(ast::TopLevelItem::Def(self.parse_abstract_definition()?));
+                }
                 Token::KwClass => {
                     items.push(ast::TopLevelItem::Def(self.parse_class_definition()?));
                 }
+                Token::KwModule => {
+                    items.push(ast::TopLevelItem::Def(self.parse_module_definition()?));
+                }
+                Token::KwInterface => {
+                    items.push(ast::TopLevelItem::Def(self.parse_interface_definition()?));
+                }
+                Token::KwEnum => {
+                    items.push(ast::TopLevelItem::Def(self.parse_enum_definition()?));
+                }
                 Token::KwDef => {
                     items.push(ast::TopLevelItem::Def(self.parse_method_definition()?));
                 }
@@ -9,6 +9,8 @@ pub enum Token {
     IVar(String),
     Number(String),
     Str(String),
+    /// String literal which contains `#{...}`
+    StrWithInterpolation(Vec<StrLiteralPart>),
     // Symbols
     LParen,       //  (
     RParen,       //  )
@@ -19,20 +21,25 @@ pub enum Token {
     UnaryPlus,    //  +a
     BinaryPlus,   //  a + b
     RightArrow,   //  ->
+    FatArrow,     //  =>
     UnaryMinus,   //  -a
     BinaryMinus,  //  a - b
     Mul,          //  *
+    Pow,          //  **
     Div,          //  /
     Mod,          //  %
     EqEq,         //  ==
+    EqEqEq,       //  ===
     NotEq,        //  !=
     LessThan,     //  <
     GreaterThan,  //  >
     LessEq,       //  <=
     GreaterEq,    //  >=
+    Spaceship,    //  <=>
     Equal,        //  =
     Bang,         //  !
     Dot,          //  .
+    AndDot,       //  &.
     At,           //  @
     Tilde,        //  ~
     Question,     //  ?
@@ -49,7 +56,14 @@ pub enum Token {
     UPlusMethod,  //  +@
     UMinusMethod, //  -@
     // Keywords
+    KwAbstract,
     KwClass,
+    KwModule,
+    KwInterface,
+    KwEnum,
+    KwCase,
+    KwMatch,
+    KwWhen,
     KwEnd,
     KwDef,
     KwVar,
@@ -59,13 +73,29 @@ pub enum Token {
     KwIf,
     KwUnless,
     KwWhile,
+    KwDo,
     KwBreak,
+    KwReturn,
+    KwBegin,
+    KwRescue,
+    KwEnsure,
+    KwRaise,
     KwThen,
     KwElse,
     KwFn,
     KwSelf,
     KwTrue,
     KwFalse,
+    KwNil,
+}
+
+/// A part of a string literal with interpolation
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum StrLiteralPart {
+    /// Literal part (escape sequences are already decoded)
+    Str(String),
+    /// Source of the expression in `#{...}`
+    Expr(String),
 }
 
 impl Token {
@@ -93,6 +123,7 @@ impl Token {
             Token::IVar(_) => true,
             Token::Number(_) => true,
             Token::Str(_) => true,
+            Token::StrWithInterpolation(_) => true,
             // Symbols
             Token::LParen => true,        //  (
             Token::RParen => false,       //  )
@@ -103,20 +134,25 @@ impl Token {
             Token::UnaryPlus => true,     //  +
             Token::BinaryPlus => false,   //  +
             Token::RightArrow => false,   //  ->
+            Token::FatArrow => false,     //  =>
             Token::UnaryMinus => true,    //  -
             Token::BinaryMinus => false,  //  -
             Token::Mul => false,          //  *
+            Token::Pow => false,          //  **
             Token::Div => false,          //  /
             Token::Mod => false,          //  %
             Token::EqEq => false,         //  ==
+            Token::EqEqEq => false,       //  ===
             Token::NotEq => false,        //  !=
             Token::LessThan => false,     //  <
             Token::GreaterThan => false,  //  >
             Token::LessEq => false,       //  <=
             Token::GreaterEq => false,    //  >=
+            Token::Spaceship => false,    //  <=>
             Token::Equal => false,        //  =
             Token::Bang => true,          //  !
             Token::Dot => false,          //  .
+            Token::AndDot => false,       //  &.
             Token::At => true,            //  @
             Token::Tilde => true,         //  ~
             Token::Question => false,     //  ?
@@ -133,23 +169,37 @@ impl Token {
             Token::UPlusMethod => false,  //  +@
             Token::UMinusMethod => false, //  -@
             // Keywords
+            Token::KwAbstract => false,
             Token::KwClass => false,
+            Token::KwModule => false,
+            Token::KwInterface => false,
+            Token::KwEnum => false,
+            Token::KwCase => true,
+            Token::KwMatch => true,
+            Token::KwWhen => false,
             Token::KwEnd => false,
             Token::KwDef => false,
             Token::KwVar => false,
             Token::KwAnd => false,
             Token::KwOr => false,
             Token::KwNot => true,
-            Token::KwIf => true,
-            Token::KwUnless => true,
+            Token::KwIf => false, // `foo if bar` is a modifier
+            Token::KwUnless => false,
             Token::KwWhile => true,
+            Token::KwDo => false,
             Token::KwBreak => false,
+            Token::KwReturn => false,
+            Token::KwBegin => true,
+            Token::KwRescue => false,
+            Token::KwEnsure => false,
+            Token::KwRaise => true,
             Token::KwThen => false,
             Token::KwElse => false,
             Token::KwFn => true,
             Token::KwSelf => true,
             Token::KwTrue => true,
             Token::KwFalse => true,
+            Token::KwNil => true,
         }
     }
 }
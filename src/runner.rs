@@ -6,6 +6,18 @@ use std::process::Command;
 
 /// Generate .ll from .sk
 pub fn compile<P: AsRef<Path>>(filepath: P) -> Result<(), Box<dyn std::error::Error>> {
+    compile_(filepath, false)
+}
+
+/// Generate .ll from .sk, raising OverflowError on Int overflow
+pub fn compile_checked<P: AsRef<Path>>(filepath: P) -> Result<(), Box<dyn std::error::Error>> {
+    compile_(filepath, true)
+}
+
+fn compile_<P: AsRef<Path>>(
+    filepath: P,
+    checked_arithmetic: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     let path = filepath
         .as_ref()
         .to_str()
@@ -18,7 +30,7 @@ pub fn compile<P: AsRef<Path>>(filepath: P) -> Result<(), Box<dyn std::error::Er
     let ast = crate::parser::Parser::parse(&str)?;
     let corelib = crate::corelib::Corelib::create();
     let hir = crate::hir::build(ast, corelib)?;
-    crate::code_gen::run(&hir, &(path + ".ll"))?;
+    crate::code_gen::run(&hir, &(path + ".ll"), checked_arithmetic)?;
     Ok(())
 }
 
@@ -48,13 +60,22 @@ pub fn run<P: AsRef<Path>>(sk_path: P) -> Result<(), Box<dyn std::error::Error>>
 pub fn run_and_capture<P: AsRef<Path>>(
     sk_path: P,
 ) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let (stdout, stderr, _) = run_(sk_path, true)?;
+    Ok((stdout, stderr))
+}
+
+/// Execute compiled .ll and return the outputs and whether it exited
+/// successfully
+pub fn run_and_capture_status<P: AsRef<Path>>(
+    sk_path: P,
+) -> Result<(String, String, bool), Box<dyn std::error::Error>> {
     run_(sk_path, true)
 }
 
 fn run_<P: AsRef<Path>>(
     sk_path: P,
     capture_out: bool,
-) -> Result<(String, String), Box<dyn std::error::Error>> {
+) -> Result<(String, String, bool), Box<dyn std::error::Error>> {
     let s = sk_path.as_ref().to_str().expect("failed to unwrap sk_path");
     let ll_path = s.to_string() + ".ll";
     //let opt_ll_path = s.to_string() + ".opt.ll";
@@ -112,10 +133,10 @@ fn run_<P: AsRef<Path>>(
             .map_err(|e| runner_error("failed to execute process", e))?;
         let stdout = String::from_utf8(output.stdout).expect("invalid utf8 in stdout");
         let stderr = String::from_utf8(output.stderr).expect("invalid utf8 in stderr");
-        Ok((stdout, stderr))
+        Ok((stdout, stderr, output.status.success()))
     } else {
-        cmd.status()?;
-        Ok(("".to_string(), "".to_string()))
+        let status = cmd.status()?;
+        Ok(("".to_string(), "".to_string(), status.success()))
     }
 }
 
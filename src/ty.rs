@@ -59,9 +59,23 @@ pub enum TyBody {
     },
     // Type parameter reference eg. `T`
     TyParamRef {
+        kind: TyParamKind,
         name: String,
         idx: usize,
+        upper_bound: Box<TermTy>,
     },
+    // Type which may be nil eg. `Int?`
+    // (`nil` itself is `Never?`)
+    TyNullable {
+        inner: Box<TermTy>,
+    },
+}
+
+/// Whether a type parameter belongs to a class or a method
+#[derive(Debug, PartialEq, Clone)]
+pub enum TyParamKind {
+    Class,  // eg. `T` of `class Array<T>`
+    Method, // eg. `U` of `def map<U>`
 }
 
 use TyBody::*;
@@ -75,6 +89,30 @@ impl TermTy {
         }
     }
 
+    // Returns true when this is the Never type
+    pub fn is_never_type(&self) -> bool {
+        match self.body {
+            TyRaw => self.fullname.0 == "Never",
+            _ => false,
+        }
+    }
+
+    /// Returns true if this may be nil
+    pub fn is_nullable(&self) -> bool {
+        match self.body {
+            TyNullable { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the type without `?` (eg. `Int` for `Int?`)
+    pub fn non_nullable(&self) -> &TermTy {
+        match &self.body {
+            TyNullable { inner } => inner,
+            _ => self,
+        }
+    }
+
     pub fn meta_ty(&self) -> TermTy {
         match self.body {
             TyRaw => ty::meta(&self.fullname.0),
@@ -84,12 +122,65 @@ impl TermTy {
         }
     }
 
-    pub fn conforms_to(&self, other: &TermTy) -> bool {
-        if let TyParamRef { .. } = other.body {
-            return self == &ty::raw("Object"); // The upper bound
+    /// Return true if a value of this type can be used as `other`
+    /// (i.e. `other` is this type or its ancestor)
+    pub fn conforms_to(&self, other: &TermTy, class_dict: &ClassDict) -> bool {
+        // Never returns, so it can be anything
+        if self.is_never_type() {
+            return true;
+        }
+        // `Int` and `nil` can be used as `Int?` but `Int?` cannot be `Int`
+        match (&self.body, &other.body) {
+            (TyNullable { inner: inner1 }, TyNullable { inner: inner2 }) => {
+                return inner1.conforms_to(inner2, class_dict)
+            }
+            (TyNullable { .. }, _) => return false,
+            (_, TyNullable { inner }) => return self.conforms_to(inner, class_dict),
+            _ => (),
+        }
+        if let TyParamRef { upper_bound, .. } = &other.body {
+            return self.equals_to(other) || self.conforms_to(upper_bound, class_dict);
+        }
+        if let TyParamRef { upper_bound, .. } = &self.body {
+            return self.equals_to(other) || upper_bound.conforms_to(other, class_dict);
         }
-        // TODO: Should respect class hierarchy
-        self.equals_to(other)
+        class_dict.ancestor_types(self).iter().any(|t| {
+            t.equals_to(other)
+                || t.conforms_by_variance(other, class_dict)
+                || class_dict.implements(t, other)
+        })
+    }
+
+    /// Return true if self and `other` are the same generic class and
+    /// the type arguments conform according to the variance of the type
+    /// parameters (eg. `Fn0<Dog>` can be used as `Fn0<Animal>`)
+    fn conforms_by_variance(&self, other: &TermTy, class_dict: &ClassDict) -> bool {
+        let (base_name, args1, args2) = match (&self.body, &other.body) {
+            (
+                TySpe {
+                    base_name,
+                    type_args: args1,
+                },
+                TySpe {
+                    base_name: base2,
+                    type_args: args2,
+                },
+            ) if base_name == base2 && args1.len() == args2.len() => (base_name, args1, args2),
+            _ => return false,
+        };
+        let typarams = match class_dict.find_class(&class_fullname(base_name)) {
+            Some(cls) => &cls.typarams,
+            None => return false,
+        };
+        args1
+            .iter()
+            .zip(args2.iter())
+            .enumerate()
+            .all(|(i, (a1, a2))| match typarams.get(i).map(|t| &t.variance) {
+                Some(Variance::Covariant) => a1.conforms_to(a2, class_dict),
+                Some(Variance::Contravariant) => a2.conforms_to(a1, class_dict),
+                _ => a1.equals_to(a2),
+            })
     }
 
     /// Return true if two types are identical
@@ -103,25 +194,93 @@ impl TermTy {
             TyRaw => class_dict
                 .get_superclass(&self.fullname)
                 .map(|scls| ty::raw(&scls.fullname.0)),
-            TyMeta { base_fullname } => {
-                match class_dict.get_superclass(&class_fullname(base_fullname)) {
+            TyMeta {
+                base_fullname: base_name,
+            }
+            | TyGenMeta { base_name, .. }
+            | TySpeMeta { base_name, .. } => {
+                match class_dict.get_superclass(&class_fullname(base_name)) {
                     Some(scls) => Some(ty::meta(&scls.fullname.0)),
                     None => Some(ty::class()), // Meta:Object < Class
                 }
             }
             TyClass => Some(ty::raw("Object")),
-            _ => panic!("TODO"),
+            TyParamRef { upper_bound, .. } => Some((**upper_bound).clone()),
+            TyNullable { inner } => inner.supertype(class_dict).map(ty::nullable),
+            TySpe {
+                base_name,
+                type_args,
+            } => {
+                // TODO #115: Assumes that a generic superclass takes the
+                // same type arguments (eg. `Maybe::Some<T> : Maybe<T>`)
+                class_dict
+                    .get_superclass(&class_fullname(base_name))
+                    .map(|scls| {
+                        if scls.typarams.is_empty() {
+                            ty::raw(&scls.fullname.0)
+                        } else {
+                            ty::spe(&scls.fullname.0, type_args.clone())
+                        }
+                    })
+            }
         }
     }
 
     /// Apply type argments into type parameters
-    pub fn substitute(&self, type_args: &[TermTy]) -> TermTy {
+    /// (Type parameters are left as is if the type arguments are empty)
+    pub fn substitute(&self, class_tyargs: &[TermTy], method_tyargs: &[TermTy]) -> TermTy {
         match &self.body {
-            TyParamRef { idx, .. } => type_args[*idx].clone(),
+            TyParamRef { kind, idx, .. } => {
+                let tyargs = match kind {
+                    TyParamKind::Class => class_tyargs,
+                    TyParamKind::Method => method_tyargs,
+                };
+                match tyargs.get(*idx) {
+                    Some(t) => t.clone(),
+                    None => self.clone(),
+                }
+            }
+            // eg. `Fn1<T, Void>` of `Array<Int>` is `Fn1<Int, Void>`
+            TySpe {
+                base_name,
+                type_args: args,
+            } => ty::spe(
+                base_name,
+                args.iter()
+                    .map(|t| t.substitute(class_tyargs, method_tyargs))
+                    .collect(),
+            ),
+            TyNullable { inner } => ty::nullable(inner.substitute(class_tyargs, method_tyargs)),
             _ => self.clone(),
         }
     }
 
+    /// Replace `Self` (used in interfaces) with `self_ty`
+    pub fn replace_self(&self, self_ty: &TermTy) -> TermTy {
+        match &self.body {
+            TyRaw if self.fullname.0 == "Self" => self_ty.clone(),
+            TySpe {
+                base_name,
+                type_args,
+            } => ty::spe(
+                base_name,
+                type_args.iter().map(|t| t.replace_self(self_ty)).collect(),
+            ),
+            TyNullable { inner } => ty::nullable(inner.replace_self(self_ty)),
+            _ => self.clone(),
+        }
+    }
+
+    /// Return true if `Self` (used in interfaces) appears in this type
+    pub fn contains_self(&self) -> bool {
+        match &self.body {
+            TyRaw => self.fullname.0 == "Self",
+            TySpe { type_args, .. } => type_args.iter().any(|t| t.contains_self()),
+            TyNullable { inner } => inner.contains_self(),
+            _ => false,
+        }
+    }
+
     pub fn is_specialized(&self) -> bool {
         match self.body {
             TySpe { .. } | TySpeMeta { .. } => true,
@@ -167,19 +326,88 @@ pub fn spe(base_name: &str, type_args: Vec<TermTy>) -> TermTy {
     }
 }
 
-pub fn typaram(name: impl Into<String>, idx: usize) -> TermTy {
+/// Returns the type of the class object eg. `Meta:Array<Int>`
+pub fn spe_meta(base_name: &str, type_args: Vec<TermTy>) -> TermTy {
+    let spe = spe(base_name, type_args.clone());
+    TermTy {
+        fullname: metaclass_fullname(&spe.fullname.0),
+        body: TySpeMeta {
+            base_name: base_name.to_string(),
+            type_args,
+        },
+    }
+}
+
+/// Returns `T?` for `T`
+pub fn nullable(inner: TermTy) -> TermTy {
+    if inner.is_nullable() {
+        return inner;
+    }
+    TermTy {
+        fullname: class_fullname(&format!("{}?", &inner.fullname.0)),
+        body: TyNullable {
+            inner: Box::new(inner),
+        },
+    }
+}
+
+/// The type of `nil`
+pub fn nil() -> TermTy {
+    nullable(raw("Never"))
+}
+
+pub fn typaram(
+    name: impl Into<String>,
+    kind: TyParamKind,
+    idx: usize,
+    upper_bound: TermTy,
+) -> TermTy {
     let s = name.into();
     TermTy {
         fullname: class_fullname(&s),
-        body: TyParamRef { name: s, idx },
+        body: TyParamRef {
+            kind,
+            name: s,
+            idx,
+            upper_bound: Box::new(upper_bound),
+        },
     }
 }
 
 /// A type parameter
-/// In the future, may have something like +T/-T or in/out
 #[derive(Debug, PartialEq, Clone)]
 pub struct TyParam {
     pub name: String,
+    pub variance: Variance,
+    /// eg. `Comparable` of `T: Comparable` (`Object` if not given)
+    pub upper_bound: TermTy,
+}
+
+impl TyParam {
+    /// Create an invariant type parameter without bound
+    pub fn new(name: impl Into<String>) -> TyParam {
+        TyParam {
+            name: name.into(),
+            variance: Variance::Invariant,
+            upper_bound: ty::raw("Object"),
+        }
+    }
+}
+
+/// Variance of a type parameter
+#[derive(Debug, PartialEq, Clone)]
+pub enum Variance {
+    Invariant,
+    Covariant,     // eg. `out T`
+    Contravariant, // eg. `in T`
+}
+
+/// Visibility of a method
+#[derive(Debug, PartialEq, Clone)]
+pub enum Visibility {
+    Public,
+    Private,   // eg. `private def foo`
+    Protected, // eg. `protected def foo`
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -187,6 +415,8 @@ pub struct MethodSignature {
     pub fullname: MethodFullname,
     pub ret_ty: TermTy,
     pub params: Vec<MethodParam>,
+    pub typarams: Vec<TyParam>,
+    pub visibility: Visibility,
 }
 
 impl MethodSignature {
@@ -203,17 +433,78 @@ impl MethodSignature {
     }
 
     /// Substitute type parameters with type arguments
-    pub fn specialize(&self, type_args: &[TermTy]) -> MethodSignature {
+    pub fn specialize(&self, class_tyargs: &[TermTy], method_tyargs: &[TermTy]) -> MethodSignature {
         MethodSignature {
             fullname: self.fullname.clone(),
-            ret_ty: self.ret_ty.substitute(&type_args),
+            ret_ty: self.ret_ty.substitute(class_tyargs, method_tyargs),
             params: self
                 .params
                 .iter()
-                .map(|param| param.substitute(&type_args))
+                .map(|param| param.substitute(class_tyargs, method_tyargs))
                 .collect(),
+            // The method type parameters are consumed if given
+            typarams: if method_tyargs.is_empty() {
+                self.typarams
+                    .iter()
+                    .map(|t| TyParam {
+                        upper_bound: t.upper_bound.substitute(class_tyargs, &[]),
+                        ..t.clone()
+                    })
+                    .collect()
+            } else {
+                vec![]
+            },
+            visibility: self.visibility.clone(),
         }
     }
+
+    /// Replace `Self` in the signature with `self_ty`
+    pub fn replace_self(&self, self_ty: &TermTy) -> MethodSignature {
+        MethodSignature {
+            fullname: self.fullname.clone(),
+            ret_ty: self.ret_ty.replace_self(self_ty),
+            params: self
+                .params
+                .iter()
+                .map(|param| MethodParam {
+                    name: param.name.clone(),
+                    ty: param.ty.replace_self(self_ty),
+                })
+                .collect(),
+            typarams: self.typarams.clone(),
+            visibility: self.visibility.clone(),
+        }
+    }
+
+    /// Return true if a method of this signature can be called as `other`
+    /// (The params are contravariant and the return type is covariant)
+    pub fn conforms_to(&self, other: &MethodSignature, class_dict: &ClassDict) -> bool {
+        self.params.len() == other.params.len()
+            && self.typarams.len() == other.typarams.len()
+            && self
+                .params
+                .iter()
+                .zip(other.params.iter())
+                .all(|(p1, p2)| p2.ty.conforms_to(&p1.ty, class_dict))
+            && (other.ret_ty.is_void_type() || self.ret_ty.conforms_to(&other.ret_ty, class_dict))
+    }
+}
+
+/// eg. `<=>(other: Version) -> Int`
+impl std::fmt::Display for MethodSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|p| format!("{}: {}", p.name, p.ty))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "`{}({}) -> {}'",
+            self.fullname.first_name, params, self.ret_ty
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -223,10 +514,10 @@ pub struct MethodParam {
 }
 
 impl MethodParam {
-    pub fn substitute(&self, type_args: &[TermTy]) -> MethodParam {
+    pub fn substitute(&self, class_tyargs: &[TermTy], method_tyargs: &[TermTy]) -> MethodParam {
         MethodParam {
             name: self.name.clone(),
-            ty: self.ty.substitute(&type_args),
+            ty: self.ty.substitute(class_tyargs, method_tyargs),
         }
     }
 }
@@ -1,5 +1,7 @@
 use crate::error::Error;
 use crate::hir;
+use crate::hir::class_dict::ClassDict;
+use crate::names::*;
 use crate::ty;
 use crate::ty::*;
 
@@ -9,8 +11,12 @@ macro_rules! type_error {
     })
 }
 
-pub fn check_return_value(sig: &MethodSignature, ty: &TermTy) -> Result<(), Error> {
-    if ty.conforms_to(&sig.ret_ty) || sig.ret_ty.is_void_type() {
+pub fn check_return_value(
+    class_dict: &ClassDict,
+    sig: &MethodSignature,
+    ty: &TermTy,
+) -> Result<(), Error> {
+    if ty.conforms_to(&sig.ret_ty, class_dict) || sig.ret_ty.is_void_type() {
         Ok(())
     } else {
         Err(type_error!(
@@ -42,8 +48,13 @@ pub fn check_condition_ty(ty: &TermTy, on: &str) -> Result<(), Error> {
     }
 }
 
-pub fn check_reassign_var(orig_ty: &TermTy, new_ty: &TermTy, name: &str) -> Result<(), Error> {
-    if orig_ty.equals_to(new_ty) {
+pub fn check_reassign_var(
+    class_dict: &ClassDict,
+    orig_ty: &TermTy,
+    new_ty: &TermTy,
+    name: &str,
+) -> Result<(), Error> {
+    if new_ty.conforms_to(orig_ty, class_dict) {
         Ok(())
     } else {
         Err(type_error!(
@@ -56,6 +67,7 @@ pub fn check_reassign_var(orig_ty: &TermTy, new_ty: &TermTy, name: &str) -> Resu
 }
 
 pub fn check_method_args(
+    class_dict: &ClassDict,
     sig: &MethodSignature,
     arg_tys: &[&TermTy],
     receiver_hir: &hir::HirExpression,
@@ -76,7 +88,7 @@ pub fn check_method_args(
         .iter()
         .zip(arg_tys.iter())
         .try_for_each(|(param, arg_ty)| {
-            if arg_ty.conforms_to(&param.ty) {
+            if arg_ty.conforms_to(&param.ty, class_dict) {
                 Ok(())
             } else {
                 Err(type_error!(
@@ -92,3 +104,158 @@ pub fn check_method_args(
 
     Ok(())
 }
+
+/// Check that the patterns of a `match` cover all the values of `value_ty`
+/// - `enum_cases`: the cases if the value is an enum
+/// - `covered`: classes whose instances are always matched by some pattern
+pub fn check_match_exhaustiveness(
+    value_ty: &TermTy,
+    value_class: &ClassFullname,
+    enum_cases: Option<&Vec<ClassFullname>>,
+    covered: &[ClassFullname],
+) -> Result<(), Error> {
+    if covered.contains(value_class) {
+        return Ok(());
+    }
+    match enum_cases {
+        Some(cases) => match cases.iter().find(|case| !covered.contains(case)) {
+            Some(case) => Err(type_error!(
+                "non-exhaustive match on {}: `{}' is not covered",
+                value_ty,
+                case
+            )),
+            None => Ok(()),
+        },
+        None => Err(type_error!(
+            "non-exhaustive match on {} (Hint: add `when _')",
+            value_ty
+        )),
+    }
+}
+
+/// Check that the type arguments satisfy the upper bounds of the type
+/// parameters (eg. `SortedList<Int>` for `SortedList<T: Comparable>`)
+pub fn check_tyarg_bounds(
+    class_dict: &ClassDict,
+    name: &str,
+    typarams: &[TyParam],
+    tyargs: &[TermTy],
+) -> Result<(), Error> {
+    typarams
+        .iter()
+        .zip(tyargs.iter())
+        .try_for_each(|(typaram, tyarg)| {
+            if tyarg.conforms_to(&typaram.upper_bound, class_dict) {
+                Ok(())
+            } else {
+                Err(type_error!(
+                    "type argument {} of {} does not satisfy the bound {} of {}",
+                    tyarg,
+                    name,
+                    typaram.upper_bound,
+                    typaram.name
+                ))
+            }
+        })
+}
+
+/// Check that the covariant (`out`) and contravariant (`in`) type
+/// parameters of a class only appear where it is sound
+/// (eg. `out T` must not be a method param)
+pub fn check_variance(class_dict: &ClassDict, class: &hir::SkClass) -> Result<(), Error> {
+    if class
+        .typarams
+        .iter()
+        .all(|t| t.variance == Variance::Invariant)
+    {
+        return Ok(());
+    }
+    class
+        .method_sigs
+        .values()
+        // The instance is not visible as `Foo<Animal>` yet
+        .filter(|sig| sig.first_name().0 != "initialize")
+        .try_for_each(|sig| {
+            sig.params.iter().try_for_each(|param| {
+                check_variance_position(
+                    class_dict,
+                    &class.typarams,
+                    &param.ty,
+                    &Variance::Contravariant,
+                    sig,
+                )
+            })?;
+            check_variance_position(
+                class_dict,
+                &class.typarams,
+                &sig.ret_ty,
+                &Variance::Covariant,
+                sig,
+            )
+        })
+}
+
+/// `position` is Covariant for return values and Contravariant for
+/// params (flipped by `in` type parameters like `S1` of `Fn1<S1, T>`)
+fn check_variance_position(
+    class_dict: &ClassDict,
+    typarams: &[TyParam],
+    ty: &TermTy,
+    position: &Variance,
+    sig: &MethodSignature,
+) -> Result<(), Error> {
+    match &ty.body {
+        TyBody::TyParamRef {
+            kind: TyParamKind::Class,
+            name,
+            idx,
+            ..
+        } => {
+            let variance = typarams
+                .get(*idx)
+                .map(|t| &t.variance)
+                .unwrap_or(&Variance::Invariant);
+            if *variance == Variance::Invariant || variance == position {
+                Ok(())
+            } else {
+                Err(type_error!(
+                    "{} type parameter {} cannot appear in a {} position (in {})",
+                    variance_name(variance),
+                    name,
+                    variance_name(position),
+                    sig.fullname
+                ))
+            }
+        }
+        TyBody::TySpe {
+            base_name,
+            type_args,
+        } => {
+            let base_typarams = class_dict
+                .find_class(&class_fullname(base_name))
+                .map(|cls| cls.typarams.clone())
+                .unwrap_or_default();
+            type_args.iter().enumerate().try_for_each(|(i, arg)| {
+                let arg_position = match base_typarams.get(i).map(|t| &t.variance) {
+                    Some(Variance::Covariant) => position.clone(),
+                    Some(Variance::Contravariant) => match position {
+                        Variance::Covariant => Variance::Contravariant,
+                        Variance::Contravariant => Variance::Covariant,
+                        Variance::Invariant => Variance::Invariant,
+                    },
+                    _ => Variance::Invariant,
+                };
+                check_variance_position(class_dict, typarams, arg, &arg_position, sig)
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn variance_name(variance: &Variance) -> &'static str {
+    match variance {
+        Variance::Invariant => "invariant",
+        Variance::Covariant => "covariant",
+        Variance::Contravariant => "contravariant",
+    }
+}
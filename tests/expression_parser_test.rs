@@ -59,6 +59,21 @@ fn test_equality_expr() {
     )
 }
 
+#[test]
+fn test_spaceship_expr() {
+    let result = parse_expr("1 <=> 2");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::decimal_literal(1)),
+            "<=>",
+            vec![ast::decimal_literal(2)],
+            false,
+            false
+        )
+    )
+}
+
 #[test]
 fn test_relational_expr() {
     let result = parse_expr("1 < 2 < 3");
@@ -127,6 +142,17 @@ fn test_unary() {
     )
 }
 
+#[test]
+fn test_power_expr() {
+    let result = parse_expr("-2 ** 3 ** 2");
+    let pow = ast::bin_op_expr(
+        ast::decimal_literal(2),
+        "**",
+        ast::bin_op_expr(ast::decimal_literal(3), "**", ast::decimal_literal(2)),
+    );
+    assert_eq!(result.unwrap(), ast::unary_expr(pow, "-@"))
+}
+
 #[test]
 fn test_binary() {
     let result = parse_expr("p - 1");
@@ -168,6 +194,13 @@ fn test_decimal_literal() {
     assert_eq!(result.unwrap(), ast::decimal_literal(123),)
 }
 
+#[test]
+fn test_decimal_literal_64bit() {
+    let result = parse_expr("9223372036854775807");
+    assert_eq!(result.unwrap(), ast::decimal_literal(9223372036854775807),);
+    assert!(parse_expr("9223372036854775808").is_err());
+}
+
 //
 // Method call (0 args)
 //
@@ -196,6 +229,22 @@ fn test_call_with_dot() {
     )
 }
 
+#[test]
+fn test_call_with_question_mark() {
+    let result = parse_expr("a.empty? != b");
+    let left = ast::method_call(Some(ast::bare_name("a")), "empty?", vec![], true, true);
+    assert_eq!(
+        result.unwrap(),
+        ast::logical_not(ast::method_call(
+            Some(left),
+            "==",
+            vec![ast::bare_name("b")],
+            false,
+            false
+        ))
+    )
+}
+
 //
 // Method call (1 arg)
 //
@@ -251,3 +300,404 @@ fn test_call_with_space_2() {
         )
     )
 }
+
+//
+// Method call with a block
+//
+
+#[test]
+fn test_call_with_do_block() {
+    let result = parse_expr("a.each do |x| x end");
+    let block = ast::block_expr(vec!["x".to_string()], vec![ast::bare_name("x")]);
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(Some(ast::bare_name("a")), "each", vec![block], true, false)
+    )
+}
+
+#[test]
+fn test_call_with_brace_block() {
+    let result = parse_expr("a.map(1) { |x, y| x }");
+    let block = ast::block_expr(
+        vec!["x".to_string(), "y".to_string()],
+        vec![ast::bare_name("x")],
+    );
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::bare_name("a")),
+            "map",
+            vec![ast::decimal_literal(1), block],
+            true,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_call_with_space_and_do_block() {
+    let result = parse_expr("foo 1 do\n  2\nend");
+    let block = ast::block_expr(vec![], vec![ast::decimal_literal(2)]);
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            None,
+            "foo",
+            vec![ast::decimal_literal(1), block],
+            false,
+            false
+        )
+    )
+}
+
+//
+// Subscript
+//
+
+#[test]
+fn test_subscript() {
+    let result = parse_expr("a[1]");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::bare_name("a")),
+            "[]",
+            vec![ast::decimal_literal(1)],
+            true,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_subscript_assign() {
+    let result = parse_expr("a[1, 2] = 3");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::bare_name("a")),
+            "[]=",
+            vec![
+                ast::decimal_literal(1),
+                ast::decimal_literal(2),
+                ast::decimal_literal(3)
+            ],
+            false,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_call_with_array_arg() {
+    let result = parse_expr("p [1]");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            None,
+            "p",
+            vec![ast::array_literal(vec![ast::decimal_literal(1)])],
+            false,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_hash_literal() {
+    let result = parse_expr("{1 => 2, 3 => 4}");
+    assert_eq!(
+        result.unwrap(),
+        ast::hash_literal(vec![
+            (ast::decimal_literal(1), ast::decimal_literal(2)),
+            (ast::decimal_literal(3), ast::decimal_literal(4)),
+        ])
+    )
+}
+
+#[test]
+fn test_hash_literal_multiline() {
+    let result = parse_expr("{\n  1 => 2,\n  3 => 4\n}");
+    assert_eq!(
+        result.unwrap(),
+        ast::hash_literal(vec![
+            (ast::decimal_literal(1), ast::decimal_literal(2)),
+            (ast::decimal_literal(3), ast::decimal_literal(4)),
+        ])
+    )
+}
+
+#[test]
+fn test_begin_rescue_ensure() {
+    let result = parse_expr("begin\n  1\nrescue e: Foo\n  2\nrescue\n  3\nensure\n  4\nend");
+    assert_eq!(
+        result.unwrap(),
+        ast::begin_expr(
+            vec![ast::decimal_literal(1)],
+            vec![
+                ast::AstRescueClause {
+                    var_name: Some("e".to_string()),
+                    typ: Some(ast::Typ {
+                        name: "Foo".to_string(),
+                        typ_args: vec![],
+                        is_nullable: false,
+                    }),
+                    exprs: vec![ast::decimal_literal(2)],
+                },
+                ast::AstRescueClause {
+                    var_name: None,
+                    typ: None,
+                    exprs: vec![ast::decimal_literal(3)],
+                },
+            ],
+            Some(vec![ast::decimal_literal(4)]),
+        )
+    )
+}
+
+#[test]
+fn test_raise() {
+    let result = parse_expr("raise Foo.new(\"x\")");
+    assert_eq!(
+        result.unwrap(),
+        ast::raise_expr(ast::method_call(
+            Some(ast::const_ref(vec!["Foo".to_string()])),
+            "new",
+            vec![ast::string_literal("x".to_string())],
+            true,
+            false
+        ))
+    )
+}
+
+#[test]
+fn test_return() {
+    let result = parse_expr("return 1");
+    assert_eq!(
+        result.unwrap(),
+        ast::return_expr(Some(ast::decimal_literal(1)))
+    );
+    let result = parse_expr("return");
+    assert_eq!(result.unwrap(), ast::return_expr(None))
+}
+
+#[test]
+fn test_if_modifier() {
+    let result = parse_expr("return 0 if n < 1");
+    assert_eq!(
+        result.unwrap(),
+        ast::if_expr(
+            ast::method_call(
+                Some(ast::bare_name("n")),
+                "<",
+                vec![ast::decimal_literal(1)],
+                false,
+                false
+            ),
+            vec![ast::return_expr(Some(ast::decimal_literal(0)))],
+            None
+        )
+    )
+}
+
+#[test]
+fn test_unless_modifier() {
+    let result = parse_expr("foo unless bar");
+    assert_eq!(
+        result.unwrap(),
+        ast::if_expr(
+            ast::logical_not(ast::bare_name("bar")),
+            vec![ast::bare_name("foo")],
+            None
+        )
+    )
+}
+
+#[test]
+fn test_case() {
+    let result = parse_expr("case x\nwhen 1, 2 then a\nwhen 3\nb\nelse c\nend");
+    assert_eq!(
+        result.unwrap(),
+        ast::case_expr(
+            ast::bare_name("x"),
+            vec![
+                ast::AstWhenClause {
+                    exprs: vec![ast::decimal_literal(1), ast::decimal_literal(2)],
+                    body_exprs: vec![ast::bare_name("a")],
+                },
+                ast::AstWhenClause {
+                    exprs: vec![ast::decimal_literal(3)],
+                    body_exprs: vec![ast::bare_name("b")],
+                },
+            ],
+            Some(vec![ast::bare_name("c")])
+        )
+    )
+}
+
+#[test]
+fn test_match() {
+    let result = parse_expr("match x\nwhen Foo(a, _) then a\nwhen 1 then b\nend");
+    assert_eq!(
+        result.unwrap(),
+        ast::match_expr(
+            ast::bare_name("x"),
+            vec![
+                ast::AstMatchClause {
+                    pattern: ast::AstPattern::ExtractorPattern {
+                        names: vec!["Foo".to_string()],
+                        params: vec![
+                            ast::AstPattern::Binding("a".to_string()),
+                            ast::AstPattern::Wildcard
+                        ],
+                    },
+                    body_exprs: vec![ast::bare_name("a")],
+                },
+                ast::AstMatchClause {
+                    pattern: ast::AstPattern::Literal(Box::new(ast::decimal_literal(1))),
+                    body_exprs: vec![ast::bare_name("b")],
+                },
+            ]
+        )
+    )
+}
+
+#[test]
+fn test_match_typed_binding() {
+    let result = parse_expr("match x\nwhen s: Foo::Bar then s\nwhen _: Baz then 1\nend");
+    assert_eq!(
+        result.unwrap(),
+        ast::match_expr(
+            ast::bare_name("x"),
+            vec![
+                ast::AstMatchClause {
+                    pattern: ast::AstPattern::TypedBinding {
+                        name: "s".to_string(),
+                        names: vec!["Foo".to_string(), "Bar".to_string()],
+                    },
+                    body_exprs: vec![ast::bare_name("s")],
+                },
+                ast::AstMatchClause {
+                    pattern: ast::AstPattern::ExtractorPattern {
+                        names: vec!["Baz".to_string()],
+                        params: vec![],
+                    },
+                    body_exprs: vec![ast::decimal_literal(1)],
+                },
+            ]
+        )
+    )
+}
+
+#[test]
+fn test_nil() {
+    let result = parse_expr("nil");
+    assert_eq!(
+        result.unwrap(),
+        ast::pseudo_variable(shiika::parser::Token::KwNil)
+    )
+}
+
+#[test]
+fn test_safe_method_call() {
+    let result = parse_expr("x&.foo(1)");
+    assert_eq!(
+        result.unwrap(),
+        ast::safe_method_call(ast::method_call(
+            Some(ast::bare_name("x")),
+            "foo",
+            vec![ast::decimal_literal(1)],
+            true,
+            false
+        ))
+    )
+}
+
+#[test]
+fn test_specialize_expr() {
+    let result = parse_expr("Array<Int>.new(0)");
+    let int_typ = ast::Typ {
+        name: "Int".to_string(),
+        typ_args: vec![],
+        is_nullable: false,
+    };
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::specialize_expr(
+                vec!["Array".to_string()],
+                vec![int_typ]
+            )),
+            "new",
+            vec![ast::decimal_literal(0)],
+            true,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_less_than_after_const() {
+    let result = parse_expr("A<b");
+    assert_eq!(
+        result.unwrap(),
+        ast::bin_op_expr(
+            ast::const_ref(vec!["A".to_string()]),
+            "<",
+            ast::bare_name("b")
+        )
+    )
+}
+
+#[test]
+fn test_string_escape() {
+    let result = parse_expr(r#""a\tb\n\"\\\u{3042}""#);
+    assert_eq!(
+        result.unwrap(),
+        ast::string_literal("a\tb\n\"\\\u{3042}".to_string())
+    )
+}
+
+#[test]
+fn test_string_interpolation() {
+    let result = parse_expr(r#""x = #{x+1}!""#);
+    assert_eq!(
+        result.unwrap(),
+        ast::string_interpolation(vec![
+            ast::string_literal("x = ".to_string()),
+            ast::method_call(
+                Some(ast::bare_name("x")),
+                "+",
+                vec![ast::decimal_literal(1)],
+                false,
+                false
+            ),
+            ast::string_literal("!".to_string()),
+        ])
+    )
+}
+
+#[test]
+fn test_string_interpolation_with_nested_string() {
+    let result = parse_expr(r##""#{"}"}""##);
+    assert_eq!(
+        result.unwrap(),
+        ast::string_interpolation(vec![ast::string_literal("}".to_string())])
+    )
+}
+
+#[test]
+fn test_malformed_string_literals() {
+    for src in &[
+        r#"x = "\u{zz}""#,
+        r#"x = "\u{3042""#,
+        r##"x = "#{1"##,
+        r#"x = "abc"#,
+    ] {
+        let err = Parser::parse(src).unwrap_err();
+        match err.details {
+            shiika::error::ErrorDetails::ParseError { .. } => (),
+            _ => panic!("{}: unexpected error: {:?}", src, err),
+        }
+    }
+}
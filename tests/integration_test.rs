@@ -10,7 +10,24 @@ fn test_compile_and_run() -> Result<(), Box<dyn std::error::Error>> {
             .to_str()
             .ok_or(plain_runner_error("Filename not utf8"))?;
         if path.ends_with(".sk") {
-            run_sk_test(path)?;
+            run_sk_test(path, false)?;
+            run_sk_test(path, true)?;
+        }
+    }
+    Ok(())
+}
+
+/// Tests which need the overflow checks (`--checked`)
+#[test]
+fn test_checked_arithmetic() -> Result<(), Box<dyn std::error::Error>> {
+    let paths = fs::read_dir("tests/sk_checked/")?;
+    for item in paths {
+        let pathbuf = item?.path();
+        let path = pathbuf
+            .to_str()
+            .ok_or(plain_runner_error("Filename not utf8"))?;
+        if path.ends_with(".sk") {
+            run_sk_test(path, true)?;
         }
     }
     Ok(())
@@ -18,12 +35,72 @@ fn test_compile_and_run() -> Result<(), Box<dyn std::error::Error>> {
 
 /// Execute tests/sk/x.sk
 /// Fail if it prints something
-fn run_sk_test(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    dbg!(&path);
-    shiika::runner::compile(path)?;
+fn run_sk_test(path: &str, checked: bool) -> Result<(), Box<dyn std::error::Error>> {
+    dbg!(&path, checked);
+    if checked {
+        shiika::runner::compile_checked(path)?;
+    } else {
+        shiika::runner::compile(path)?;
+    }
     let (stdout, stderr) = shiika::runner::run_and_capture(path)?;
     assert_eq!(stderr, "");
     assert_eq!(stdout, "ok\n");
     shiika::runner::cleanup(path)?;
     Ok(())
 }
+
+/// An uncaught exception is reported to stderr and makes the exit
+/// status non-zero
+#[test]
+fn test_uncaught_exception() -> Result<(), Box<dyn std::error::Error>> {
+    let path = "tests/sk_uncaught/raise.sk";
+    shiika::runner::compile(path)?;
+    let (stdout, stderr, success) = shiika::runner::run_and_capture_status(path)?;
+    assert_eq!(stdout, "before\n");
+    assert_eq!(stderr, "ArgumentError: oops\n");
+    assert!(!success);
+    shiika::runner::cleanup(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_compile_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let paths = fs::read_dir("tests/sk_error/")?;
+    for item in paths {
+        let pathbuf = item?.path();
+        let path = pathbuf
+            .to_str()
+            .ok_or(plain_runner_error("Filename not utf8"))?;
+        if path.ends_with(".sk") {
+            check_compile_error(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compile tests/sk_error/x.sk
+/// Fail unless it is rejected with the message written in the first line
+/// (eg. `# error: cannot instantiate`)
+fn check_compile_error(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    dbg!(&path);
+    let src = fs::read_to_string(path)?;
+    let first_line = src.lines().next().unwrap_or("");
+    let prefix = "# error: ";
+    assert!(
+        first_line.starts_with(prefix),
+        "{}: the first line must be `{}...'",
+        path,
+        prefix
+    );
+    let expected = &first_line[prefix.len()..];
+    match shiika::runner::compile(path) {
+        Ok(_) => panic!("{}: compiled without error", path),
+        Err(e) => assert!(
+            e.to_string().contains(expected),
+            "{}: unexpected error: {}",
+            path,
+            e
+        ),
+    }
+    Ok(())
+}